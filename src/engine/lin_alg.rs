@@ -13,6 +13,7 @@ pub trait Convert<U> {
 ///     - convert between types
 
 #[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
 pub struct Vector2<T: Num> {
     pub x: T,
     pub y: T,
@@ -91,6 +92,7 @@ impl<T: Num + Copy> std::ops::Mul<T> for Vector2<T> {
 ///     - convert between types
 
 #[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
 pub struct Vector3<T: Num> {
     pub x: T,
     pub y: T,
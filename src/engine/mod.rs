@@ -1,8 +1,9 @@
 pub mod lin_alg;
 
+/// Byte offset of `$field` in `$type`.
 #[macro_export]
 macro_rules! offset_of {
-    ($type:ty, $field:ident) => {{
-        unsafe { &(*(0 as usize as *const $type)).$field as *const _ as usize }
-    }};
+    ($type:ty, $field:ident) => {
+        ::std::mem::offset_of!($type, $field)
+    };
 }
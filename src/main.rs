@@ -1,14 +1,21 @@
 use std::time::Instant;
 
-use renderer::Renderer;
+use ash::vk;
+use vulkan::{
+    engine::lin_alg::{Vector2, Vector3},
+    msg,
+    renderer::{
+        config::{DepthAttachment, RendererConfig},
+        error::RendererError,
+        resources::vertex::Vertex,
+        Renderer,
+    },
+};
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::ControlFlow,
 };
 
-pub mod engine;
-mod renderer;
-
 fn main() {
     let mut loggers: Vec<Box<dyn simplelog::SharedLogger>> = vec![simplelog::TermLogger::new(
         simplelog::LevelFilter::Info,
@@ -27,6 +34,11 @@ fn main() {
 
     simplelog::CombinedLogger::init(loggers).unwrap();
 
+    if std::env::args().any(|arg| arg == "--headless") {
+        run_headless();
+        return;
+    }
+
     let event_loop = winit::event_loop::EventLoop::new();
     let window = winit::window::WindowBuilder::new()
         .with_title("HAHA")
@@ -34,7 +46,7 @@ fn main() {
         .build(&event_loop)
         .unwrap();
 
-    let mut renderer = match Renderer::new(&window) {
+    let mut renderer = match Renderer::new(&window, config_from_args()) {
         Ok(base) => base,
         Err(err) => {
             msg!(error, err);
@@ -42,6 +54,10 @@ fn main() {
         }
     };
 
+    if let Err(err) = set_up_scene(&mut renderer) {
+        msg!(error, err);
+    }
+
     let mut start_time = Instant::now();
     let mut visible = true;
 
@@ -68,14 +84,38 @@ fn main() {
                 }
             }
 
-            if let Err(msg) = renderer.draw(&delta_time) {
-                msg!(error, msg);
-                *control_flow = ControlFlow::Exit;
-                return;
+            match renderer.draw(&delta_time) {
+                Ok(()) => {}
+                Err(err) if err.is_out_of_date() => renderer.rebuild_swapchain = true,
+                Err(err) => {
+                    msg!(error, err);
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            }
+
+            if let Some(frame) = renderer.take_capture() {
+                if let Err(err) = frame.save_png("screenshot.png") {
+                    msg!(error, err);
+                }
             }
             start_time = Instant::now();
         }
 
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => renderer.request_capture(),
+
         Event::WindowEvent {
             event: WindowEvent::Resized(physical_size),
             ..
@@ -94,3 +134,121 @@ fn main() {
         _ => {}
     });
 }
+
+/// The default config, with `--no-depth` or `--depth-stencil` changing the depth attachment.
+fn config_from_args() -> RendererConfig {
+    let mut config = RendererConfig::default();
+
+    for arg in std::env::args() {
+        match arg.as_str() {
+            "--no-depth" => config = config.depth_attachment(DepthAttachment::None),
+            "--depth-stencil" => config = config.depth_attachment(DepthAttachment::DepthStencil),
+            _ => {}
+        }
+    }
+
+    config
+}
+
+/// Renders a fixed number of frames without opening a window, for machines without a display.
+fn run_headless() {
+    const FRAME_COUNT: usize = 10;
+
+    let extent = vk::Extent2D {
+        width: 800,
+        height: 600,
+    };
+
+    let mut renderer = match Renderer::new_headless(extent, config_from_args()) {
+        Ok(renderer) => renderer,
+        Err(err) => {
+            msg!(error, err);
+            panic!("{}", err);
+        }
+    };
+
+    if let Err(err) = set_up_scene(&mut renderer) {
+        msg!(error, err);
+    }
+
+    let mut start_time = Instant::now();
+    for frame in 0..FRAME_COUNT {
+        if frame == FRAME_COUNT - 1 {
+            renderer.request_capture();
+        }
+
+        if let Err(err) = renderer.draw(&start_time.elapsed()) {
+            msg!(error, err);
+            panic!("{}", err);
+        }
+        start_time = Instant::now();
+    }
+
+    msg!(info, format!("rendered {} headless frames", FRAME_COUNT));
+
+    if let Some(frame) = renderer.take_capture() {
+        if let Err(err) = frame.save_ppm("headless.ppm") {
+            msg!(error, err);
+        }
+    }
+}
+
+/// A quad in the middle of the screen and a small copy of it in the top left corner, both
+/// sampling a gradient texture.
+fn set_up_scene(renderer: &mut Renderer) -> Result<(), RendererError> {
+    add_quad(renderer)?;
+
+    let inset = add_quad(renderer)?;
+    renderer.data.meshes[inset].set_region(vk::Rect2D {
+        offset: vk::Offset2D { x: 16, y: 16 },
+        extent: vk::Extent2D {
+            width: 200,
+            height: 150,
+        },
+    });
+
+    set_gradient_texture(renderer)
+}
+
+/// Uploads a colored, textured quad in the middle of the screen.
+fn add_quad(renderer: &mut Renderer) -> Result<usize, RendererError> {
+    let vertices = [
+        Vertex::new(
+            Vector3::new(-0.5, -0.5, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector2::new(0.0, 0.0),
+        ),
+        Vertex::new(
+            Vector3::new(0.5, -0.5, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector2::new(1.0, 0.0),
+        ),
+        Vertex::new(
+            Vector3::new(0.5, 0.5, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector2::new(1.0, 1.0),
+        ),
+        Vertex::new(
+            Vector3::new(-0.5, 0.5, 0.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector2::new(0.0, 1.0),
+        ),
+    ];
+    let indices = [0, 1, 2, 2, 3, 0];
+
+    renderer.add_mesh(&vertices, &indices)
+}
+
+/// Replaces the default checkerboard with a red to green gradient.
+fn set_gradient_texture(renderer: &mut Renderer) -> Result<(), RendererError> {
+    const SIZE: u32 = 64;
+
+    let pixels = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % SIZE, i / SIZE);
+            [(x * 255 / SIZE) as u8, (y * 255 / SIZE) as u8, 128, 255]
+        })
+        .collect::<Vec<_>>();
+
+    renderer.set_texture(SIZE, SIZE, &pixels)
+}
@@ -0,0 +1,620 @@
+use std::ffi::c_void;
+
+use ash::vk;
+
+use crate::renderer::error::RendererError;
+
+/// Size of the blocks allocations are carved out of, smaller heaps use an eighth of their size.
+pub const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Where an allocation should live, which decides the memory type it is placed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Device local memory, not accessible from the host.
+    GpuOnly,
+    /// Host visible memory written by the cpu and read by the gpu, like uniform or staging
+    /// buffers.
+    CpuToGpu,
+    /// Host visible memory written by the gpu and read back by the cpu, cached if possible.
+    GpuToCpu,
+}
+
+impl MemoryLocation {
+    /// The (required, preferred) property flags of the location.
+    fn property_flags(self) -> (vk::MemoryPropertyFlags, vk::MemoryPropertyFlags) {
+        match self {
+            MemoryLocation::GpuOnly => (
+                vk::MemoryPropertyFlags::empty(),
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ),
+            MemoryLocation::CpuToGpu => (
+                vk::MemoryPropertyFlags::HOST_VISIBLE,
+                vk::MemoryPropertyFlags::HOST_COHERENT,
+            ),
+            MemoryLocation::GpuToCpu => (
+                vk::MemoryPropertyFlags::HOST_VISIBLE,
+                vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_CACHED,
+            ),
+        }
+    }
+}
+
+/// Describes the memory an allocation needs.
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationDesc<'a> {
+    /// Shown in usage and leak reports.
+    pub name: &'a str,
+    pub requirements: vk::MemoryRequirements,
+    pub location: MemoryLocation,
+    /// Whether the resource is a buffer or linearly tiled image, as opposed to an optimally
+    /// tiled image. Neighbours of different kinds are kept `bufferImageGranularity` apart.
+    pub linear: bool,
+}
+
+/// A range of device memory handed out by the [`Allocator`].
+///
+/// Has to be returned with [`Allocator::free`], anything still allocated when the allocator
+/// is destroyed is reported as a leak.
+#[derive(Debug)]
+pub struct Allocation {
+    id: u64,
+    memory_type_index: u32,
+    block_index: usize,
+    pub memory: vk::DeviceMemory,
+    pub offset: u64,
+    mapped_ptr: *mut c_void,
+    /// `nonCoherentAtomSize` for non-coherent memory, 0 if the memory is coherent.
+    atom_size: u64,
+    block_size: u64,
+}
+
+// the mapped range belongs to the allocation alone and is only accessed through its owner,
+// so moving it to another thread is fine. sharing it isn't, writes through `&self` would race.
+unsafe impl Send for Allocation {}
+
+impl Allocation {
+    /// Pointer to the start of the allocation if it is host visible, blocks stay mapped for
+    /// their whole lifetime.
+    #[inline]
+    pub fn mapped_ptr(&self) -> Option<*mut c_void> {
+        (!self.mapped_ptr.is_null()).then_some(self.mapped_ptr)
+    }
+
+    #[inline]
+    pub fn is_coherent(&self) -> bool {
+        self.atom_size == 0
+    }
+
+    /// Makes host writes to `size` bytes at `offset` visible to the device, a no-op for
+    /// coherent memory.
+    pub fn flush(&self, device: &ash::Device, offset: u64, size: u64) -> Result<(), RendererError> {
+        if let Some(range) = self.atom_range(offset, size) {
+            unsafe {
+                device
+                    .flush_mapped_memory_ranges(&[range])
+                    .map_err(RendererError::memory("failed to flush mapped memory"))?
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Makes device writes to `size` bytes at `offset` visible to the host, a no-op for
+    /// coherent memory.
+    pub fn invalidate(
+        &self,
+        device: &ash::Device,
+        offset: u64,
+        size: u64,
+    ) -> Result<(), RendererError> {
+        if let Some(range) = self.atom_range(offset, size) {
+            unsafe {
+                device
+                    .invalidate_mapped_memory_ranges(&[range])
+                    .map_err(RendererError::memory("failed to invalidate mapped memory"))?
+            };
+        }
+
+        Ok(())
+    }
+
+    /// The range covering `size` bytes at `offset`, widened to whole atoms as flushes and
+    /// invalidations require. `None` for coherent memory.
+    fn atom_range(&self, offset: u64, size: u64) -> Option<vk::MappedMemoryRange> {
+        if self.is_coherent() {
+            return None;
+        }
+
+        let start = align_down(self.offset + offset, self.atom_size);
+        let end = align_up(self.offset + offset + size, self.atom_size).min(self.block_size);
+
+        Some(
+            vk::MappedMemoryRange::builder()
+                .memory(self.memory)
+                .offset(start)
+                .size(end - start)
+                .build(),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct SubAllocation {
+    id: u64,
+    offset: u64,
+    size: u64,
+    linear: bool,
+    name: String,
+}
+
+#[derive(Debug)]
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    size: u64,
+    mapped_ptr: *mut c_void,
+    /// Holds a single allocation too large to share a block.
+    dedicated: bool,
+    /// Sorted by offset.
+    allocations: Vec<SubAllocation>,
+}
+
+// the block only keeps the mapping to free it and to derive the pointers of its
+// allocations, it never reads or writes through it
+unsafe impl Send for MemoryBlock {}
+
+impl MemoryBlock {
+    #[inline]
+    fn used(&self) -> u64 {
+        self.allocations
+            .iter()
+            .map(|allocation| allocation.size)
+            .sum()
+    }
+
+    /// First fit search for a free range, returning the offset and the insertion index.
+    fn find_free_range(
+        &self,
+        size: u64,
+        alignment: u64,
+        linear: bool,
+        granularity: u64,
+    ) -> Option<(u64, usize)> {
+        let mut start = 0;
+
+        for index in 0..=self.allocations.len() {
+            let previous = index.checked_sub(1).map(|i| &self.allocations[i]);
+            let next = self.allocations.get(index);
+
+            let mut offset = align_up(start, alignment);
+            if let Some(previous) = previous {
+                if previous.linear != linear
+                    && on_same_page(previous.offset + previous.size - 1, offset, granularity)
+                {
+                    offset = align_up(offset, granularity);
+                }
+            }
+
+            let end = offset + size;
+            let fits = match next {
+                Some(next) => {
+                    end <= next.offset
+                        && !(next.linear != linear
+                            && on_same_page(end - 1, next.offset, granularity))
+                }
+                None => end <= self.size,
+            };
+
+            if fits {
+                return Some((offset, index));
+            }
+
+            if let Some(next) = next {
+                start = next.offset + next.size;
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug)]
+struct MemoryTypePool {
+    property_flags: vk::MemoryPropertyFlags,
+    heap_index: usize,
+    /// `None` for freed blocks, so the indices of the other blocks stay valid.
+    blocks: Vec<Option<MemoryBlock>>,
+}
+
+/// Memory usage of one memory type.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryTypeUsage {
+    pub memory_type_index: u32,
+    pub blocks: usize,
+    pub allocations: usize,
+    /// Bytes allocated from the driver.
+    pub reserved: u64,
+    /// Bytes handed out to allocations.
+    pub used: u64,
+}
+
+/// Destroys the logical device when dropped.
+struct DeviceOwner(ash::Device);
+
+impl Drop for DeviceOwner {
+    fn drop(&mut self) {
+        unsafe { self.0.destroy_device(None) };
+    }
+}
+
+/// Sub-allocates buffers and images from large `vkDeviceMemory` blocks, one pool of blocks
+/// per memory type.
+///
+/// Owns the device: it is destroyed when the allocator is dropped, after the blocks were
+/// freed. Buffers and images hold on to the allocator, so the device outlives all of them.
+pub struct Allocator {
+    device: ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: u64,
+    non_coherent_atom_size: u64,
+    max_allocation_count: u32,
+    allocation_count: u32,
+    pools: Vec<MemoryTypePool>,
+    next_id: u64,
+    /// Set by [`Allocator::destroy`], frees after it are no-ops.
+    destroyed: bool,
+    /// Declared last so it is dropped after everything else.
+    _device_owner: DeviceOwner,
+}
+
+impl Allocator {
+    /// Creates the allocator, which takes over destroying `device`.
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        limits: &vk::PhysicalDeviceLimits,
+    ) -> Self {
+        let pools = memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+            .iter()
+            .map(|memory_type| MemoryTypePool {
+                property_flags: memory_type.property_flags,
+                heap_index: memory_type.heap_index as usize,
+                blocks: Vec::new(),
+            })
+            .collect();
+
+        Self {
+            device: device.clone(),
+            memory_properties,
+            buffer_image_granularity: limits.buffer_image_granularity.max(1),
+            non_coherent_atom_size: limits.non_coherent_atom_size.max(1),
+            max_allocation_count: limits.max_memory_allocation_count,
+            allocation_count: 0,
+            pools,
+            next_id: 0,
+            destroyed: false,
+            _device_owner: DeviceOwner(device.clone()),
+        }
+    }
+
+    pub fn allocate(&mut self, desc: &AllocationDesc) -> Result<Allocation, RendererError> {
+        let (required, preferred) = desc.location.property_flags();
+        let memory_type_bits = desc.requirements.memory_type_bits;
+
+        let memory_type_index = self
+            .find_memory_type(memory_type_bits, required | preferred)
+            .or_else(|| self.find_memory_type(memory_type_bits, required))
+            .ok_or_else(|| {
+                RendererError::Unsupported(format!(
+                    "couldn't find a memory type with {:?} for {}",
+                    required, desc.name
+                ))
+            })?;
+
+        let property_flags = self.pools[memory_type_index as usize].property_flags;
+
+        // non-coherent allocations cover whole atoms, so flushing or invalidating one never
+        // touches another
+        let atom_size = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+            && !property_flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+        {
+            self.non_coherent_atom_size
+        } else {
+            0
+        };
+
+        let size = align_up(desc.requirements.size, atom_size.max(1));
+        let alignment = desc.requirements.alignment.max(atom_size).max(1);
+        let granularity = self.buffer_image_granularity;
+        let block_size = self.block_size(memory_type_index);
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let sub_allocation = SubAllocation {
+            id,
+            offset: 0,
+            size,
+            linear: desc.linear,
+            name: String::from(desc.name),
+        };
+
+        let pool = &mut self.pools[memory_type_index as usize];
+
+        if size <= block_size / 2 {
+            let free_range = pool.blocks.iter().enumerate().find_map(|(index, block)| {
+                let block = block.as_ref().filter(|block| !block.dedicated)?;
+                let (offset, position) =
+                    block.find_free_range(size, alignment, desc.linear, granularity)?;
+                Some((index, offset, position))
+            });
+
+            if let Some((block_index, offset, position)) = free_range {
+                let block = pool.blocks[block_index].as_mut().unwrap();
+                block.allocations.insert(
+                    position,
+                    SubAllocation {
+                        offset,
+                        ..sub_allocation
+                    },
+                );
+
+                return Ok(Allocation {
+                    id,
+                    memory_type_index,
+                    block_index,
+                    memory: block.memory,
+                    offset,
+                    mapped_ptr: offset_ptr(block.mapped_ptr, offset),
+                    atom_size,
+                    block_size: block.size,
+                });
+            }
+        }
+
+        // no room in the existing blocks, large allocations get a block of their own
+        let dedicated = size > block_size / 2;
+        let block = self.allocate_block(
+            memory_type_index,
+            if dedicated { size } else { block_size },
+            dedicated,
+            sub_allocation,
+        )?;
+
+        let pool = &mut self.pools[memory_type_index as usize];
+        let allocation = Allocation {
+            id,
+            memory_type_index,
+            block_index: 0,
+            memory: block.memory,
+            offset: 0,
+            mapped_ptr: block.mapped_ptr,
+            atom_size,
+            block_size: block.size,
+        };
+
+        let block_index = match pool.blocks.iter().position(|block| block.is_none()) {
+            Some(index) => {
+                pool.blocks[index] = Some(block);
+                index
+            }
+            None => {
+                pool.blocks.push(Some(block));
+                pool.blocks.len() - 1
+            }
+        };
+
+        Ok(Allocation {
+            block_index,
+            ..allocation
+        })
+    }
+
+    pub fn free(&mut self, allocation: Allocation) {
+        let block = self
+            .pools
+            .get_mut(allocation.memory_type_index as usize)
+            .and_then(|pool| pool.blocks.get_mut(allocation.block_index))
+            .and_then(Option::as_mut);
+        let Some(block) = block else {
+            // `destroy` already freed the block and reported the allocation as leaked
+            if !self.destroyed {
+                log::error!("freed allocation {} of a freed block", allocation.id);
+            }
+            return;
+        };
+
+        let Some(position) = block
+            .allocations
+            .iter()
+            .position(|sub_allocation| sub_allocation.id == allocation.id)
+        else {
+            log::error!("allocation {} was freed twice", allocation.id);
+            return;
+        };
+        block.allocations.remove(position);
+
+        if !block.allocations.is_empty() {
+            return;
+        }
+
+        // keep one shared block per memory type around to avoid reallocating it
+        let dedicated = block.dedicated;
+        let pool = &mut self.pools[allocation.memory_type_index as usize];
+        let keep = !dedicated
+            && pool
+                .blocks
+                .iter()
+                .flatten()
+                .filter(|block| !block.dedicated)
+                .count()
+                == 1;
+
+        if !keep {
+            let block = pool.blocks[allocation.block_index].take().unwrap();
+            unsafe { self.device.free_memory(block.memory, None) };
+            self.allocation_count -= 1;
+        }
+    }
+
+    /// Usage of every memory type that has blocks allocated.
+    pub fn usage(&self) -> Vec<MemoryTypeUsage> {
+        self.pools
+            .iter()
+            .enumerate()
+            .filter(|(_, pool)| pool.blocks.iter().any(Option::is_some))
+            .map(|(index, pool)| {
+                let blocks = pool.blocks.iter().flatten();
+                MemoryTypeUsage {
+                    memory_type_index: index as u32,
+                    blocks: blocks.clone().count(),
+                    allocations: blocks.clone().map(|block| block.allocations.len()).sum(),
+                    reserved: blocks.clone().map(|block| block.size).sum(),
+                    used: blocks.map(MemoryBlock::used).sum(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn log_usage(&self) {
+        for usage in self.usage() {
+            let pool = &self.pools[usage.memory_type_index as usize];
+            log::info!(
+                "memory type {} ({:?}, heap {}): {} allocations, {} KiB used of {} KiB in {} blocks",
+                usage.memory_type_index,
+                pool.property_flags,
+                pool.heap_index,
+                usage.allocations,
+                usage.used >> 10,
+                usage.reserved >> 10,
+                usage.blocks,
+            );
+        }
+    }
+
+    /// Frees every block, reporting allocations that were never freed. Called when the
+    /// allocator is dropped, allocations freed after it are ignored.
+    pub fn destroy(&mut self) {
+        self.destroyed = true;
+        self.log_usage();
+
+        for (index, pool) in self.pools.iter_mut().enumerate() {
+            for block in pool.blocks.drain(..).flatten() {
+                for leak in &block.allocations {
+                    log::warn!(
+                        "leaked allocation \"{}\" of {} bytes in memory type {}",
+                        leak.name,
+                        leak.size,
+                        index
+                    );
+                }
+                unsafe { self.device.free_memory(block.memory, None) };
+            }
+        }
+
+        self.allocation_count = 0;
+    }
+
+    fn find_memory_type(
+        &self,
+        memory_type_bits: u32,
+        flags: vk::MemoryPropertyFlags,
+    ) -> Option<u32> {
+        self.pools
+            .iter()
+            .enumerate()
+            .find(|(index, pool)| {
+                memory_type_bits & (1 << index) != 0 && pool.property_flags.contains(flags)
+            })
+            .map(|(index, _)| index as u32)
+    }
+
+    fn block_size(&self, memory_type_index: u32) -> u64 {
+        let heap_index = self.pools[memory_type_index as usize].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index].size;
+
+        DEFAULT_BLOCK_SIZE.min(heap_size / 8)
+    }
+
+    fn allocate_block(
+        &mut self,
+        memory_type_index: u32,
+        size: u64,
+        dedicated: bool,
+        first_allocation: SubAllocation,
+    ) -> Result<MemoryBlock, RendererError> {
+        if self.allocation_count >= self.max_allocation_count {
+            return Err(RendererError::Unsupported(format!(
+                "reached maxMemoryAllocationCount ({}) allocating {}",
+                self.max_allocation_count, first_allocation.name
+            )));
+        }
+
+        let alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+
+        let memory = unsafe {
+            self.device
+                .allocate_memory(&alloc_info, None)
+                .map_err(RendererError::memory("failed to allocate memory block"))?
+        };
+
+        let property_flags = self.pools[memory_type_index as usize].property_flags;
+        let mapped_ptr = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            unsafe {
+                self.device
+                    .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                    .map_err(|result| {
+                        self.device.free_memory(memory, None);
+                        RendererError::Memory {
+                            context: String::from("failed to map memory block"),
+                            result,
+                        }
+                    })?
+            }
+        } else {
+            std::ptr::null_mut()
+        };
+
+        self.allocation_count += 1;
+
+        Ok(MemoryBlock {
+            memory,
+            size,
+            mapped_ptr,
+            dedicated,
+            allocations: vec![first_allocation],
+        })
+    }
+}
+
+impl Drop for Allocator {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+#[inline]
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+#[inline]
+fn align_down(value: u64, alignment: u64) -> u64 {
+    value / alignment * alignment
+}
+
+/// Whether two byte offsets fall on the same `granularity` sized page.
+#[inline]
+fn on_same_page(a: u64, b: u64, granularity: u64) -> bool {
+    a / granularity == b / granularity
+}
+
+#[inline]
+fn offset_ptr(ptr: *mut c_void, offset: u64) -> *mut c_void {
+    if ptr.is_null() {
+        ptr
+    } else {
+        unsafe { ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
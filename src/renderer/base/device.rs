@@ -0,0 +1,276 @@
+use std::ffi::CStr;
+
+use ash::extensions::khr;
+use ash::vk;
+
+use crate::renderer::{
+    config::{DeviceSelection, RendererConfig},
+    error::RendererError,
+};
+
+use super::setup::{check_device_suitability, get_queue_family};
+
+/// A physical device considered during device selection.
+#[derive(Debug, Clone)]
+pub struct DeviceCandidate {
+    /// Position in the list returned by `vkEnumeratePhysicalDevices`.
+    pub index: usize,
+    pub physical_device: vk::PhysicalDevice,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub api_version: u32,
+    /// Total size of the device local memory heaps in bytes.
+    pub device_local_memory: u64,
+    pub score: DeviceScore,
+    /// Why the device can't be used, `None` if it is suitable.
+    pub rejection: Option<String>,
+}
+
+/// Ranks suitable devices, compared field by field: device type first (discrete >
+/// integrated > virtual > cpu), then device local memory, then supported API version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceScore {
+    pub type_rank: u32,
+    pub device_local_memory: u64,
+    pub api_version: u32,
+}
+
+impl DeviceCandidate {
+    #[inline]
+    pub fn is_suitable(&self) -> bool {
+        self.rejection.is_none()
+    }
+
+    fn matches(&self, selection: &DeviceSelection) -> bool {
+        match selection {
+            DeviceSelection::Best => true,
+            DeviceSelection::Index(index) => self.index == *index,
+            DeviceSelection::Name(name) => self.name.to_lowercase().contains(&name.to_lowercase()),
+            DeviceSelection::Id {
+                vendor_id,
+                device_id,
+            } => self.vendor_id == *vendor_id && device_id.is_none_or(|id| self.device_id == id),
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceCandidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({:?}, {:04x}:{:04x}, vulkan {}.{}, {} MiB)",
+            self.index,
+            self.name,
+            self.device_type,
+            self.vendor_id,
+            self.device_id,
+            vk::api_version_major(self.api_version),
+            vk::api_version_minor(self.api_version),
+            self.device_local_memory >> 20,
+        )?;
+
+        match &self.rejection {
+            Some(reason) => write!(f, ": rejected, {}", reason),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Lists every physical device with its score, checking each against the requirements of
+/// the renderer. When a surface is given, devices that can't present to it are rejected.
+pub fn enumerate_device_candidates(
+    instance: &ash::Instance,
+    required_device_extensions: &Vec<&CStr>,
+    surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+    config: &RendererConfig,
+) -> Result<Vec<DeviceCandidate>, RendererError> {
+    let devices = unsafe {
+        instance
+            .enumerate_physical_devices()
+            .map_err(RendererError::instance(
+                "failed to enumerate physical devices",
+            ))?
+    };
+
+    let mut candidates = Vec::with_capacity(devices.len());
+
+    for (index, physical_device) in devices.into_iter().enumerate() {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+        let device_local_memory = memory_properties.memory_heaps
+            [..memory_properties.memory_heap_count as usize]
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        let type_rank = match properties.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+            vk::PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        };
+
+        let rejection = check_device_suitability(
+            instance,
+            physical_device,
+            required_device_extensions,
+            &properties,
+            config,
+        )
+        .and_then(|_| get_queue_family(instance, physical_device, surface))
+        .err()
+        .map(|err| err.to_string());
+
+        candidates.push(DeviceCandidate {
+            index,
+            physical_device,
+            name: unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+                .to_string_lossy()
+                .into_owned(),
+            device_type: properties.device_type,
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            api_version: properties.api_version,
+            device_local_memory,
+            score: DeviceScore {
+                type_rank,
+                device_local_memory,
+                api_version: properties.api_version,
+            },
+            rejection,
+        });
+    }
+
+    Ok(candidates)
+}
+
+/// Picks the highest scoring suitable candidate matching `selection`.
+///
+/// An explicit selection that matches no suitable device is an error rather than silently
+/// falling back to another GPU.
+pub fn select_device<'a>(
+    candidates: &'a [DeviceCandidate],
+    selection: &DeviceSelection,
+) -> Result<&'a DeviceCandidate, RendererError> {
+    let matching = candidates
+        .iter()
+        .filter(|candidate| candidate.matches(selection))
+        .collect::<Vec<_>>();
+
+    if let Some(selected) = matching
+        .iter()
+        .filter(|candidate| candidate.is_suitable())
+        .max_by_key(|candidate| candidate.score)
+    {
+        return Ok(selected);
+    }
+
+    let listing = candidates
+        .iter()
+        .map(|candidate| format!("\n\t{}", candidate))
+        .collect::<String>();
+
+    Err(RendererError::Unsupported(
+        match (selection, matching.is_empty()) {
+            (DeviceSelection::Best, _) => format!("failed to find suitable device:{}", listing),
+            (_, true) => format!("no device matches {:?}:{}", selection, listing),
+            (_, false) => format!("no suitable device matches {:?}:{}", selection, listing),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(
+        index: usize,
+        name: &str,
+        type_rank: u32,
+        ids: (u32, u32),
+        rejection: Option<&str>,
+    ) -> DeviceCandidate {
+        DeviceCandidate {
+            index,
+            physical_device: vk::PhysicalDevice::null(),
+            name: String::from(name),
+            device_type: vk::PhysicalDeviceType::OTHER,
+            vendor_id: ids.0,
+            device_id: ids.1,
+            api_version: vk::API_VERSION_1_3,
+            device_local_memory: 0,
+            score: DeviceScore {
+                type_rank,
+                device_local_memory: 0,
+                api_version: vk::API_VERSION_1_3,
+            },
+            rejection: rejection.map(String::from),
+        }
+    }
+
+    fn candidates() -> Vec<DeviceCandidate> {
+        vec![
+            candidate(0, "Intel UHD", 3, (0x8086, 0x9bc4), None),
+            candidate(1, "NVIDIA GeForce RTX", 4, (0x10de, 0x2684), None),
+            candidate(2, "llvmpipe", 1, (0x10005, 0), Some("missing extension")),
+        ]
+    }
+
+    #[test]
+    fn best_picks_highest_score() {
+        let candidates = candidates();
+        let selected = select_device(&candidates, &DeviceSelection::Best).unwrap();
+        assert_eq!(selected.index, 1);
+    }
+
+    #[test]
+    fn selects_by_index_name_and_id() {
+        let candidates = candidates();
+
+        let selected = select_device(&candidates, &DeviceSelection::Index(0)).unwrap();
+        assert_eq!(selected.index, 0);
+
+        let name = DeviceSelection::Name(String::from("intel"));
+        assert_eq!(select_device(&candidates, &name).unwrap().index, 0);
+
+        let vendor = DeviceSelection::Id {
+            vendor_id: 0x10de,
+            device_id: None,
+        };
+        assert_eq!(select_device(&candidates, &vendor).unwrap().index, 1);
+
+        let device = DeviceSelection::Id {
+            vendor_id: 0x8086,
+            device_id: Some(0x9bc4),
+        };
+        assert_eq!(select_device(&candidates, &device).unwrap().index, 0);
+    }
+
+    #[test]
+    fn explicit_selection_does_not_fall_back() {
+        let candidates = candidates();
+
+        // matches only a rejected device
+        assert!(select_device(&candidates, &DeviceSelection::Index(2)).is_err());
+        assert!(select_device(&candidates, &DeviceSelection::Index(3)).is_err());
+
+        let id = DeviceSelection::Id {
+            vendor_id: 0x10de,
+            device_id: Some(0x1234),
+        };
+        assert!(select_device(&candidates, &id).is_err());
+    }
+
+    #[test]
+    fn no_suitable_device() {
+        let candidates = vec![candidate(0, "llvmpipe", 1, (0x10005, 0), Some("too old"))];
+        assert!(select_device(&candidates, &DeviceSelection::Best).is_err());
+        assert!(select_device(&[], &DeviceSelection::Best).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::ffi::CStr;
+
+use ash::extensions::khr;
+use ash::vk;
+
+use super::features::DeviceFeatures;
+
+/// Where a device gets `VK_KHR_dynamic_rendering` from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicRenderingSupport {
+    /// Core since Vulkan 1.3, enabled through the `vulkan13.dynamic_rendering` feature.
+    Core,
+    /// The extension on Vulkan 1.2 devices, enabled with its own feature struct.
+    Extension,
+}
+
+impl DynamicRenderingSupport {
+    /// How `physical_device` supports dynamic rendering, `api_version` being the lower of the
+    /// instance and device versions. `None` if it doesn't.
+    pub fn query(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        api_version: u32,
+    ) -> Option<Self> {
+        let supported = DeviceFeatures::query(instance, physical_device, api_version);
+        if supported.vulkan13.dynamic_rendering != vk::FALSE {
+            return Some(Self::Core);
+        }
+
+        // the extension depends on VK_KHR_depth_stencil_resolve and VK_KHR_create_renderpass2,
+        // which are core in 1.2
+        if vk::api_version_minor(api_version) < 2 && vk::api_version_major(api_version) == 1 {
+            return None;
+        }
+
+        let extensions = unsafe {
+            instance
+                .enumerate_device_extension_properties(physical_device)
+                .unwrap_or_default()
+        };
+        extensions
+            .iter()
+            .any(|extension| {
+                let name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+                name == khr::DynamicRendering::name()
+            })
+            .then_some(Self::Extension)
+    }
+}
+
+/// Begins and ends dynamic rendering through the core or the extension entry points.
+#[derive(Clone)]
+pub struct DynamicRendering {
+    device: ash::Device,
+    /// `None` when dynamic rendering is core.
+    extension: Option<khr::DynamicRendering>,
+}
+
+impl DynamicRendering {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        support: DynamicRenderingSupport,
+    ) -> Self {
+        Self {
+            device: device.clone(),
+            extension: match support {
+                DynamicRenderingSupport::Core => None,
+                DynamicRenderingSupport::Extension => {
+                    Some(khr::DynamicRendering::new(instance, device))
+                }
+            },
+        }
+    }
+
+    #[inline]
+    pub fn cmd_begin_rendering(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        rendering_info: &vk::RenderingInfo,
+    ) {
+        unsafe {
+            match &self.extension {
+                Some(extension) => extension.cmd_begin_rendering(command_buffer, rendering_info),
+                None => self
+                    .device
+                    .cmd_begin_rendering(command_buffer, rendering_info),
+            }
+        }
+    }
+
+    #[inline]
+    pub fn cmd_end_rendering(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            match &self.extension {
+                Some(extension) => extension.cmd_end_rendering(command_buffer),
+                None => self.device.cmd_end_rendering(command_buffer),
+            }
+        }
+    }
+}
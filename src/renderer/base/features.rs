@@ -0,0 +1,356 @@
+use ash::vk;
+
+use crate::renderer::error::RendererError;
+
+/// Name, getter and setter of a single feature flag.
+type Field<T> = (&'static str, fn(&T) -> vk::Bool32, fn(&mut T, vk::Bool32));
+
+macro_rules! feature_fields {
+    ($($name:ident),* $(,)?) => {
+        &[$((
+            stringify!($name),
+            |features| features.$name,
+            |features, value| features.$name = value,
+        )),*]
+    };
+}
+
+/// A set of device features, covering the core features and the Vulkan 1.1, 1.2 and 1.3
+/// feature structs that are chained through `VkPhysicalDeviceFeatures2`.
+///
+/// The `p_next` members are ignored, the chain is built when querying or enabling features.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeviceFeatures {
+    pub core: vk::PhysicalDeviceFeatures,
+    pub vulkan11: vk::PhysicalDeviceVulkan11Features,
+    pub vulkan12: vk::PhysicalDeviceVulkan12Features,
+    pub vulkan13: vk::PhysicalDeviceVulkan13Features,
+}
+
+// the only pointers are the p_next members, which are never dereferenced
+unsafe impl Send for DeviceFeatures {}
+unsafe impl Sync for DeviceFeatures {}
+
+impl DeviceFeatures {
+    /// Queries the features `physical_device` supports. Feature structs newer than
+    /// `api_version` (the lower of the instance and device versions) are left empty.
+    pub fn query(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        api_version: u32,
+    ) -> Self {
+        let mut supported = Self::default();
+        let version = api_minor(api_version);
+
+        if version < 1 {
+            supported.core = unsafe { instance.get_physical_device_features(physical_device) };
+            return supported;
+        }
+
+        let mut features2 = vk::PhysicalDeviceFeatures2::default();
+        if version >= 2 {
+            supported.vulkan11.p_next = std::ptr::null_mut();
+            supported.vulkan12.p_next = &mut supported.vulkan11 as *mut _ as *mut std::ffi::c_void;
+            features2.p_next = &mut supported.vulkan12 as *mut _ as *mut std::ffi::c_void;
+        }
+        if version >= 3 {
+            supported.vulkan13.p_next = features2.p_next;
+            features2.p_next = &mut supported.vulkan13 as *mut _ as *mut std::ffi::c_void;
+        }
+
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+        supported.core = features2.features;
+        supported.clear_chain();
+
+        supported
+    }
+
+    /// Names of the features set in `self` but not in `supported`.
+    pub fn missing_from(&self, supported: &DeviceFeatures) -> Vec<String> {
+        let mut missing = Vec::new();
+
+        self.for_each_pair(supported, |name, requested, available| {
+            if requested != vk::FALSE && available == vk::FALSE {
+                missing.push(String::from(name));
+            }
+        });
+
+        missing
+    }
+
+    /// The features set in both `self` and `other`.
+    pub fn intersection(&self, other: &DeviceFeatures) -> DeviceFeatures {
+        self.combine(other, |a, b| a != vk::FALSE && b != vk::FALSE)
+    }
+
+    /// The features set in either `self` or `other`.
+    pub fn union(&self, other: &DeviceFeatures) -> DeviceFeatures {
+        self.combine(other, |a, b| a != vk::FALSE || b != vk::FALSE)
+    }
+
+    /// Names of every feature that is set.
+    pub fn enabled_names(&self) -> Vec<String> {
+        self.missing_from(&DeviceFeatures::default())
+    }
+
+    /// Checks that the device supports `required`, failing with the list of missing features.
+    pub fn check_supported(
+        required: &DeviceFeatures,
+        supported: &DeviceFeatures,
+    ) -> Result<(), RendererError> {
+        let missing = required.missing_from(supported);
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(RendererError::Unsupported(format!(
+                "the device does not support required features: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+
+    /// Whether any feature of a struct that has to be chained through
+    /// `VkPhysicalDeviceFeatures2` is set.
+    pub fn needs_features2(&self) -> bool {
+        self.has_vulkan11() || self.has_vulkan12() || self.has_vulkan13()
+    }
+
+    pub(super) fn has_vulkan11(&self) -> bool {
+        VULKAN11_FIELDS
+            .iter()
+            .any(|(_, get, _)| get(&self.vulkan11) != vk::FALSE)
+    }
+
+    pub(super) fn has_vulkan12(&self) -> bool {
+        VULKAN12_FIELDS
+            .iter()
+            .any(|(_, get, _)| get(&self.vulkan12) != vk::FALSE)
+    }
+
+    pub(super) fn has_vulkan13(&self) -> bool {
+        VULKAN13_FIELDS
+            .iter()
+            .any(|(_, get, _)| get(&self.vulkan13) != vk::FALSE)
+    }
+
+    /// Resets the `p_next` members, so copies never point into another value.
+    pub(super) fn clear_chain(&mut self) {
+        self.vulkan11.p_next = std::ptr::null_mut();
+        self.vulkan12.p_next = std::ptr::null_mut();
+        self.vulkan13.p_next = std::ptr::null_mut();
+    }
+
+    fn for_each_pair(
+        &self,
+        other: &DeviceFeatures,
+        mut f: impl FnMut(&str, vk::Bool32, vk::Bool32),
+    ) {
+        for (name, get, _) in CORE_FIELDS {
+            f(name, get(&self.core), get(&other.core));
+        }
+        for (name, get, _) in VULKAN11_FIELDS {
+            f(
+                &format!("vulkan11.{}", name),
+                get(&self.vulkan11),
+                get(&other.vulkan11),
+            );
+        }
+        for (name, get, _) in VULKAN12_FIELDS {
+            f(
+                &format!("vulkan12.{}", name),
+                get(&self.vulkan12),
+                get(&other.vulkan12),
+            );
+        }
+        for (name, get, _) in VULKAN13_FIELDS {
+            f(
+                &format!("vulkan13.{}", name),
+                get(&self.vulkan13),
+                get(&other.vulkan13),
+            );
+        }
+    }
+
+    fn combine(&self, other: &DeviceFeatures, op: impl Fn(vk::Bool32, vk::Bool32) -> bool) -> Self {
+        let mut result = DeviceFeatures::default();
+
+        for (_, get, set) in CORE_FIELDS {
+            set(
+                &mut result.core,
+                op(get(&self.core), get(&other.core)) as vk::Bool32,
+            );
+        }
+        for (_, get, set) in VULKAN11_FIELDS {
+            set(
+                &mut result.vulkan11,
+                op(get(&self.vulkan11), get(&other.vulkan11)) as vk::Bool32,
+            );
+        }
+        for (_, get, set) in VULKAN12_FIELDS {
+            set(
+                &mut result.vulkan12,
+                op(get(&self.vulkan12), get(&other.vulkan12)) as vk::Bool32,
+            );
+        }
+        for (_, get, set) in VULKAN13_FIELDS {
+            set(
+                &mut result.vulkan13,
+                op(get(&self.vulkan13), get(&other.vulkan13)) as vk::Bool32,
+            );
+        }
+
+        result
+    }
+}
+
+#[inline]
+fn api_minor(api_version: u32) -> u32 {
+    if vk::api_version_major(api_version) > 1 {
+        u32::MAX
+    } else {
+        vk::api_version_minor(api_version)
+    }
+}
+
+const CORE_FIELDS: &[Field<vk::PhysicalDeviceFeatures>] = feature_fields![
+    robust_buffer_access,
+    full_draw_index_uint32,
+    image_cube_array,
+    independent_blend,
+    geometry_shader,
+    tessellation_shader,
+    sample_rate_shading,
+    dual_src_blend,
+    logic_op,
+    multi_draw_indirect,
+    draw_indirect_first_instance,
+    depth_clamp,
+    depth_bias_clamp,
+    fill_mode_non_solid,
+    depth_bounds,
+    wide_lines,
+    large_points,
+    alpha_to_one,
+    multi_viewport,
+    sampler_anisotropy,
+    texture_compression_etc2,
+    texture_compression_astc_ldr,
+    texture_compression_bc,
+    occlusion_query_precise,
+    pipeline_statistics_query,
+    vertex_pipeline_stores_and_atomics,
+    fragment_stores_and_atomics,
+    shader_tessellation_and_geometry_point_size,
+    shader_image_gather_extended,
+    shader_storage_image_extended_formats,
+    shader_storage_image_multisample,
+    shader_storage_image_read_without_format,
+    shader_storage_image_write_without_format,
+    shader_uniform_buffer_array_dynamic_indexing,
+    shader_sampled_image_array_dynamic_indexing,
+    shader_storage_buffer_array_dynamic_indexing,
+    shader_storage_image_array_dynamic_indexing,
+    shader_clip_distance,
+    shader_cull_distance,
+    shader_float64,
+    shader_int64,
+    shader_int16,
+    shader_resource_residency,
+    shader_resource_min_lod,
+    sparse_binding,
+    sparse_residency_buffer,
+    sparse_residency_image2_d,
+    sparse_residency_image3_d,
+    sparse_residency2_samples,
+    sparse_residency4_samples,
+    sparse_residency8_samples,
+    sparse_residency16_samples,
+    sparse_residency_aliased,
+    variable_multisample_rate,
+    inherited_queries,
+];
+
+const VULKAN11_FIELDS: &[Field<vk::PhysicalDeviceVulkan11Features>] = feature_fields![
+    storage_buffer16_bit_access,
+    uniform_and_storage_buffer16_bit_access,
+    storage_push_constant16,
+    storage_input_output16,
+    multiview,
+    multiview_geometry_shader,
+    multiview_tessellation_shader,
+    variable_pointers_storage_buffer,
+    variable_pointers,
+    protected_memory,
+    sampler_ycbcr_conversion,
+    shader_draw_parameters,
+];
+
+const VULKAN12_FIELDS: &[Field<vk::PhysicalDeviceVulkan12Features>] = feature_fields![
+    sampler_mirror_clamp_to_edge,
+    draw_indirect_count,
+    storage_buffer8_bit_access,
+    uniform_and_storage_buffer8_bit_access,
+    storage_push_constant8,
+    shader_buffer_int64_atomics,
+    shader_shared_int64_atomics,
+    shader_float16,
+    shader_int8,
+    descriptor_indexing,
+    shader_input_attachment_array_dynamic_indexing,
+    shader_uniform_texel_buffer_array_dynamic_indexing,
+    shader_storage_texel_buffer_array_dynamic_indexing,
+    shader_uniform_buffer_array_non_uniform_indexing,
+    shader_sampled_image_array_non_uniform_indexing,
+    shader_storage_buffer_array_non_uniform_indexing,
+    shader_storage_image_array_non_uniform_indexing,
+    shader_input_attachment_array_non_uniform_indexing,
+    shader_uniform_texel_buffer_array_non_uniform_indexing,
+    shader_storage_texel_buffer_array_non_uniform_indexing,
+    descriptor_binding_uniform_buffer_update_after_bind,
+    descriptor_binding_sampled_image_update_after_bind,
+    descriptor_binding_storage_image_update_after_bind,
+    descriptor_binding_storage_buffer_update_after_bind,
+    descriptor_binding_uniform_texel_buffer_update_after_bind,
+    descriptor_binding_storage_texel_buffer_update_after_bind,
+    descriptor_binding_update_unused_while_pending,
+    descriptor_binding_partially_bound,
+    descriptor_binding_variable_descriptor_count,
+    runtime_descriptor_array,
+    sampler_filter_minmax,
+    scalar_block_layout,
+    imageless_framebuffer,
+    uniform_buffer_standard_layout,
+    shader_subgroup_extended_types,
+    separate_depth_stencil_layouts,
+    host_query_reset,
+    timeline_semaphore,
+    buffer_device_address,
+    buffer_device_address_capture_replay,
+    buffer_device_address_multi_device,
+    vulkan_memory_model,
+    vulkan_memory_model_device_scope,
+    vulkan_memory_model_availability_visibility_chains,
+    shader_output_viewport_index,
+    shader_output_layer,
+    subgroup_broadcast_dynamic_id,
+];
+
+const VULKAN13_FIELDS: &[Field<vk::PhysicalDeviceVulkan13Features>] = feature_fields![
+    robust_image_access,
+    inline_uniform_block,
+    descriptor_binding_inline_uniform_block_update_after_bind,
+    pipeline_creation_cache_control,
+    private_data,
+    shader_demote_to_helper_invocation,
+    shader_terminate_invocation,
+    subgroup_size_control,
+    compute_full_subgroups,
+    synchronization2,
+    texture_compression_astc_hdr,
+    shader_zero_initialize_workgroup_memory,
+    dynamic_rendering,
+    shader_integer_dot_product,
+    maintenance4,
+];
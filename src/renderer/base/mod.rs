@@ -1,209 +1,564 @@
+pub mod allocator;
+pub mod device;
+pub mod dynamic_rendering;
+pub mod features;
+pub mod pipeline_cache;
 mod setup;
 
+use std::sync::{Arc, Mutex};
+
 use ash::extensions::{ext, khr};
 use ash::vk;
 
 use crate::renderer::base::setup::{
-    create_swapchain, create_swapchain_image_views, get_surface_capabilities, get_surface_extent,
-    get_swapchain_images,
+    create_offscreen_images, create_swapchain, create_swapchain_image_views,
+    get_surface_capabilities, get_surface_extent, get_swapchain_images,
+};
+
+use super::{
+    config::{DepthAttachment, DeviceSelection, RendererConfig},
+    error::RendererError,
 };
 
+use self::allocator::{Allocation, Allocator};
+use self::device::{enumerate_device_candidates, select_device, DeviceCandidate};
+use self::dynamic_rendering::{DynamicRendering, DynamicRenderingSupport};
+use self::features::DeviceFeatures;
+use self::pipeline_cache::PipelineCache;
+
 use self::setup::{
     create_debug_call_back, create_instance, create_logical_device, create_surface,
-    get_physical_device, get_present_mode, get_queue_family, get_required_instance_extensions,
+    get_async_queue_families, get_present_mode, get_queue_family, get_required_instance_extensions,
     get_surface_format,
 };
 
 pub struct RenderBase {
+    pub config: RendererConfig,
+    /// The loaded Vulkan library, kept alive for as long as the instance.
     pub entry: ash::Entry,
     pub instance: ash::Instance,
-    pub surface_loader: khr::Surface,
-    pub swapchain_loader: khr::Swapchain,
-    #[cfg(debug_assertions)]
-    pub debug_utils_loader: ext::DebugUtils,
-    #[cfg(debug_assertions)]
+    /// `None` when running headless.
+    pub surface_loader: Option<khr::Surface>,
+    /// `None` when running headless.
+    pub swapchain_loader: Option<khr::Swapchain>,
+    /// `None` unless validation is enabled.
+    pub debug_utils_loader: Option<ext::DebugUtils>,
     pub debug_call_back: vk::DebugUtilsMessengerEXT,
+    /// Null when running headless.
     pub surface: vk::SurfaceKHR,
+    /// Every device that was considered, with the reason unsuitable ones were rejected.
+    pub device_candidates: Vec<DeviceCandidate>,
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// The required features plus the optional ones the device supports.
+    pub enabled_features: DeviceFeatures,
+    /// `None` when disabled in the config or unsupported by the device, rendering then goes
+    /// through render pass and framebuffer objects.
+    pub dynamic_rendering: Option<DynamicRendering>,
     pub surface_format: vk::SurfaceFormatKHR,
     pub present_mode: vk::PresentModeKHR,
+    /// Family of the graphics queue, which also presents.
     pub queue_family: u32,
     pub device: ash::Device,
     pub queue: vk::Queue,
+    /// Family of the transfer queue, the graphics family if there is no separate one.
+    pub transfer_queue_family: u32,
+    pub transfer_queue: vk::Queue,
+    /// Family of the async compute queue, the graphics family if there is no separate one.
+    pub compute_queue_family: u32,
+    /// For async compute work, the graphics queue if there is no separate family.
+    pub compute_queue: vk::Queue,
     pub surface_capabilities: vk::SurfaceCapabilitiesKHR,
     pub surface_extent: vk::Extent2D,
+    /// Null when running headless.
     pub swapchain: vk::SwapchainKHR,
+    /// The images frames are rendered into: the swapchain images, or one offscreen color
+    /// image per frame in flight when running headless.
     pub swapchain_images: Vec<vk::Image>,
     pub swapchain_image_views: Vec<vk::ImageView>,
+    /// Backing memory of the offscreen images, empty unless running headless.
+    pub offscreen_memory: Vec<Allocation>,
+    /// Sub-allocates device memory for buffers and images. Owns the device, which is destroyed
+    /// once the base and every buffer and image were dropped.
+    pub allocator: Arc<Mutex<Allocator>>,
+    /// Used for every pipeline, saved to `config.pipeline_cache_path` in `clean_up`.
+    pub pipeline_cache: PipelineCache,
+}
+
+/// What a [`RenderBase`] renders frames into.
+enum RenderTarget<'a> {
+    /// The swapchain of a surface created for the window.
+    Window(&'a winit::window::Window),
+    /// Offscreen color images of the given extent.
+    Offscreen(vk::Extent2D),
 }
 
 impl RenderBase {
-    pub fn new(window: &winit::window::Window) -> Result<Self, String> {
-        let entry = unsafe { ash::Entry::load().map_err(|msg| format!("{}", msg))? };
-        let instance_extensions = get_required_instance_extensions(window).unwrap();
-        let device_extensions = vec![ash::extensions::khr::Swapchain::name()];
+    pub fn new(
+        window: &winit::window::Window,
+        config: RendererConfig,
+    ) -> Result<Self, RendererError> {
+        Self::create(RenderTarget::Window(window), config)
+    }
+
+    /// Creates a base without a window, surface or swapchain.
+    ///
+    /// Any device with a graphics queue is accepted, present support is not required.
+    /// Frames are rendered into offscreen color images of the given extent, which are left
+    /// in `TRANSFER_SRC_OPTIMAL` layout so they can be read back.
+    pub fn new_headless(
+        extent: vk::Extent2D,
+        config: RendererConfig,
+    ) -> Result<Self, RendererError> {
+        Self::create(RenderTarget::Offscreen(extent), config)
+    }
 
-        let instance = create_instance(&entry, &instance_extensions);
+    fn create(target: RenderTarget, config: RendererConfig) -> Result<Self, RendererError> {
+        let window = match target {
+            RenderTarget::Window(window) => Some(window),
+            RenderTarget::Offscreen(_) => None,
+        };
 
-        #[cfg(debug_assertions)]
-        let debug_utils_loader = ext::DebugUtils::new(&entry, &instance);
-        #[cfg(debug_assertions)]
-        let debug_call_back = create_debug_call_back(&debug_utils_loader)?;
+        let entry =
+            unsafe { ash::Entry::load().map_err(|err| RendererError::Loading(err.to_string()))? };
+        let instance_extensions = get_required_instance_extensions(window, config.validation)?;
+        let mut device_extensions = match window {
+            Some(_) => vec![khr::Swapchain::name()],
+            None => vec![],
+        };
 
-        let surface_loader = khr::Surface::new(&entry, &instance);
+        let instance = create_instance(&entry, &instance_extensions, &config)?;
 
-        let surface = create_surface(&entry, &instance, window)?;
+        let (debug_utils_loader, debug_call_back) = if config.validation {
+            let debug_utils_loader = ext::DebugUtils::new(&entry, &instance);
+            let debug_call_back = create_debug_call_back(&debug_utils_loader)?;
+            (Some(debug_utils_loader), debug_call_back)
+        } else {
+            (None, vk::DebugUtilsMessengerEXT::null())
+        };
 
-        let physical_device = get_physical_device(&instance, &device_extensions)?;
+        let (surface_loader, surface) = match window {
+            Some(window) => (
+                Some(khr::Surface::new(&entry, &instance)),
+                create_surface(&entry, &instance, window)?,
+            ),
+            None => (None, vk::SurfaceKHR::null()),
+        };
+        let presentation = surface_loader.as_ref().map(|loader| (loader, surface));
+
+        let device_candidates =
+            enumerate_device_candidates(&instance, &device_extensions, presentation, &config)?;
+        let physical_device = pick_physical_device(&device_candidates, &config)?;
         let physical_device_properties =
             unsafe { instance.get_physical_device_properties(physical_device) };
-        let surface_format = get_surface_format(physical_device, &surface_loader, surface)?;
-        let present_mode = get_present_mode(physical_device, &surface_loader, surface)?;
-        let queue_family = get_queue_family(&instance, physical_device, &surface_loader, surface)?;
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let (surface_format, present_mode) = match presentation {
+            Some((surface_loader, surface)) => (
+                get_surface_format(physical_device, surface_loader, surface)?,
+                get_present_mode(
+                    physical_device,
+                    surface_loader,
+                    surface,
+                    &config.present_modes,
+                )?,
+            ),
+            None => (
+                vk::SurfaceFormatKHR {
+                    format: vk::Format::B8G8R8A8_UNORM,
+                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                },
+                vk::PresentModeKHR::FIFO,
+            ),
+        };
+        let queue_family = get_queue_family(&instance, physical_device, presentation)?;
+        let (transfer_queue_family, compute_queue_family) =
+            get_async_queue_families(&instance, physical_device, queue_family);
+
+        let mut enabled_features = get_enabled_features(
+            &instance,
+            physical_device,
+            &physical_device_properties,
+            &config,
+        );
+        let dynamic_rendering_support = enable_dynamic_rendering(
+            &instance,
+            physical_device,
+            &physical_device_properties,
+            &config,
+            &mut enabled_features,
+            &mut device_extensions,
+        );
 
-        let device =
-            create_logical_device(&instance, physical_device, queue_family, &device_extensions)?;
+        let device = create_logical_device(
+            &instance,
+            physical_device,
+            &[queue_family, transfer_queue_family, compute_queue_family],
+            &device_extensions,
+            &enabled_features,
+            dynamic_rendering_support == Some(DynamicRenderingSupport::Extension),
+        )?;
+        let dynamic_rendering = dynamic_rendering_support
+            .map(|support| DynamicRendering::new(&instance, &device, support));
 
         let queue = unsafe { device.get_device_queue(queue_family, 0) };
+        let transfer_queue = unsafe { device.get_device_queue(transfer_queue_family, 0) };
+        let compute_queue = unsafe { device.get_device_queue(compute_queue_family, 0) };
 
-        let swapchain_loader = khr::Swapchain::new(&instance, &device);
+        let allocator = Arc::new(Mutex::new(Allocator::new(
+            &device,
+            memory_properties,
+            &physical_device_properties.limits,
+        )));
 
-        let resize_data = resize_internal(
-            window,
+        let pipeline_cache = PipelineCache::new(
             &device,
-            &surface_loader,
-            &swapchain_loader,
-            physical_device,
-            vk::SwapchainKHR::null(),
-            surface,
-            &surface_format,
-            present_mode,
-            &vec![],
+            &physical_device_properties,
+            config.pipeline_cache_path.clone(),
         )?;
 
-        Ok(RenderBase {
+        let swapchain_loader = window.map(|_| khr::Swapchain::new(&instance, &device));
+
+        let mut base = RenderBase {
+            config,
             entry,
             instance,
             surface,
             surface_loader,
-
-            #[cfg(debug_assertions)]
             debug_utils_loader,
-            #[cfg(debug_assertions)]
             debug_call_back,
 
+            device_candidates,
             physical_device,
             physical_device_properties,
+            memory_properties,
+            enabled_features,
+            dynamic_rendering,
             surface_format,
             present_mode,
             queue_family,
             queue,
-            surface_capabilities: resize_data.surface_capabilities,
-            surface_extent: resize_data.surface_extent,
-            swapchain: resize_data.swapchain,
-            swapchain_images: resize_data.swapchain_images,
-            swapchain_image_views: resize_data.swapchain_image_views,
+            transfer_queue_family,
+            transfer_queue,
+            compute_queue_family,
+            compute_queue,
+            surface_capabilities: vk::SurfaceCapabilitiesKHR::default(),
+            surface_extent: vk::Extent2D::default(),
+            swapchain: vk::SwapchainKHR::null(),
+            swapchain_images: vec![],
+            swapchain_image_views: vec![],
             swapchain_loader,
+            offscreen_memory: vec![],
+            allocator,
+            pipeline_cache,
             device,
-        })
+        };
+
+        let dedicated = |dedicated: bool| if dedicated { " (dedicated)" } else { "" };
+        log::info!(
+            "queue families: graphics {}, transfer {}{}, compute {}{}",
+            base.queue_family,
+            base.transfer_queue_family,
+            dedicated(base.has_dedicated_transfer_queue()),
+            base.compute_queue_family,
+            dedicated(base.has_dedicated_compute_queue()),
+        );
+        log::info!(
+            "enabled optional features: {}",
+            base.enabled_optional_features().enabled_names().join(", ")
+        );
+
+        match target {
+            RenderTarget::Window(window) => base.resize(window)?,
+            RenderTarget::Offscreen(extent) => base.create_offscreen_images(extent)?,
+        }
+
+        Ok(base)
+    }
+
+    /// The optional features from the config that the device supports and were enabled.
+    #[inline]
+    pub fn enabled_optional_features(&self) -> DeviceFeatures {
+        self.config
+            .optional_features
+            .intersection(&self.enabled_features)
+    }
+
+    /// The distinct queue families in use, for resources shared between queues with
+    /// `SharingMode::CONCURRENT`.
+    pub fn unique_queue_families(&self) -> Vec<u32> {
+        let mut families = vec![
+            self.queue_family,
+            self.transfer_queue_family,
+            self.compute_queue_family,
+        ];
+        families.sort_unstable();
+        families.dedup();
+        families
+    }
+
+    #[inline]
+    pub fn has_dedicated_transfer_queue(&self) -> bool {
+        self.transfer_queue_family != self.queue_family
     }
 
     #[inline]
-    pub fn resize(&mut self, window: &winit::window::Window) -> Result<(), String> {
-        let resize_data = resize_internal(
-            window,
+    pub fn has_dedicated_compute_queue(&self) -> bool {
+        self.compute_queue_family != self.queue_family
+    }
+
+    #[inline]
+    pub fn is_headless(&self) -> bool {
+        self.swapchain_loader.is_none()
+    }
+
+    /// The layout the frame image is left in at the end of a frame.
+    #[inline]
+    pub fn final_image_layout(&self) -> vk::ImageLayout {
+        if self.is_headless() {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        }
+    }
+
+    /// The first of `candidates` that supports `features` with optimal tiling.
+    pub fn find_supported_format(
+        &self,
+        candidates: &[vk::Format],
+        features: vk::FormatFeatureFlags,
+    ) -> Option<vk::Format> {
+        candidates.iter().copied().find(|&format| {
+            let properties = unsafe {
+                self.instance
+                    .get_physical_device_format_properties(self.physical_device, format)
+            };
+            properties.optimal_tiling_features.contains(features)
+        })
+    }
+
+    /// The format of the depth attachment requested by `config.depth_attachment`, `None`
+    /// if it is disabled.
+    pub fn depth_format(&self) -> Result<Option<vk::Format>, RendererError> {
+        let candidates: &[vk::Format] = match self.config.depth_attachment {
+            DepthAttachment::None => return Ok(None),
+            DepthAttachment::Depth => &[
+                vk::Format::D32_SFLOAT,
+                vk::Format::X8_D24_UNORM_PACK32,
+                vk::Format::D16_UNORM,
+            ],
+            DepthAttachment::DepthStencil => &[
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D24_UNORM_S8_UINT,
+                vk::Format::D16_UNORM_S8_UINT,
+            ],
+        };
+
+        self.find_supported_format(candidates, vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            .map(Some)
+            .ok_or_else(|| {
+                RendererError::Unsupported(format!(
+                    "no supported format for a {:?} attachment",
+                    self.config.depth_attachment
+                ))
+            })
+    }
+
+    /// The highest sample count usable for both color and depth attachments.
+    pub fn max_sample_count(&self) -> vk::SampleCountFlags {
+        let limits = &self.physical_device_properties.limits;
+        let supported =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+        [
+            vk::SampleCountFlags::TYPE_64,
+            vk::SampleCountFlags::TYPE_32,
+            vk::SampleCountFlags::TYPE_16,
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2,
+        ]
+        .into_iter()
+        .find(|&count| supported.contains(count))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+
+    /// `config.msaa_samples`, lowered to [`max_sample_count`](Self::max_sample_count) if
+    /// the device doesn't support that many.
+    pub fn sample_count(&self) -> vk::SampleCountFlags {
+        let requested = self.config.msaa_samples.as_raw().max(1);
+        let max = self.max_sample_count().as_raw();
+
+        if requested > max {
+            log::warn!(
+                "{} msaa samples requested, the device supports at most {}",
+                requested,
+                max
+            );
+        }
+
+        // sample counts are single bits, keep the highest one not above either limit
+        let count = requested.min(max);
+        vk::SampleCountFlags::from_raw(1 << (u32::BITS - 1 - count.leading_zeros()))
+    }
+
+    /// Recreates the swapchain for the current window size, does nothing when headless.
+    pub fn resize(&mut self, window: &winit::window::Window) -> Result<(), RendererError> {
+        let (Some(surface_loader), Some(swapchain_loader)) =
+            (&self.surface_loader, &self.swapchain_loader)
+        else {
+            return Ok(());
+        };
+
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .map_err(RendererError::device("failed to wait for device idle"))?;
+        }
+
+        self.surface_capabilities =
+            get_surface_capabilities(surface_loader, self.physical_device, self.surface)?;
+        self.surface_extent = get_surface_extent(window, &self.surface_capabilities);
+        self.swapchain = create_swapchain(self, swapchain_loader)?;
+        self.swapchain_images = get_swapchain_images(swapchain_loader, self.swapchain)?;
+
+        for image_view in self.swapchain_image_views.drain(..) {
+            unsafe {
+                self.device.destroy_image_view(image_view, None);
+            }
+        }
+        self.swapchain_image_views = create_swapchain_image_views(
             &self.device,
-            &self.surface_loader,
-            &self.swapchain_loader,
-            self.physical_device,
-            self.swapchain,
-            self.surface,
+            &self.swapchain_images,
             &self.surface_format,
-            self.present_mode,
-            &self.swapchain_image_views,
         )?;
 
-        self.surface_capabilities = resize_data.surface_capabilities;
-        self.surface_extent = resize_data.surface_extent;
-        self.swapchain = resize_data.swapchain;
-        self.swapchain_images = resize_data.swapchain_images;
-        self.swapchain_image_views = resize_data.swapchain_image_views;
+        Ok(())
+    }
+
+    /// Creates the images headless frames are rendered into, one per frame in flight.
+    fn create_offscreen_images(&mut self, extent: vk::Extent2D) -> Result<(), RendererError> {
+        let (images, memory) = create_offscreen_images(
+            &self.device,
+            &self.allocator,
+            self.surface_format.format,
+            extent,
+            self.config.frames_in_flight,
+        )?;
+
+        self.surface_extent = extent;
+        self.swapchain_image_views =
+            create_swapchain_image_views(&self.device, &images, &self.surface_format)?;
+        self.swapchain_images = images;
+        self.offscreen_memory = memory;
 
         Ok(())
     }
 
-    pub fn clean_up(&self) {
+    pub fn clean_up(&mut self) {
         unsafe {
-            self.swapchain_loader
-                .destroy_swapchain(self.swapchain, None);
+            if let Some(swapchain_loader) = &self.swapchain_loader {
+                swapchain_loader.destroy_swapchain(self.swapchain, None);
+            }
             for &image_view in &self.swapchain_image_views {
                 self.device.destroy_image_view(image_view, None);
             }
-            self.device.destroy_device(None);
-            self.surface_loader.destroy_surface(self.surface, None);
-            #[cfg(debug_assertions)]
-            self.debug_utils_loader
-                .destroy_debug_utils_messenger(self.debug_call_back, None);
+            self.pipeline_cache.clean_up();
+            if self.is_headless() {
+                let mut allocator = self.allocator.lock().unwrap();
+                for &image in &self.swapchain_images {
+                    self.device.destroy_image(image, None);
+                }
+                for allocation in self.offscreen_memory.drain(..) {
+                    allocator.free(allocation);
+                }
+            }
+            // the device is destroyed by the allocator once the last resource was dropped
+            if let Some(surface_loader) = &self.surface_loader {
+                surface_loader.destroy_surface(self.surface, None);
+            }
+            if let Some(debug_utils_loader) = &self.debug_utils_loader {
+                debug_utils_loader.destroy_debug_utils_messenger(self.debug_call_back, None);
+            }
         }
     }
 }
 
-struct ResizeResult {
-    surface_capabilities: vk::SurfaceCapabilitiesKHR,
-    surface_extent: vk::Extent2D,
-    swapchain: vk::SwapchainKHR,
-    swapchain_images: Vec<vk::Image>,
-    swapchain_image_views: Vec<vk::ImageView>,
+/// Selects a device according to the environment override or the config, logging every
+/// candidate so the choice can be diagnosed.
+fn pick_physical_device(
+    candidates: &[DeviceCandidate],
+    config: &RendererConfig,
+) -> Result<vk::PhysicalDevice, RendererError> {
+    let selection = DeviceSelection::from_env().unwrap_or_else(|| config.device_selection.clone());
+
+    for candidate in candidates {
+        log::info!("device candidate {}", candidate);
+    }
+
+    let selected = select_device(candidates, &selection)?;
+    log::info!("selected device [{}] {}", selected.index, selected.name);
+
+    Ok(selected.physical_device)
 }
 
-fn resize_internal(
-    window: &winit::window::Window,
-    device: &ash::Device,
-    surface_loader: &ash::extensions::khr::Surface,
-    swapchain_loader: &ash::extensions::khr::Swapchain,
+/// Combines the required features with the optional ones `physical_device` supports.
+fn get_enabled_features(
+    instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-    old_swapchain: vk::SwapchainKHR,
-    surface: vk::SurfaceKHR,
-    surface_format: &vk::SurfaceFormatKHR,
-    present_mode: vk::PresentModeKHR,
-    old_swapchain_image_views: &Vec<vk::ImageView>,
-) -> Result<ResizeResult, String> {
-    unsafe {
-        device.device_wait_idle().unwrap();
-    }
-
-    let surface_capabilities = get_surface_capabilities(surface_loader, physical_device, surface)?;
-    let surface_extent = get_surface_extent(window, &surface_capabilities);
-
-    let swapchain = create_swapchain(
-        old_swapchain,
-        surface,
-        &surface_capabilities,
-        surface_format,
-        surface_extent,
-        present_mode,
-        swapchain_loader,
-    )?;
-
-    let swapchain_images = get_swapchain_images(swapchain_loader, swapchain)?;
-
-    if !old_swapchain_image_views.is_empty() {
-        for &image_view in old_swapchain_image_views {
-            unsafe {
-                device.destroy_image_view(image_view, None);
-            };
-        }
+    properties: &vk::PhysicalDeviceProperties,
+    config: &RendererConfig,
+) -> DeviceFeatures {
+    let supported = DeviceFeatures::query(
+        instance,
+        physical_device,
+        properties.api_version.min(config.api_version),
+    );
+
+    let unavailable = config.optional_features.missing_from(&supported);
+    if !unavailable.is_empty() {
+        log::info!(
+            "optional features not supported by the device: {}",
+            unavailable.join(", ")
+        );
     }
 
-    let swapchain_image_views =
-        create_swapchain_image_views(device, &swapchain_images, surface_format)?;
+    config
+        .required_features
+        .union(&config.optional_features.intersection(&supported))
+}
+
+/// Picks how dynamic rendering is enabled when the config asks for it, adding its feature to
+/// `enabled_features` or its extension to `device_extensions`.
+fn enable_dynamic_rendering(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    properties: &vk::PhysicalDeviceProperties,
+    config: &RendererConfig,
+    enabled_features: &mut DeviceFeatures,
+    device_extensions: &mut Vec<&std::ffi::CStr>,
+) -> Option<DynamicRenderingSupport> {
+    if !config.dynamic_rendering {
+        return None;
+    }
+
+    let support = DynamicRenderingSupport::query(
+        instance,
+        physical_device,
+        properties.api_version.min(config.api_version),
+    );
+    match support {
+        Some(DynamicRenderingSupport::Core) => {
+            enabled_features.vulkan13.dynamic_rendering = vk::TRUE;
+            log::info!("using core dynamic rendering");
+        }
+        Some(DynamicRenderingSupport::Extension) => {
+            device_extensions.push(khr::DynamicRendering::name());
+            log::info!("using dynamic rendering through VK_KHR_dynamic_rendering");
+        }
+        None => log::info!("dynamic rendering is not supported, using render passes"),
+    }
 
-    Ok(ResizeResult {
-        surface_capabilities,
-        surface_extent,
-        swapchain,
-        swapchain_images,
-        swapchain_image_views,
-    })
+    support
 }
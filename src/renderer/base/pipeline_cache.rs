@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+
+use crate::renderer::error::RendererError;
+
+/// Size of `VkPipelineCacheHeaderVersionOne`.
+const HEADER_SIZE: usize = 32;
+
+/// A `VkPipelineCache` loaded from and saved to a file, so pipelines compiled in one run
+/// don't have to be compiled again in the next.
+///
+/// Cache data written by a different device or driver is discarded, drivers are not
+/// required to reject it themselves.
+pub struct PipelineCache {
+    pub cache: vk::PipelineCache,
+    /// `None` keeps the cache in memory only.
+    pub path: Option<PathBuf>,
+    device: ash::Device,
+}
+
+impl PipelineCache {
+    /// Creates the cache, seeded with the contents of `path` when they were written by the
+    /// same device and driver. A missing or stale file starts an empty cache.
+    pub fn new(
+        device: &ash::Device,
+        properties: &vk::PhysicalDeviceProperties,
+        path: Option<PathBuf>,
+    ) -> Result<Self, RendererError> {
+        let data = path
+            .as_deref()
+            .and_then(|path| read_cache_file(path, properties))
+            .unwrap_or_default();
+
+        let create_info = vk::PipelineCacheCreateInfo::builder().initial_data(&data);
+
+        let cache = unsafe {
+            device
+                .create_pipeline_cache(&create_info, None)
+                .or_else(|_| {
+                    // the driver may still reject data that passed the header check
+                    device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+                })
+                .map_err(RendererError::pipeline("failed to create pipeline cache"))?
+        };
+
+        Ok(Self {
+            cache,
+            path,
+            device: device.clone(),
+        })
+    }
+
+    /// Writes the cache contents to `path`, does nothing without a path.
+    pub fn save(&self) -> Result<(), RendererError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let data = unsafe {
+            self.device
+                .get_pipeline_cache_data(self.cache)
+                .map_err(RendererError::pipeline("failed to get pipeline cache data"))?
+        };
+
+        // write next to the cache and rename, so an interrupted save can't corrupt it
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, &data)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .map_err(RendererError::io(format!(
+                "failed to write pipeline cache {}",
+                path.display()
+            )))?;
+
+        log::info!(
+            "saved {} bytes of pipeline cache to {}",
+            data.len(),
+            path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Saves the cache, logging failures, and destroys it.
+    pub fn clean_up(&mut self) {
+        if let Err(err) = self.save() {
+            log::warn!("{}", err);
+        }
+
+        unsafe { self.device.destroy_pipeline_cache(self.cache, None) };
+        self.cache = vk::PipelineCache::null();
+    }
+}
+
+/// Reads the cache file at `path` if its header matches the device.
+fn read_cache_file(path: &Path, properties: &vk::PhysicalDeviceProperties) -> Option<Vec<u8>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            log::info!("no pipeline cache loaded from {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    if let Err(reason) = check_header(&data, properties) {
+        log::info!("discarding pipeline cache {}: {}", path.display(), reason);
+        return None;
+    }
+
+    log::info!(
+        "loaded {} bytes of pipeline cache from {}",
+        data.len(),
+        path.display()
+    );
+
+    Some(data)
+}
+
+/// Checks a `VkPipelineCacheHeaderVersionOne`, whose fields are always little endian.
+fn check_header(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> Result<(), String> {
+    if data.len() < HEADER_SIZE {
+        return Err(format!("{} bytes is too short for a header", data.len()));
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let header_size = read_u32(0) as usize;
+    let header_version = read_u32(4);
+    let vendor_id = read_u32(8);
+    let device_id = read_u32(12);
+    let uuid = &data[16..32];
+
+    if header_size < HEADER_SIZE || header_size > data.len() {
+        return Err(format!("invalid header size {}", header_size));
+    }
+    if header_version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        return Err(format!("unknown header version {}", header_version));
+    }
+    if vendor_id != properties.vendor_id || device_id != properties.device_id {
+        return Err(format!(
+            "written by device {:04x}:{:04x}, running on {:04x}:{:04x}",
+            vendor_id, device_id, properties.vendor_id, properties.device_id
+        ));
+    }
+    if uuid != properties.pipeline_cache_uuid {
+        return Err(String::from(
+            "the pipeline cache uuid changed, likely a driver update",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2204,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    /// A header for `properties()` followed by some cache data.
+    fn header() -> Vec<u8> {
+        let properties = properties();
+
+        let mut data = Vec::new();
+        data.extend((HEADER_SIZE as u32).to_le_bytes());
+        data.extend((vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes());
+        data.extend(properties.vendor_id.to_le_bytes());
+        data.extend(properties.device_id.to_le_bytes());
+        data.extend(properties.pipeline_cache_uuid);
+        data.extend([1, 2, 3, 4]);
+        data
+    }
+
+    #[test]
+    fn accepts_a_matching_header() {
+        assert_eq!(check_header(&header(), &properties()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_short_file() {
+        assert!(check_header(&header()[..HEADER_SIZE - 1], &properties()).is_err());
+        assert!(check_header(&[], &properties()).is_err());
+    }
+
+    #[test]
+    fn rejects_a_wrong_header_size_or_version() {
+        let mut data = header();
+        data[0] = 16;
+        assert!(check_header(&data, &properties()).is_err());
+
+        let mut data = header();
+        data[4] = 2;
+        assert!(check_header(&data, &properties()).is_err());
+    }
+
+    #[test]
+    fn rejects_another_device() {
+        let mut other_vendor = properties();
+        other_vendor.vendor_id = 0x1002;
+        assert!(check_header(&header(), &other_vendor).is_err());
+
+        let mut other_device = properties();
+        other_device.device_id = 0x2206;
+        assert!(check_header(&header(), &other_device).is_err());
+    }
+
+    #[test]
+    fn rejects_another_pipeline_cache_uuid() {
+        let mut other_driver = properties();
+        other_driver.pipeline_cache_uuid[15] = 8;
+
+        assert!(check_header(&header(), &other_driver).is_err());
+    }
+}
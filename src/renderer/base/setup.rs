@@ -1,19 +1,26 @@
 use std::ffi::{c_char, CStr};
+use std::sync::Mutex;
 
 use ash::extensions::{ext, khr};
 use ash::vk::{self};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
+use crate::renderer::config::RendererConfig;
+
+use super::allocator::{Allocation, AllocationDesc, Allocator, MemoryLocation};
+use super::features::DeviceFeatures;
+use super::RenderBase;
+use crate::renderer::error::RendererError;
 use crate::renderer::utils::vulkan_debug_callback;
 
 pub fn get_swapchain_images(
     swapchain_loader: &khr::Swapchain,
     swapchain: vk::SwapchainKHR,
-) -> Result<Vec<vk::Image>, String> {
+) -> Result<Vec<vk::Image>, RendererError> {
     let swapchain_images = unsafe {
         swapchain_loader
             .get_swapchain_images(swapchain)
-            .map_err(|_| String::from("failed to get swapchain images"))?
+            .map_err(RendererError::swapchain("failed to get swapchain images"))?
     };
 
     Ok(swapchain_images)
@@ -23,16 +30,13 @@ pub fn get_surface_format(
     physical_device: vk::PhysicalDevice,
     surface_loader: &khr::Surface,
     surface: vk::SurfaceKHR,
-) -> Result<vk::SurfaceFormatKHR, String> {
-    let formats = match unsafe {
-        surface_loader.get_physical_device_surface_formats(physical_device, surface)
-    } {
-        Ok(formats) => formats,
-        Err(_) => {
-            return Err(String::from(
+) -> Result<vk::SurfaceFormatKHR, RendererError> {
+    let formats = unsafe {
+        surface_loader
+            .get_physical_device_surface_formats(physical_device, surface)
+            .map_err(RendererError::surface(
                 "failed to get physical device surface formats",
-            ));
-        }
+            ))?
     };
 
     for f in &formats {
@@ -85,39 +89,38 @@ pub fn get_surface_capabilities(
     surface_loader: &khr::Surface,
     physical_device: vk::PhysicalDevice,
     surface: vk::SurfaceKHR,
-) -> Result<vk::SurfaceCapabilitiesKHR, String> {
+) -> Result<vk::SurfaceCapabilitiesKHR, RendererError> {
     let surface_capabilities = unsafe {
         surface_loader
             .get_physical_device_surface_capabilities(physical_device, surface)
-            .map_err(|_| String::from("failed to get physical device surface capabilities"))?
+            .map_err(RendererError::surface(
+                "failed to get physical device surface capabilities",
+            ))?
     };
 
     Ok(surface_capabilities)
 }
 
+/// Finds a queue family with graphics support. When a surface is given the family must
+/// also be able to present to it.
 pub fn get_queue_family(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-    surface_loader: &khr::Surface,
-    surface: vk::SurfaceKHR,
-) -> Result<u32, String> {
+    surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+) -> Result<u32, RendererError> {
     let props = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
 
     for (ind, p) in props.iter().enumerate() {
         if p.queue_count > 0 && p.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-            let present_supported = match unsafe {
-                surface_loader.get_physical_device_surface_support(
-                    physical_device,
-                    ind as u32,
-                    surface,
-                )
-            } {
-                Ok(result) => result,
-                Err(_) => {
-                    return Err(String::from(
-                        "failed to get physical device surface_support",
-                    ))
-                }
+            let present_supported = match surface {
+                Some((surface_loader, surface)) => unsafe {
+                    surface_loader
+                        .get_physical_device_surface_support(physical_device, ind as u32, surface)
+                        .map_err(RendererError::surface(
+                            "failed to get physical device surface support",
+                        ))?
+                },
+                None => true,
             };
 
             if present_supported {
@@ -126,46 +129,95 @@ pub fn get_queue_family(
         }
     }
 
-    Err(String::from(
-        "failed to find graphics queue with present support",
-    ))
+    Err(RendererError::Unsupported(String::from(
+        "failed to find suitable graphics queue",
+    )))
+}
+
+/// Finds a family supporting `required` and none of `avoided`, preferring the one with the
+/// fewest other capabilities.
+pub fn get_dedicated_queue_family(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    required: vk::QueueFlags,
+    avoided: vk::QueueFlags,
+) -> Option<u32> {
+    let props = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+
+    props
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| {
+            p.queue_count > 0
+                && p.queue_flags.contains(required)
+                && !p.queue_flags.intersects(avoided)
+        })
+        .min_by_key(|(_, p)| p.queue_flags.as_raw().count_ones())
+        .map(|(ind, _)| ind as u32)
+}
+
+/// Picks the transfer and compute queue families, preferring families separate from the
+/// graphics one and falling back to `graphics_family`.
+pub fn get_async_queue_families(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    graphics_family: u32,
+) -> (u32, u32) {
+    // transfer only families are usually backed by dma engines
+    let transfer_family = get_dedicated_queue_family(
+        instance,
+        physical_device,
+        vk::QueueFlags::TRANSFER,
+        vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+    )
+    .or_else(|| {
+        get_dedicated_queue_family(
+            instance,
+            physical_device,
+            vk::QueueFlags::TRANSFER,
+            vk::QueueFlags::GRAPHICS,
+        )
+    })
+    .unwrap_or(graphics_family);
+
+    let compute_family = get_dedicated_queue_family(
+        instance,
+        physical_device,
+        vk::QueueFlags::COMPUTE,
+        vk::QueueFlags::GRAPHICS,
+    )
+    .unwrap_or(graphics_family);
+
+    (transfer_family, compute_family)
 }
 
+/// Picks the first of `preferred_modes` the surface supports, falling back to FIFO which
+/// every surface has to support.
 pub fn get_present_mode(
     physical_device: vk::PhysicalDevice,
     surface_loader: &khr::Surface,
     surface: vk::SurfaceKHR,
-) -> Result<vk::PresentModeKHR, String> {
-    let modes = match unsafe {
-        surface_loader.get_physical_device_surface_present_modes(physical_device, surface)
-    } {
-        Ok(formats) => formats,
-        Err(_) => {
-            return Err(String::from(
+    preferred_modes: &[vk::PresentModeKHR],
+) -> Result<vk::PresentModeKHR, RendererError> {
+    let modes = unsafe {
+        surface_loader
+            .get_physical_device_surface_present_modes(physical_device, surface)
+            .map_err(RendererError::surface(
                 "failed to get physical device surface present modes",
-            ));
-        }
+            ))?
     };
 
     if modes.is_empty() {
-        return Err(String::from(
-            "failed to get physical device surface present modes",
-        ));
-    }
-
-    if modes.contains(&vk::PresentModeKHR::MAILBOX) {
-        let present_mode = vk::PresentModeKHR::MAILBOX;
-
-        return Ok(present_mode);
+        return Err(RendererError::Unsupported(String::from(
+            "the surface does not support any present modes",
+        )));
     }
 
-    if modes.contains(&vk::PresentModeKHR::IMMEDIATE) {
-        let present_mode = vk::PresentModeKHR::IMMEDIATE;
-
-        return Ok(present_mode);
-    }
-
-    let present_mode = vk::PresentModeKHR::FIFO;
+    let present_mode = preferred_modes
+        .iter()
+        .copied()
+        .find(|mode| modes.contains(mode))
+        .unwrap_or(vk::PresentModeKHR::FIFO);
 
     Ok(present_mode)
 }
@@ -174,16 +226,14 @@ fn check_required_device_extensions(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
     required_extensions: &Vec<&std::ffi::CStr>,
-) -> Result<(), String> {
-    let supported_device_extensions =
-        match unsafe { instance.enumerate_device_extension_properties(physical_device) } {
-            Ok(props) => props,
-            Err(_) => {
-                return Err(String::from(
-                    "failed to enumerate instance extension properies",
-                ))
-            }
-        };
+) -> Result<(), RendererError> {
+    let supported_device_extensions = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device)
+            .map_err(RendererError::device(
+                "failed to enumerate device extension properties",
+            ))?
+    };
 
     let mut supported_device_extensions_set = std::collections::HashSet::new();
     for vk::ExtensionProperties { extension_name, .. } in &supported_device_extensions {
@@ -193,107 +243,87 @@ fn check_required_device_extensions(
 
     for extension_name in required_extensions {
         if !supported_device_extensions_set.contains(extension_name) {
-            return Err(format!(
+            return Err(RendererError::Unsupported(format!(
                 "device extension {:?} is not supported",
                 extension_name
-            ));
+            )));
         }
     }
 
     Ok(())
 }
 
-fn check_device_suitability(
+pub fn check_device_suitability(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
     required_extensions: &Vec<&std::ffi::CStr>,
     properties: &vk::PhysicalDeviceProperties,
-) -> Result<(), String> {
+    config: &RendererConfig,
+) -> Result<(), RendererError> {
     // api version
 
-    if vk::api_version_major(properties.api_version) < 1
-        && vk::api_version_minor(properties.api_version) < 2
-    {
-        return Err(String::from(
-            "the device does not support API version 1.2.0",
-        ));
+    if (
+        vk::api_version_major(properties.api_version),
+        vk::api_version_minor(properties.api_version),
+    ) < (
+        vk::api_version_major(config.api_version),
+        vk::api_version_minor(config.api_version),
+    ) {
+        return Err(RendererError::Unsupported(format!(
+            "the device does not support API version {}.{}",
+            vk::api_version_major(config.api_version),
+            vk::api_version_minor(config.api_version),
+        )));
     }
 
     // features
-    let features = unsafe { instance.get_physical_device_features(physical_device) };
+    let supported_features = DeviceFeatures::query(
+        instance,
+        physical_device,
+        properties.api_version.min(config.api_version),
+    );
 
-    // TODO pass as parameter
-    if features.tessellation_shader == 0 {
-        return Err(String::from(
-            "the device does not support tesselation shader",
-        ));
-    }
-
-    if features.fill_mode_non_solid == 0 {
-        return Err(String::from(
-            "the device does not support fill mode non solid",
-        ));
-    }
+    DeviceFeatures::check_supported(&config.required_features, &supported_features)?;
 
     check_required_device_extensions(instance, physical_device, required_extensions)?;
 
     Ok(())
 }
 
-pub fn get_physical_device<'a>(
-    instance: &ash::Instance,
-    required_device_extensions: &Vec<&'a std::ffi::CStr>,
-) -> Result<vk::PhysicalDevice, String> {
-    let devices = match unsafe { instance.enumerate_physical_devices() } {
-        Ok(devices) => devices,
-        Err(_) => return Err(String::from("failed to enumerate physical devices")),
-    };
-
-    for physical_device in devices {
-        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
-
-        if let Err(_) = check_device_suitability(
-            instance,
-            physical_device,
-            required_device_extensions,
-            &properties,
-        ) {
-            continue;
-        }
-
-        return Ok(physical_device);
-    }
-
-    Err(String::from("failed to find suitable device"))
-}
-
+/// Creates a swapchain for `base.surface_capabilities` and `base.surface_extent`, replacing
+/// and destroying `base.swapchain` if there is one.
 pub fn create_swapchain(
-    old_swapchain: vk::SwapchainKHR,
-    surface: vk::SurfaceKHR,
-    surface_capabilities: &vk::SurfaceCapabilitiesKHR,
-    surface_format: &vk::SurfaceFormatKHR,
-    surface_extent: vk::Extent2D,
-    present_mode: vk::PresentModeKHR,
+    base: &RenderBase,
     swapchain_loader: &khr::Swapchain,
-) -> Result<vk::SwapchainKHR, String> {
-    let mut image_count = std::cmp::max(surface_capabilities.min_image_count, 3);
+) -> Result<vk::SwapchainKHR, RendererError> {
+    let surface_capabilities = &base.surface_capabilities;
+    let old_swapchain = base.swapchain;
+
+    let mut image_count = std::cmp::max(
+        surface_capabilities.min_image_count,
+        base.config.swapchain_image_count,
+    );
 
     if surface_capabilities.max_image_count != 0 {
         image_count = std::cmp::min(image_count, surface_capabilities.max_image_count);
     }
 
+    // allow reading frames back when the surface supports it
+    let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (surface_capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
     let create_info = vk::SwapchainCreateInfoKHR::builder()
-        .surface(surface)
+        .surface(base.surface)
         .min_image_count(image_count)
-        .image_format(surface_format.format)
-        .image_color_space(surface_format.color_space)
-        .image_extent(surface_extent)
+        .image_format(base.surface_format.format)
+        .image_color_space(base.surface_format.color_space)
+        .image_extent(base.surface_extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(image_usage)
         .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
         .pre_transform(surface_capabilities.current_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-        .present_mode(present_mode)
+        .present_mode(base.present_mode)
         .clipped(true)
         .old_swapchain(old_swapchain)
         .build();
@@ -301,7 +331,7 @@ pub fn create_swapchain(
     let swapchain = unsafe {
         swapchain_loader
             .create_swapchain(&create_info, None)
-            .map_err(|_| String::from("failed to create swapchain"))?
+            .map_err(RendererError::swapchain("failed to create swapchain"))?
     };
 
     if old_swapchain != vk::SwapchainKHR::null() {
@@ -313,9 +343,9 @@ pub fn create_swapchain(
 
 pub fn create_swapchain_image_views(
     device: &ash::Device,
-    swapchain_images: &Vec<vk::Image>,
+    swapchain_images: &[vk::Image],
     surface_format: &vk::SurfaceFormatKHR,
-) -> Result<Vec<vk::ImageView>, String> {
+) -> Result<Vec<vk::ImageView>, RendererError> {
     let mut swapchain_image_views = Vec::with_capacity(swapchain_images.len());
 
     for (i, &image) in swapchain_images.iter().enumerate() {
@@ -339,10 +369,15 @@ pub fn create_swapchain_image_views(
             .build();
 
         let view = unsafe {
-            device.create_image_view(&create_info, None).map_err(|_| {
-                clear_image_views(device, &swapchain_image_views);
-                format!("failed to create image view {}", i)
-            })?
+            device
+                .create_image_view(&create_info, None)
+                .map_err(|result| {
+                    clear_image_views(device, &swapchain_image_views);
+                    RendererError::Swapchain {
+                        context: format!("failed to create image view {}", i),
+                        result,
+                    }
+                })?
         };
 
         swapchain_image_views.push(view);
@@ -351,7 +386,92 @@ pub fn create_swapchain_image_views(
     Ok(swapchain_image_views)
 }
 
-fn clear_image_views(device: &ash::Device, image_views: &Vec<vk::ImageView>) {
+/// Creates `count` device local color images to render into when there is no swapchain.
+pub fn create_offscreen_images(
+    device: &ash::Device,
+    allocator: &Mutex<Allocator>,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    count: usize,
+) -> Result<(Vec<vk::Image>, Vec<Allocation>), RendererError> {
+    let mut images = Vec::with_capacity(count);
+    let mut allocations = Vec::with_capacity(count);
+
+    let clear = |images: &mut Vec<vk::Image>, allocations: &mut Vec<Allocation>| unsafe {
+        for image in images.drain(..) {
+            device.destroy_image(image, None);
+        }
+        let mut allocator = allocator.lock().unwrap();
+        for allocation in allocations.drain(..) {
+            allocator.free(allocation);
+        }
+    };
+
+    for i in 0..count {
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .build();
+
+        let image = unsafe {
+            device.create_image(&create_info, None).map_err(|result| {
+                clear(&mut images, &mut allocations);
+                RendererError::Memory {
+                    context: format!("failed to create offscreen image {}", i),
+                    result,
+                }
+            })?
+        };
+        images.push(image);
+
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+
+        let allocation = allocator.lock().unwrap().allocate(&AllocationDesc {
+            name: "offscreen image",
+            requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false,
+        });
+        let allocation = match allocation {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                clear(&mut images, &mut allocations);
+                return Err(err);
+            }
+        };
+
+        let (memory, offset) = (allocation.memory, allocation.offset);
+        allocations.push(allocation);
+
+        unsafe {
+            device
+                .bind_image_memory(image, memory, offset)
+                .map_err(|result| {
+                    clear(&mut images, &mut allocations);
+                    RendererError::Memory {
+                        context: format!("failed to bind offscreen image memory {}", i),
+                        result,
+                    }
+                })?
+        };
+    }
+
+    Ok((images, allocations))
+}
+
+fn clear_image_views(device: &ash::Device, image_views: &[vk::ImageView]) {
     for &image_view in image_views {
         unsafe {
             device.destroy_image_view(image_view, None);
@@ -363,28 +483,39 @@ pub fn create_surface(
     entry: &ash::Entry,
     instance: &ash::Instance,
     window: &winit::window::Window,
-) -> Result<vk::SurfaceKHR, String> {
+) -> Result<vk::SurfaceKHR, RendererError> {
     let surface = unsafe {
         ash_window::create_surface(
-            &entry,
-            &instance,
+            entry,
+            instance,
             window.raw_display_handle(),
             window.raw_window_handle(),
             None,
         )
-        .map_err(|_| String::from("failed to create surface"))?
+        .map_err(RendererError::surface("failed to create surface"))?
     };
 
     Ok(surface)
 }
 
-pub fn create_logical_device<'a>(
+/// Creates the device with one queue for each of `queue_families` and `features` enabled,
+/// chaining the Vulkan 1.1+ feature structs through `VkPhysicalDeviceFeatures2` when any of
+/// them are set.
+///
+/// `dynamic_rendering_extension` enables the feature of `VK_KHR_dynamic_rendering`, which has
+/// to be in `device_extensions`.
+pub fn create_logical_device(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-    queue_family: u32,
-    device_extensions: &Vec<&'a std::ffi::CStr>,
-) -> Result<ash::Device, String> {
-    let queue_indices = [queue_family];
+    queue_families: &[u32],
+    device_extensions: &[&std::ffi::CStr],
+    features: &DeviceFeatures,
+    dynamic_rendering_extension: bool,
+) -> Result<ash::Device, RendererError> {
+    // one queue per distinct family
+    let mut queue_indices = queue_families.to_vec();
+    queue_indices.sort_unstable();
+    queue_indices.dedup();
 
     let mut queue_priorities = Vec::new();
     for _ in &queue_indices {
@@ -401,62 +532,59 @@ pub fn create_logical_device<'a>(
         queue_create_infos.push(info.build());
     }
 
-    // TODO pass features as parameter
-    let features = vk::PhysicalDeviceFeatures::builder()
-        .tessellation_shader(true)
-        .fill_mode_non_solid(true)
-        .build();
+    let mut features = *features;
+    features.clear_chain();
 
     let device_extensions_raw = device_extensions
         .iter()
         .map(|&s| s.as_ptr())
         .collect::<Vec<*const std::os::raw::c_char>>();
 
-    let create_info = vk::DeviceCreateInfo::builder()
+    let mut create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_create_infos)
-        .enabled_extension_names(&device_extensions_raw)
-        .enabled_features(&features);
+        .enabled_extension_names(&device_extensions_raw);
+
+    let mut features2 = vk::PhysicalDeviceFeatures2::builder().features(features.core);
+
+    let (has_vulkan11, has_vulkan12, has_vulkan13) = (
+        features.has_vulkan11(),
+        features.has_vulkan12(),
+        features.has_vulkan13(),
+    );
+
+    if features.needs_features2() {
+        if has_vulkan11 {
+            features2 = features2.push_next(&mut features.vulkan11);
+        }
+        if has_vulkan12 {
+            features2 = features2.push_next(&mut features.vulkan12);
+        }
+        if has_vulkan13 {
+            features2 = features2.push_next(&mut features.vulkan13);
+        }
+        create_info = create_info.push_next(&mut features2);
+    } else {
+        create_info = create_info.enabled_features(&features.core);
+    }
+
+    let mut dynamic_rendering_features =
+        vk::PhysicalDeviceDynamicRenderingFeatures::builder().dynamic_rendering(true);
+    if dynamic_rendering_extension {
+        create_info = create_info.push_next(&mut dynamic_rendering_features);
+    }
 
     let device = unsafe {
         instance
             .create_device(physical_device, &create_info, None)
-            .map_err(|_| String::from("failed to create device"))?
+            .map_err(RendererError::device("failed to create device"))?
     };
 
-    return Ok(device);
+    Ok(device)
 }
 
-// pub fn create_allocator(
-//     instance: &ash::Instance,
-//     device: &ash::Device,
-//     physical_device: vk::PhysicalDevice,
-// ) -> Result<vulkan::Allocator, String> {
-//     let debug_settings = gpu_allocator::AllocatorDebugSettings {
-//         log_memory_information: true,
-//         log_leaks_on_shutdown: true,
-//         store_stack_traces: false,
-//         log_allocations: true,
-//         log_frees: true,
-//         log_stack_traces: false,
-//     };
-
-//     let create_info = &vulkan::AllocatorCreateDesc {
-//         instance: instance.clone(),
-//         device: device.clone(),
-//         physical_device,
-//         debug_settings,
-//         buffer_device_address: false,
-//     };
-
-//     let allocator = vulkan::Allocator::new(&create_info)
-//         .map_err(|_| String::from("failed to create allocator"))?;
-
-//     Ok(allocator)
-// }
-
 pub fn create_debug_call_back(
     debug_utils_loader: &ext::DebugUtils,
-) -> Result<vk::DebugUtilsMessengerEXT, String> {
+) -> Result<vk::DebugUtilsMessengerEXT, RendererError> {
     let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
         .message_severity(
             vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
@@ -473,73 +601,90 @@ pub fn create_debug_call_back(
     let debug_call_back = unsafe {
         debug_utils_loader
             .create_debug_utils_messenger(&debug_info, None)
-            .map_err(|err| format!("{}", err))?
+            .map_err(RendererError::instance("failed to create debug messenger"))?
     };
 
     Ok(debug_call_back)
 }
 
-pub fn create_instance<'a>(
+pub fn create_instance(
     entry: &ash::Entry,
-    instance_extensions: &Vec<&'a std::ffi::CStr>,
-) -> ash::Instance {
+    instance_extensions: &[&std::ffi::CStr],
+    config: &RendererConfig,
+) -> Result<ash::Instance, RendererError> {
     let extension_names_raw = instance_extensions
         .iter()
         .map(|ext| ext.as_ptr())
         .collect::<Vec<_>>();
 
     let app_info = vk::ApplicationInfo::builder()
-        .api_version(vk::make_api_version(0, 1, 2, 0))
+        .api_version(config.api_version)
         .build();
 
-    let layer_names = unsafe {
-        [CStr::from_bytes_with_nul_unchecked(
-            b"VK_LAYER_KHRONOS_validation\0",
-        )]
-    };
+    let validation_layer = c"VK_LAYER_KHRONOS_validation";
+
+    let mut layer_names = Vec::new();
+    if config.validation {
+        let available_layers =
+            entry
+                .enumerate_instance_layer_properties()
+                .map_err(RendererError::instance(
+                    "failed to enumerate instance layer properties",
+                ))?;
+
+        if available_layers
+            .iter()
+            .any(|layer| unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) } == validation_layer)
+        {
+            layer_names.push(validation_layer);
+        } else {
+            log::warn!(
+                "validation was requested but {:?} is not installed",
+                validation_layer
+            );
+        }
+    }
+
     let layers_names_raw: Vec<*const c_char> = layer_names
         .iter()
         .map(|raw_name| raw_name.as_ptr())
         .collect();
 
-    let create_info = vk::InstanceCreateInfo {
-        p_application_info: &app_info,
-        enabled_extension_count: extension_names_raw.len() as u32,
-        pp_enabled_extension_names: extension_names_raw.as_ptr(),
-        #[cfg(debug_assertions)]
-        enabled_layer_count: layers_names_raw.len() as u32,
-        #[cfg(debug_assertions)]
-        pp_enabled_layer_names: layers_names_raw.as_ptr(),
-        ..Default::default()
-    };
+    let create_info = vk::InstanceCreateInfo::builder()
+        .application_info(&app_info)
+        .enabled_extension_names(&extension_names_raw)
+        .enabled_layer_names(&layers_names_raw);
 
     let instance = unsafe {
         entry
             .create_instance(&create_info, None)
-            .expect("Failed to create instance")
+            .map_err(RendererError::instance("failed to create instance"))?
     };
 
-    instance
+    Ok(instance)
 }
 
+/// The extensions needed to present to `window` (none when headless), plus debug utils when
+/// validation is enabled.
 pub fn get_required_instance_extensions(
-    window: &winit::window::Window,
-) -> Result<Vec<&'static std::ffi::CStr>, String> {
-    let mut instance_extensions =
-        match ash_window::enumerate_required_extensions(window.raw_display_handle()) {
-            Ok(extensions) => extensions
-                .to_vec()
-                .into_iter()
-                .map(|name| unsafe { std::ffi::CStr::from_ptr(name) })
-                .collect::<Vec<&'static std::ffi::CStr>>(),
-            Err(_) => {
-                return Err(String::from(
-                    "failed to enumerate required instance extensions",
-                ))
-            }
-        };
+    window: Option<&winit::window::Window>,
+    validation: bool,
+) -> Result<Vec<&'static std::ffi::CStr>, RendererError> {
+    let mut instance_extensions = match window {
+        Some(window) => ash_window::enumerate_required_extensions(window.raw_display_handle())
+            .map_err(RendererError::instance(
+                "failed to enumerate required instance extensions",
+            ))?
+            .to_vec()
+            .into_iter()
+            .map(|name| unsafe { std::ffi::CStr::from_ptr(name) })
+            .collect::<Vec<&'static std::ffi::CStr>>(),
+        None => vec![],
+    };
 
-    instance_extensions.push(ash::extensions::ext::DebugUtils::name());
+    if validation {
+        instance_extensions.push(ash::extensions::ext::DebugUtils::name());
+    }
 
     Ok(instance_extensions)
 }
@@ -0,0 +1,246 @@
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use ash::vk;
+
+use super::{
+    base::allocator::MemoryLocation, error::RendererError, resources::buffer::Buffer, Renderer,
+};
+
+/// A frame copied back from the GPU, as tightly packed 8 bit RGBA rows.
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl CapturedFrame {
+    pub fn save_png(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgba)?;
+
+        Ok(())
+    }
+
+    /// Writes a binary (P6) PPM, the alpha channel is dropped.
+    pub fn save_ppm(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in self.rgba.chunks_exact(4) {
+            file.write_all(&pixel[..3])?;
+        }
+
+        file.flush()
+    }
+}
+
+impl Renderer {
+    /// Copies the next frame drawn into host memory, retrieve it with
+    /// [`Renderer::take_capture`] after the following [`Renderer::draw`]. The request is
+    /// logged and dropped if frames of the surface can't be captured.
+    pub fn request_capture(&mut self) {
+        match self.check_capture_support() {
+            Ok(()) => self.capture_requested = true,
+            Err(err) => log::warn!("dropping capture request: {}", err),
+        }
+    }
+
+    #[inline]
+    pub fn take_capture(&mut self) -> Option<CapturedFrame> {
+        self.captured_frame.take()
+    }
+
+    /// Frames can be captured from surfaces whose images can be copied from, in an 8 bit
+    /// RGBA or BGRA format.
+    pub(super) fn check_capture_support(&self) -> Result<(), RendererError> {
+        if !self.base.is_headless()
+            && !self
+                .base
+                .surface_capabilities
+                .supported_usage_flags
+                .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            return Err(RendererError::Unsupported(String::from(
+                "the surface does not support copying from swapchain images",
+            )));
+        }
+
+        swaps_red_blue(self.base.surface_format.format).map(|_| ())
+    }
+
+    /// Records the copy of the current frame image into the readback buffer.
+    ///
+    /// Must be recorded after the render graph ran, the image is returned to the final layout
+    /// the graph left it in.
+    pub(super) fn record_capture(&mut self) -> Result<(), RendererError> {
+        let extent = self.base.surface_extent;
+        let size = extent.width as u64 * extent.height as u64 * 4;
+
+        let readback_buffer = match self.readback_buffer.take() {
+            Some(buffer) if buffer.size >= size => buffer,
+            old_buffer => {
+                // free the old buffer before allocating the bigger one
+                drop(old_buffer);
+
+                Buffer::new(
+                    &self.base.device,
+                    &self.base.allocator,
+                    size,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    MemoryLocation::GpuToCpu,
+                    "readback buffer",
+                )?
+            }
+        };
+        let readback_buf = readback_buffer.buf;
+        self.readback_buffer = Some(readback_buffer);
+
+        let command_buffer = self.data.command_buffers[self.current_frame_index];
+        let image = self.base.swapchain_images[self.image_index];
+        let final_layout = self.base.final_image_layout();
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        let to_transfer = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(final_layout)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range)
+            .build();
+
+        let to_final = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(final_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range)
+            .build();
+
+        let host_barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(readback_buf)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .build();
+
+        unsafe {
+            self.base.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer],
+            );
+
+            self.base.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buf,
+                &[region],
+            );
+
+            self.base.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_barrier],
+                &[to_final],
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the submitted frame and converts the readback buffer into a [`CapturedFrame`].
+    pub(super) fn read_capture(&mut self) -> Result<(), RendererError> {
+        let Some(readback_buffer) = &self.readback_buffer else {
+            return Ok(());
+        };
+
+        let fence = self.data.fences[self.current_frame_index];
+        let extent = self.base.surface_extent;
+        let size = extent.width as u64 * extent.height as u64 * 4;
+
+        let swap_red_blue = swaps_red_blue(self.base.surface_format.format)?;
+
+        unsafe {
+            self.base
+                .device
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .map_err(RendererError::sync("failed to wait for captured frame"))?;
+        }
+
+        let mut rgba = readback_buffer.read_slice::<u8>(0, size as usize)?;
+
+        if swap_red_blue {
+            for pixel in rgba.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        self.captured_frame = Some(CapturedFrame {
+            width: extent.width,
+            height: extent.height,
+            rgba,
+        });
+
+        Ok(())
+    }
+}
+
+/// Whether frames of `format` are stored as BGRA and need their red and blue channels swapped.
+fn swaps_red_blue(format: vk::Format) -> Result<bool, RendererError> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Ok(true),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(false),
+        format => Err(RendererError::Unsupported(format!(
+            "can't capture frames with format {:?}",
+            format
+        ))),
+    }
+}
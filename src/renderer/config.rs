@@ -0,0 +1,343 @@
+use std::{path::PathBuf, str::FromStr};
+
+use ash::vk;
+
+use super::base::features::DeviceFeatures;
+
+/// Environment variable overriding [`RendererConfig::device_selection`], parsed with
+/// [`DeviceSelection::from_str`].
+pub const DEVICE_SELECTION_ENV: &str = "RENDERER_DEVICE";
+
+/// Startup settings of the renderer.
+///
+/// Start from [`RendererConfig::default`] and override what's needed:
+///
+/// ```ignore
+/// let config = RendererConfig::default()
+///     .frames_in_flight(3)
+///     .present_modes(vec![vk::PresentModeKHR::FIFO])
+///     .clear_color([0.0, 0.0, 0.0, 1.0]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RendererConfig {
+    pub frames_in_flight: usize,
+    /// Present modes in order of preference, FIFO is used if none of them are supported.
+    pub present_modes: Vec<vk::PresentModeKHR>,
+    /// The minimum number of swapchain images to request, clamped to the surface limits.
+    pub swapchain_image_count: u32,
+    pub clear_color: [f32; 4],
+    /// Enables the Khronos validation layer and the debug messenger.
+    pub validation: bool,
+    pub api_version: u32,
+    /// Which GPU to use, the `RENDERER_DEVICE` environment variable takes precedence.
+    pub device_selection: DeviceSelection,
+    /// Devices lacking any of these are rejected.
+    pub required_features: DeviceFeatures,
+    /// Enabled when the selected device supports them, see
+    /// [`RenderBase::enabled_optional_features`](super::base::RenderBase::enabled_optional_features).
+    pub optional_features: DeviceFeatures,
+    /// The depth buffer of the render pass, its format is picked from the ones the device
+    /// supports.
+    pub depth_attachment: DepthAttachment,
+    /// Samples per pixel, lowered to the highest count the device supports. More than one
+    /// renders to multisampled targets that are resolved into the frame image.
+    pub msaa_samples: vk::SampleCountFlags,
+    /// File the pipeline cache is loaded from at startup and saved to on shutdown, `None`
+    /// keeps it in memory only.
+    pub pipeline_cache_path: Option<PathBuf>,
+    /// Directory the shaders are loaded from at runtime, `shaders` next to the executable if
+    /// it exists and `src/shaders` in the working directory otherwise. Missing `vert.spv` and
+    /// `frag.spv` fall back to the copies built into the binary.
+    pub shader_dir: PathBuf,
+    /// Compiles `vertex.vert` and `fragment.frag` from `shader_dir` when the renderer starts
+    /// instead of loading the precompiled `vert.spv` and `frag.spv`. On in debug builds only,
+    /// release builds load the precompiled shaders.
+    pub compile_shaders: bool,
+    /// `#define`s the shaders are compiled with, as name and value.
+    pub shader_defines: Vec<(String, String)>,
+    /// Watches the shaders and rebuilds the pipeline between frames when they change.
+    pub hot_reload: bool,
+    /// Renders with `VK_KHR_dynamic_rendering` (core in 1.3) instead of render pass and
+    /// framebuffer objects when the device supports it.
+    pub dynamic_rendering: bool,
+}
+
+/// Which depth buffer to render with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DepthAttachment {
+    None,
+    #[default]
+    Depth,
+    /// Depth with a stencil component.
+    DepthStencil,
+}
+
+/// How the physical device is chosen when several are available.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DeviceSelection {
+    /// The highest scoring suitable device.
+    #[default]
+    Best,
+    /// The device at this position in the enumeration order.
+    Index(usize),
+    /// The best device whose name contains this string, ignoring case.
+    Name(String),
+    /// The best device with this PCI vendor id, and device id if given.
+    Id {
+        vendor_id: u32,
+        device_id: Option<u32>,
+    },
+}
+
+impl DeviceSelection {
+    /// Reads the selection from the `RENDERER_DEVICE` environment variable, if it is set.
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(DEVICE_SELECTION_ENV).ok()?;
+
+        match value.parse() {
+            Ok(selection) => Some(selection),
+            Err(err) => {
+                log::warn!("ignoring {}: {}", DEVICE_SELECTION_ENV, err);
+                None
+            }
+        }
+    }
+}
+
+/// Accepts an index (`1`), a hexadecimal PCI vendor id (`0x10de`), a vendor and device id
+/// pair (`10de:2684`) or any other text as a name substring.
+impl FromStr for DeviceSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            return Err(String::from("empty device selection"));
+        }
+
+        if let Ok(index) = s.parse::<usize>() {
+            return Ok(Self::Index(index));
+        }
+
+        let parse_hex = |id: &str| u32::from_str_radix(id.trim_start_matches("0x"), 16).ok();
+
+        if let Some((vendor_id, device_id)) = s.split_once(':') {
+            if let (Some(vendor_id), Some(device_id)) = (parse_hex(vendor_id), parse_hex(device_id))
+            {
+                return Ok(Self::Id {
+                    vendor_id,
+                    device_id: Some(device_id),
+                });
+            }
+        } else if s.starts_with("0x") {
+            if let Some(vendor_id) = parse_hex(s) {
+                return Ok(Self::Id {
+                    vendor_id,
+                    device_id: None,
+                });
+            }
+        }
+
+        Ok(Self::Name(String::from(s)))
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            frames_in_flight: 2,
+            present_modes: vec![
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::FIFO,
+            ],
+            swapchain_image_count: 3,
+            clear_color: [0.04f32, 0.01f32, 0.1f32, 1.0f32],
+            validation: cfg!(debug_assertions),
+            api_version: vk::make_api_version(0, 1, 2, 0),
+            device_selection: DeviceSelection::Best,
+            required_features: DeviceFeatures::default(),
+            optional_features: DeviceFeatures {
+                core: vk::PhysicalDeviceFeatures {
+                    tessellation_shader: vk::TRUE,
+                    fill_mode_non_solid: vk::TRUE,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            depth_attachment: DepthAttachment::Depth,
+            msaa_samples: vk::SampleCountFlags::TYPE_4,
+            pipeline_cache_path: Some(PathBuf::from("pipeline_cache.bin")),
+            shader_dir: default_shader_dir(),
+            compile_shaders: cfg!(debug_assertions),
+            shader_defines: Vec::new(),
+            hot_reload: cfg!(debug_assertions),
+            dynamic_rendering: true,
+        }
+    }
+}
+
+/// `shaders` next to the executable for installed builds, `src/shaders` for `cargo run` from
+/// the repository otherwise.
+fn default_shader_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("shaders")))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from("src/shaders"))
+}
+
+impl RendererConfig {
+    #[inline]
+    pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.frames_in_flight = frames_in_flight.max(1);
+        self
+    }
+
+    #[inline]
+    pub fn present_modes(mut self, present_modes: Vec<vk::PresentModeKHR>) -> Self {
+        self.present_modes = present_modes;
+        self
+    }
+
+    #[inline]
+    pub fn swapchain_image_count(mut self, swapchain_image_count: u32) -> Self {
+        self.swapchain_image_count = swapchain_image_count;
+        self
+    }
+
+    #[inline]
+    pub fn clear_color(mut self, clear_color: [f32; 4]) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    #[inline]
+    pub fn validation(mut self, validation: bool) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    #[inline]
+    pub fn api_version(mut self, api_version: u32) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    #[inline]
+    pub fn device_selection(mut self, device_selection: DeviceSelection) -> Self {
+        self.device_selection = device_selection;
+        self
+    }
+
+    #[inline]
+    pub fn required_features(mut self, required_features: DeviceFeatures) -> Self {
+        self.required_features = required_features;
+        self
+    }
+
+    #[inline]
+    pub fn optional_features(mut self, optional_features: DeviceFeatures) -> Self {
+        self.optional_features = optional_features;
+        self
+    }
+
+    #[inline]
+    pub fn depth_attachment(mut self, depth_attachment: DepthAttachment) -> Self {
+        self.depth_attachment = depth_attachment;
+        self
+    }
+
+    #[inline]
+    pub fn msaa_samples(mut self, msaa_samples: vk::SampleCountFlags) -> Self {
+        self.msaa_samples = msaa_samples;
+        self
+    }
+
+    #[inline]
+    pub fn pipeline_cache_path(mut self, pipeline_cache_path: Option<PathBuf>) -> Self {
+        self.pipeline_cache_path = pipeline_cache_path;
+        self
+    }
+
+    #[inline]
+    pub fn shader_dir(mut self, shader_dir: PathBuf) -> Self {
+        self.shader_dir = shader_dir;
+        self
+    }
+
+    #[inline]
+    pub fn compile_shaders(mut self, compile_shaders: bool) -> Self {
+        self.compile_shaders = compile_shaders;
+        self
+    }
+
+    #[inline]
+    pub fn shader_define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.shader_defines.push((name.into(), value.into()));
+        self
+    }
+
+    #[inline]
+    pub fn hot_reload(mut self, hot_reload: bool) -> Self {
+        self.hot_reload = hot_reload;
+        self
+    }
+
+    #[inline]
+    pub fn dynamic_rendering(mut self, dynamic_rendering: bool) -> Self {
+        self.dynamic_rendering = dynamic_rendering;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_vendor_id() {
+        assert_eq!(
+            "0x10de".parse(),
+            Ok(DeviceSelection::Id {
+                vendor_id: 0x10de,
+                device_id: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_vendor_and_device_id() {
+        assert_eq!(
+            "10de:2684".parse(),
+            Ok(DeviceSelection::Id {
+                vendor_id: 0x10de,
+                device_id: Some(0x2684),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_index() {
+        assert_eq!(" 1 ".parse(), Ok(DeviceSelection::Index(1)));
+    }
+
+    #[test]
+    fn parses_name() {
+        assert_eq!(
+            "GeForce".parse(),
+            Ok(DeviceSelection::Name(String::from("GeForce")))
+        );
+        assert_eq!(
+            "0xzz".parse(),
+            Ok(DeviceSelection::Name(String::from("0xzz")))
+        );
+    }
+
+    #[test]
+    fn rejects_empty_selection() {
+        assert!("".parse::<DeviceSelection>().is_err());
+        assert!("  ".parse::<DeviceSelection>().is_err());
+    }
+}
@@ -1,48 +1,170 @@
-use crate::create_shader;
+use std::{
+    mem::ManuallyDrop,
+    path::{Path, PathBuf},
+};
+
 use ash::vk;
 
 use super::{
     base::RenderBase,
-    resources::{self},
-    utils::MAX_FRAME_DRAWS,
+    error::RendererError,
+    graph::{ImageId, ImageSize, ImportedImage, PassDesc, PassId, RenderGraph, TransientImage},
+    resources::{
+        self,
+        buffer::Buffer,
+        descriptors::{DescriptorAllocator, DescriptorLayoutCache},
+        image::{self, Image},
+        mesh::Mesh,
+        pipeline::{DepthState, PipelineBuilder},
+        reflection::{PipelineReflection, ShaderReflection},
+        shader::{self, ShaderWatcher},
+        shader_compiler::{CompiledShader, ShaderCompiler},
+        uniforms::{FrameUniforms, MeshPushConstants},
+        vertex::{Vertex, VertexFormat},
+    },
 };
 
 pub struct RenderData {
     pub vertex_shader_module: vk::ShaderModule,
     pub fragment_shader_module: vk::ShaderModule,
-    // pub index_buffer: Buffer,
-    // pub index_count: u32,
-    // pub vertex_buffer: Buffer,
+    pub vertex_shader_path: PathBuf,
+    pub fragment_shader_path: PathBuf,
+    /// Compiles the shaders when they are loaded from source.
+    pub shader_compiler: ShaderCompiler,
+    /// Watches the shader files and their includes when hot reloading is enabled.
+    pub shader_watcher: Option<ShaderWatcher>,
+    /// Vertex input of `pipeline`, meshes have to match it.
+    pub vertex_binding: vk::VertexInputBindingDescription,
+    pub vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    pub meshes: Vec<Mesh>,
+    /// Written to the current frame's uniform buffer at the start of every draw.
+    pub frame_uniforms: FrameUniforms,
+    pub uniform_buffers: Vec<Buffer>,
+    pub descriptor_layout_cache: DescriptorLayoutCache,
+    pub descriptor_allocator: DescriptorAllocator,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    /// One set per frame in flight, pointing to the uniform buffer of that frame.
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    /// Sampled by the fragment shader through `texture_set`, dropped in `clean_up`.
+    pub texture: ManuallyDrop<Image>,
+    pub sampler: vk::Sampler,
+    pub texture_set_layout: vk::DescriptorSetLayout,
+    pub texture_set: vk::DescriptorSet,
+    /// Push constant ranges of `pipeline_layout`, checked by [`Renderer::cmd_push_constants`](super::Renderer::cmd_push_constants).
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
     pub pipeline_layout: vk::PipelineLayout,
-    pub render_pass: vk::RenderPass,
+    /// What the current shaders expect, `pipeline_layout` and the descriptor set layouts are
+    /// made from it.
+    pub pipeline_reflection: PipelineReflection,
+    /// Owns the depth and multisampled color images, and the render passes unless dynamic
+    /// rendering is used.
+    pub render_graph: RenderGraph,
+    /// Draws the meshes into the frame image.
+    pub scene_pass: PassId,
+    /// The swapchain or offscreen image the frame ends up in.
+    pub frame_image: ImageId,
     pub pipeline: vk::Pipeline,
+    /// Settings of `pipeline` without its shader stages, reused when the shaders are reloaded.
+    pub pipeline_builder: PipelineBuilder,
     pub viewport: vk::Viewport,
     pub scissor: vk::Rect2D,
-    pub framebuffers: Vec<vk::Framebuffer>,
     pub img_available_semaphores: Vec<vk::Semaphore>,
     pub render_finished_semaphores: Vec<vk::Semaphore>,
     pub fences: Vec<vk::Fence>,
     pub command_pool: vk::CommandPool,
+    /// For uploads on the transfer queue.
+    pub transfer_command_pool: vk::CommandPool,
     pub command_buffers: Vec<vk::CommandBuffer>,
 }
 
 impl RenderData {
-    pub fn new(base: &mut RenderBase) -> Result<Self, String> {
-        let vertex_shader_module = create_shader!("../shaders/vert.spv", base.device);
-        let fragment_shader_module = create_shader!("../shaders/frag.spv", base.device);
+    pub fn new(base: &mut RenderBase) -> Result<Self, RendererError> {
+        let shader_dir = &base.config.shader_dir;
+        let (vertex_shader_path, fragment_shader_path) = if base.config.compile_shaders {
+            (
+                shader_dir.join("vertex.vert"),
+                shader_dir.join("fragment.frag"),
+            )
+        } else {
+            (shader_dir.join("vert.spv"), shader_dir.join("frag.spv"))
+        };
+        let shader_compiler = base.config.shader_defines.iter().fold(
+            ShaderCompiler::new().include_dir(shader_dir),
+            |compiler, (name, value)| compiler.define(name, value),
+        );
+
+        let shaders = LoadedShaders::load(
+            &base.device,
+            &shader_compiler,
+            &vertex_shader_path,
+            &fragment_shader_path,
+        )?;
+        let pipeline_reflection = shaders.reflection.clone();
+        let shader_watcher = base
+            .config
+            .hot_reload
+            .then(|| ShaderWatcher::new(&shaders.files));
 
-        let pipeline_layout = resources::create_pipeline_layout(&base.device)?;
+        let frames_in_flight = base.config.frames_in_flight;
 
-        let render_pass = resources::create_render_pass(&base.device, base.surface_format.format)?;
+        let uniform_buffers = resources::create_uniform_buffers(base, frames_in_flight)?;
+        let mut descriptor_layout_cache = DescriptorLayoutCache::new(&base.device);
+        let mut descriptor_allocator = DescriptorAllocator::new(&base.device);
 
-        let pipeline = resources::create_pipelines(
+        // the renderer writes sets 0 and 1 and pushes the mesh constants itself, so the
+        // shaders have to agree with it before layouts are made from their reflection
+        pipeline_reflection.check_set(0, &resources::frame_set_bindings())?;
+        pipeline_reflection.check_set(1, &resources::texture_set_bindings())?;
+        pipeline_reflection.check_push_constants(&[MeshPushConstants::range()])?;
+
+        let vertex_binding = Vertex::binding_description();
+        let vertex_attributes = Vertex::attribute_descriptions();
+        pipeline_reflection.check_vertex_input(&vertex_attributes)?;
+
+        let set_layouts = pipeline_reflection.set_layouts(&mut descriptor_layout_cache)?;
+        let descriptor_set_layout = set_layouts[0];
+        let texture_set_layout = set_layouts[1];
+
+        let descriptor_sets = resources::create_frame_descriptor_sets(
             &base.device,
-            vertex_shader_module,
-            fragment_shader_module,
-            pipeline_layout,
-            render_pass,
+            &mut descriptor_allocator,
+            descriptor_set_layout,
+            &uniform_buffers,
         )?;
 
+        let push_constant_ranges = pipeline_reflection.push_constant_ranges.clone();
+        let pipeline_layout = resources::create_pipeline_layout(
+            &base.device,
+            &set_layouts,
+            &push_constant_ranges,
+            &base.physical_device_properties.limits,
+        )?;
+
+        let depth_format = base.depth_format()?;
+        let samples = base.sample_count();
+        let (render_graph, scene_pass, frame_image) =
+            create_render_graph(base, depth_format, samples)?;
+        // null with dynamic rendering, the pipeline is made for the attachment formats then
+        let render_pass = render_graph.render_pass(scene_pass);
+        let (color_formats, _) = render_graph.attachment_formats(scene_pass);
+
+        let depth_state = if depth_format.is_some() {
+            DepthState::READ_WRITE
+        } else {
+            DepthState::DISABLED
+        };
+
+        let pipeline_builder = PipelineBuilder::new()
+            .vertex_layout(&[vertex_binding], &vertex_attributes)
+            .depth_state(depth_state)
+            .samples(samples)
+            .rendering_formats(color_formats[0], depth_format);
+
+        let pipeline =
+            shaders
+                .stages(pipeline_builder.clone())
+                .build(base, pipeline_layout, render_pass)?;
+
         let viewport = vk::Viewport {
             x: 0.0,
             y: 0.0,
@@ -60,94 +182,376 @@ impl RenderData {
             },
         };
 
-        let framebuffers = resources::create_framebuffers(
-            &base.device,
-            &base.swapchain_image_views,
-            render_pass,
-            base.surface_extent,
-        )?;
-
         let img_available_semaphores =
-            resources::create_semaphore(&base.device, "img available semaphore")?;
+            resources::create_semaphore(&base.device, "img available semaphore", frames_in_flight)?;
 
-        let render_finished_semaphores =
-            resources::create_semaphore(&base.device, "rendering finished semaphore")?;
+        let render_finished_semaphores = resources::create_semaphore(
+            &base.device,
+            "rendering finished semaphore",
+            frames_in_flight,
+        )?;
 
-        let fences = resources::create_fences(&base.device)?;
+        let fences = resources::create_fences(&base.device, frames_in_flight)?;
         let command_pool = resources::create_command_pool(&base.device, base.queue_family)?;
+        let transfer_command_pool =
+            resources::create_command_pool(&base.device, base.transfer_queue_family)?;
+
+        let texture = resources::create_default_texture(base, transfer_command_pool)?;
+        let sampler = image::create_sampler(
+            &base.device,
+            vk::Filter::NEAREST,
+            vk::SamplerAddressMode::REPEAT,
+        )?;
+        let texture_set = descriptor_allocator.allocate(texture_set_layout)?;
+        resources::write_texture_set(&base.device, texture_set, texture.view, sampler);
 
         let command_buffers = {
             unsafe {
                 let cb_info = vk::CommandBufferAllocateInfo::builder()
                     .command_pool(command_pool)
                     .level(vk::CommandBufferLevel::PRIMARY)
-                    .command_buffer_count(MAX_FRAME_DRAWS as u32)
+                    .command_buffer_count(frames_in_flight as u32)
                     .build();
                 base.device
                     .allocate_command_buffers(&cb_info)
-                    .map_err(|err| format!("{}", err))?
+                    .map_err(RendererError::command("failed to allocate command buffers"))?
             }
         };
 
         Ok(Self {
-            vertex_shader_module,
-            fragment_shader_module,
-            // index_buffer: todo!(),
-            // index_count: todo!(),
-            // vertex_buffer: todo!(),
+            vertex_shader_module: shaders.vertex_module,
+            fragment_shader_module: shaders.fragment_module,
+            vertex_shader_path,
+            fragment_shader_path,
+            shader_compiler,
+            shader_watcher,
+            vertex_binding,
+            vertex_attributes,
+            meshes: Vec::new(),
+            frame_uniforms: FrameUniforms::default(),
+            uniform_buffers,
+            descriptor_layout_cache,
+            descriptor_allocator,
+            descriptor_set_layout,
+            descriptor_sets,
+            texture: ManuallyDrop::new(texture),
+            sampler,
+            texture_set_layout,
+            texture_set,
+            push_constant_ranges,
             pipeline_layout,
-            render_pass,
+            pipeline_reflection,
+            render_graph,
+            scene_pass,
+            frame_image,
             pipeline,
+            pipeline_builder,
             viewport,
             scissor,
-            framebuffers,
             img_available_semaphores,
             render_finished_semaphores,
             fences,
             command_pool,
+            transfer_command_pool,
             command_buffers,
         })
     }
 
-    #[inline]
-    pub fn resize(&mut self, vulkan_base: &RenderBase) -> Result<(), String> {
-        unsafe {
-            for &framebuffer in &self.framebuffers {
-                vulkan_base.device.destroy_framebuffer(framebuffer, None);
+    /// Points the graph at the new frame images and recreates its images and framebuffers
+    /// for the new surface extent.
+    pub fn resize(&mut self, vulkan_base: &RenderBase) -> Result<(), RendererError> {
+        self.render_graph
+            .set_imported_images(self.frame_image, frame_image_handles(vulkan_base));
+        self.render_graph.resize(vulkan_base)
+    }
+
+    /// Loads the shaders from disk again and rebuilds `pipeline` with them. On failure the
+    /// current shaders and pipeline are kept.
+    ///
+    /// The new shaders have to fit the current pipeline layout and vertex input, changing
+    /// descriptor bindings or push constants needs a restart.
+    ///
+    /// The gpu must not be using `pipeline` anymore.
+    pub fn reload_shaders(&mut self, base: &RenderBase) -> Result<(), RendererError> {
+        let shaders = LoadedShaders::load(
+            &base.device,
+            &self.shader_compiler,
+            &self.vertex_shader_path,
+            &self.fragment_shader_path,
+        )?;
+
+        let pipeline = if shaders.reflection.same_layout(&self.pipeline_reflection) {
+            shaders
+                .reflection
+                .check_vertex_input(&self.vertex_attributes)
+                .and_then(|_| {
+                    shaders.stages(self.pipeline_builder.clone()).build(
+                        base,
+                        self.pipeline_layout,
+                        self.render_graph.render_pass(self.scene_pass),
+                    )
+                })
+        } else {
+            Err(RendererError::InvalidUsage(String::from(
+                "the reloaded shaders need a different pipeline layout",
+            )))
+        };
+
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                shaders.destroy(&base.device);
+                return Err(err);
             }
+        };
+
+        unsafe {
+            base.device.destroy_pipeline(self.pipeline, None);
+            base.device
+                .destroy_shader_module(self.vertex_shader_module, None);
+            base.device
+                .destroy_shader_module(self.fragment_shader_module, None);
         }
 
-        self.framebuffers = resources::create_framebuffers(
-            &vulkan_base.device,
-            &vulkan_base.swapchain_image_views,
-            self.render_pass,
-            vulkan_base.surface_extent,
-        )?;
+        self.pipeline = pipeline;
+        self.vertex_shader_module = shaders.vertex_module;
+        self.fragment_shader_module = shaders.fragment_module;
+        self.pipeline_reflection = shaders.reflection;
+        if let Some(watcher) = &mut self.shader_watcher {
+            // includes may have been added or removed
+            watcher.watch(&shaders.files);
+        }
 
         Ok(())
     }
 
-    pub fn clean_up(&self, device: &ash::Device) {
+    pub fn clean_up(&mut self, device: &ash::Device) {
+        self.meshes.clear();
+        self.uniform_buffers.clear();
+        self.render_graph.clean_up();
+        unsafe { ManuallyDrop::drop(&mut self.texture) };
+        self.descriptor_allocator.clean_up();
+        self.descriptor_layout_cache.clean_up();
+
         unsafe {
+            device.destroy_sampler(self.sampler, None);
+
             device.destroy_shader_module(self.vertex_shader_module, None);
             device.destroy_shader_module(self.fragment_shader_module, None);
 
             device.destroy_pipeline_layout(self.pipeline_layout, None);
 
-            device.destroy_render_pass(self.render_pass, None);
-
             device.destroy_pipeline(self.pipeline, None);
 
-            for &framebuffer in &self.framebuffers {
-                device.destroy_framebuffer(framebuffer, None);
-            }
-
-            for i in 0..MAX_FRAME_DRAWS {
+            for i in 0..self.fences.len() {
                 device.destroy_semaphore(self.img_available_semaphores[i], None);
                 device.destroy_semaphore(self.render_finished_semaphores[i], None);
                 device.destroy_fence(self.fences[i], None);
             }
             device.destroy_command_pool(self.command_pool, None);
+            device.destroy_command_pool(self.transfer_command_pool, None);
         }
     }
 }
+
+/// The vertex and fragment shader modules with their reflection.
+struct LoadedShaders {
+    vertex_module: vk::ShaderModule,
+    vertex_entry_point: String,
+    fragment_module: vk::ShaderModule,
+    fragment_entry_point: String,
+    reflection: PipelineReflection,
+    /// Every file the shaders were read from, includes too.
+    files: Vec<PathBuf>,
+}
+
+impl LoadedShaders {
+    /// Compiles or reads and then reflects both shaders, failing if they don't fit together,
+    /// then creates their modules.
+    fn load(
+        device: &ash::Device,
+        compiler: &ShaderCompiler,
+        vertex_path: &Path,
+        fragment_path: &Path,
+    ) -> Result<Self, RendererError> {
+        let vertex_shader = load_stage(compiler, vertex_path, vk::ShaderStageFlags::VERTEX)?;
+        let fragment_shader = load_stage(compiler, fragment_path, vk::ShaderStageFlags::FRAGMENT)?;
+        let (vertex_code, fragment_code) = (vertex_shader.code, fragment_shader.code);
+        let mut files = vertex_shader.files;
+        for file in fragment_shader.files {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+
+        let vertex = reflect(&vertex_code, vertex_path, vk::ShaderStageFlags::VERTEX)?;
+        let fragment = reflect(
+            &fragment_code,
+            fragment_path,
+            vk::ShaderStageFlags::FRAGMENT,
+        )?;
+        let reflection = PipelineReflection::new(&[vertex.clone(), fragment.clone()])?;
+
+        let vertex_module = shader::create_shader_module(device, &vertex_code)?;
+        let fragment_module = match shader::create_shader_module(device, &fragment_code) {
+            Ok(module) => module,
+            Err(err) => {
+                unsafe { device.destroy_shader_module(vertex_module, None) };
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            vertex_module,
+            vertex_entry_point: vertex.entry_point,
+            fragment_module,
+            fragment_entry_point: fragment.entry_point,
+            reflection,
+            files,
+        })
+    }
+
+    /// Adds both shaders to `builder`.
+    fn stages(&self, builder: PipelineBuilder) -> PipelineBuilder {
+        builder
+            .stage_with_entry(
+                vk::ShaderStageFlags::VERTEX,
+                self.vertex_module,
+                &self.vertex_entry_point,
+            )
+            .stage_with_entry(
+                vk::ShaderStageFlags::FRAGMENT,
+                self.fragment_module,
+                &self.fragment_entry_point,
+            )
+    }
+
+    fn destroy(self, device: &ash::Device) {
+        unsafe {
+            device.destroy_shader_module(self.vertex_module, None);
+            device.destroy_shader_module(self.fragment_module, None);
+        }
+    }
+}
+
+/// Reflects the only entry point of `code`, which has to be a `stage` shader.
+fn reflect(
+    code: &[u32],
+    path: &Path,
+    stage: vk::ShaderStageFlags,
+) -> Result<ShaderReflection, RendererError> {
+    let reflection = ShaderReflection::from_spirv(code, None).map_err(|err| match err {
+        RendererError::Unsupported(msg) => {
+            RendererError::Unsupported(format!("{}: {}", path.display(), msg))
+        }
+        err => RendererError::InvalidUsage(format!("{}: {}", path.display(), err)),
+    })?;
+
+    if reflection.stage != stage {
+        return Err(RendererError::InvalidUsage(format!(
+            "{}: expected a {:?} shader, found {:?}",
+            path.display(),
+            stage,
+            reflection.stage
+        )));
+    }
+
+    Ok(reflection)
+}
+
+/// Loads the shader at `path`, using the SPIR-V built into the binary when a precompiled
+/// default shader is missing from disk.
+fn load_stage(
+    compiler: &ShaderCompiler,
+    path: &Path,
+    stage: vk::ShaderStageFlags,
+) -> Result<CompiledShader, RendererError> {
+    match path.file_name().and_then(shader::embedded_spirv) {
+        Some(bytes) if !path.exists() => {
+            log::info!("{} not found, using the built in shader", path.display());
+            Ok(CompiledShader {
+                code: shader::parse_spirv(bytes, path)?,
+                files: vec![path.to_path_buf()],
+            })
+        }
+        _ => compiler.load_file(path, stage),
+    }
+}
+
+/// Builds the graph of a single pass drawing into the frame image, through a multisampled
+/// color image that is resolved into it when `samples` is more than one and with a depth image
+/// when there is a depth format.
+fn create_render_graph(
+    base: &RenderBase,
+    depth_format: Option<vk::Format>,
+    samples: vk::SampleCountFlags,
+) -> Result<(RenderGraph, PassId, ImageId), RendererError> {
+    let mut graph = RenderGraph::new();
+
+    let frame_image = graph.import_image(
+        "frame image",
+        ImportedImage {
+            format: base.surface_format.format,
+            size: ImageSize::Surface,
+            samples: vk::SampleCountFlags::TYPE_1,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: base.final_image_layout(),
+        },
+        frame_image_handles(base),
+    );
+
+    let clear_color = Some(vk::ClearColorValue {
+        float32: base.config.clear_color,
+    });
+    let mut scene = PassDesc::new("scene");
+    if samples != vk::SampleCountFlags::TYPE_1 {
+        let msaa_color_image = graph.create_image(
+            "msaa color image",
+            TransientImage {
+                format: base.surface_format.format,
+                size: ImageSize::Surface,
+                samples,
+            },
+        );
+        scene = scene
+            .color_attachment(msaa_color_image, clear_color)
+            .resolve_attachment(frame_image);
+    } else {
+        scene = scene.color_attachment(frame_image, clear_color);
+    }
+
+    if let Some(format) = depth_format {
+        let depth_image = graph.create_image(
+            "depth image",
+            TransientImage {
+                format,
+                size: ImageSize::Surface,
+                samples,
+            },
+        );
+        scene = scene.depth_attachment(
+            depth_image,
+            Some(vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            }),
+        );
+    }
+
+    let scene_pass = graph.add_pass(scene);
+    if let Err(err) = graph.compile(base) {
+        graph.clean_up();
+        return Err(err);
+    }
+
+    Ok((graph, scene_pass, frame_image))
+}
+
+#[inline]
+fn frame_image_handles(base: &RenderBase) -> Vec<(vk::Image, vk::ImageView)> {
+    base.swapchain_images
+        .iter()
+        .copied()
+        .zip(base.swapchain_image_views.iter().copied())
+        .collect()
+}
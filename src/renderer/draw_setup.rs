@@ -1,36 +1,54 @@
+use std::time::Duration;
+
 use ash::vk;
 
-use super::Renderer;
+use super::{
+    error::RendererError,
+    resources::{pod::Pod, uniforms::MeshPushConstants},
+    Renderer,
+};
 
 impl Renderer {
-    #[inline]
-    pub fn begin_render_pass(&self) {
-        let clear_color = vk::ClearColorValue {
-            float32: [0.04f32, 0.01f32, 0.1f32, 1.0f32],
-        };
-        let clear_values = vec![vk::ClearValue { color: clear_color }];
-
-        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-            .render_pass(self.data.render_pass)
-            .framebuffer(self.data.framebuffers[self.image_index])
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.base.surface_extent,
-            })
-            .clear_values(&clear_values)
-            .build();
+    /// Records the scene pass: binds the pipeline and its sets and draws every mesh.
+    pub fn record_scene(&self) -> Result<(), RendererError> {
+        let command_buffer = self.data.command_buffers[self.current_frame_index];
 
         unsafe {
-            self.base.device.cmd_begin_render_pass(
-                self.data.command_buffers[self.current_frame_index],
-                &render_pass_begin_info,
-                vk::SubpassContents::INLINE,
+            self.base.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.data.pipeline,
+            );
+
+            self.base.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.data.pipeline_layout,
+                0,
+                &[
+                    self.data.descriptor_sets[self.current_frame_index],
+                    self.data.texture_set,
+                ],
+                &[],
             );
         }
+
+        for mesh in &self.data.meshes {
+            self.cmd_set_viewport(&mesh.viewport.unwrap_or(self.data.viewport));
+            self.cmd_set_scissor(&mesh.scissor.unwrap_or(self.data.scissor));
+            self.cmd_push_constants(
+                MeshPushConstants::range().stage_flags,
+                0,
+                &MeshPushConstants { model: mesh.model },
+            )?;
+            mesh.draw(&self.base.device, command_buffer);
+        }
+
+        Ok(())
     }
 
     #[inline]
-    pub fn begin_command_buffer(&self) {
+    pub fn begin_command_buffer(&self) -> Result<(), RendererError> {
         let begin_info = vk::CommandBufferBeginInfo::builder()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
             .build();
@@ -42,15 +60,21 @@ impl Renderer {
                     self.data.command_buffers[self.current_frame_index],
                     &begin_info,
                 )
-                .map_err(|_| String::from("failed to begin command buffer"))
-                .unwrap();
+                .map_err(RendererError::command("failed to begin command buffer"))?;
         }
+
+        Ok(())
     }
 
     #[inline]
-    pub fn get_img_index(&self) -> Result<Option<u32>, String> {
+    pub fn get_img_index(&self) -> Result<Option<u32>, RendererError> {
+        // headless bases have one offscreen image per frame in flight
+        let Some(swapchain_loader) = &self.base.swapchain_loader else {
+            return Ok(Some(self.current_frame_index as u32));
+        };
+
         let (index, is_suboptimal) = match unsafe {
-            self.base.swapchain_loader.acquire_next_image(
+            swapchain_loader.acquire_next_image(
                 self.base.swapchain,
                 u64::MAX,
                 self.data.img_available_semaphores[self.current_frame_index],
@@ -59,7 +83,12 @@ impl Renderer {
         } {
             Ok((index, is_suboptimal)) => (index, is_suboptimal),
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(None),
-            Err(message) => return Err(format!("{}", message)),
+            Err(result) => {
+                return Err(RendererError::Swapchain {
+                    context: String::from("failed to acquire next swapchain image"),
+                    result,
+                })
+            }
         };
 
         if is_suboptimal {
@@ -69,7 +98,11 @@ impl Renderer {
         Ok(Some(index))
     }
     #[inline]
-    pub fn present(&self) -> Result<bool, String> {
+    pub fn present(&self) -> Result<bool, RendererError> {
+        let Some(swapchain_loader) = &self.base.swapchain_loader else {
+            return Ok(true);
+        };
+
         let semaphores = [self.data.render_finished_semaphores[self.current_frame_index]];
         let swapchains = [self.base.swapchain];
         let indices = [self.image_index as u32];
@@ -80,15 +113,14 @@ impl Renderer {
             .build();
 
         unsafe {
-            if let Err(err) = self
-                .base
-                .swapchain_loader
-                .queue_present(self.base.queue, &present_info)
-            {
+            if let Err(err) = swapchain_loader.queue_present(self.base.queue, &present_info) {
                 if err == vk::Result::SUBOPTIMAL_KHR || err == vk::Result::ERROR_OUT_OF_DATE_KHR {
                     return Ok(false);
                 } else {
-                    return Err(String::from("failed to present"));
+                    return Err(RendererError::Swapchain {
+                        context: String::from("failed to present"),
+                        result: err,
+                    });
                 }
             }
         }
@@ -96,6 +128,7 @@ impl Renderer {
         Ok(true)
     }
 
+    /// Resets `data.scissor` to the whole surface.
     #[inline]
     pub fn set_scissor(&mut self) {
         self.data.scissor = vk::Rect2D {
@@ -107,6 +140,142 @@ impl Renderer {
         };
     }
 
+    /// Rebuilds the pipeline if the watched shader files changed. Shaders that fail to load
+    /// or compile are logged and the previous pipeline stays in use.
+    pub fn reload_changed_shaders(&mut self) -> Result<(), RendererError> {
+        let changed = match &mut self.data.shader_watcher {
+            Some(watcher) => watcher.poll(),
+            None => false,
+        };
+        if !changed {
+            return Ok(());
+        }
+
+        unsafe {
+            self.base
+                .device
+                .device_wait_idle()
+                .map_err(RendererError::sync("failed to wait for device idle"))?;
+        }
+
+        match self.data.reload_shaders(&self.base) {
+            Ok(()) => log::info!("reloaded shaders"),
+            Err(err) => log::error!("failed to reload shaders, keeping the old ones: {}", err),
+        }
+
+        Ok(())
+    }
+
+    /// Sets the viewport of the following draws in the current frame's command buffer.
+    ///
+    /// Draws of meshes without their own viewport use `data.viewport`, which covers the
+    /// whole surface unless changed.
+    #[inline]
+    pub fn cmd_set_viewport(&self, viewport: &vk::Viewport) {
+        unsafe {
+            self.base.device.cmd_set_viewport(
+                self.data.command_buffers[self.current_frame_index],
+                0,
+                std::slice::from_ref(viewport),
+            );
+        }
+    }
+
+    /// Sets the scissor rect of the following draws in the current frame's command buffer.
+    #[inline]
+    pub fn cmd_set_scissor(&self, scissor: &vk::Rect2D) {
+        unsafe {
+            self.base.device.cmd_set_scissor(
+                self.data.command_buffers[self.current_frame_index],
+                0,
+                std::slice::from_ref(scissor),
+            );
+        }
+    }
+
+    /// Records a push of `value` at byte `offset` into the current frame's command buffer.
+    ///
+    /// The pushed bytes have to lie within the declared push constant ranges of `stages`, and
+    /// every range they overlap has to be fully included in `stages`.
+    pub fn cmd_push_constants<T: Pod>(
+        &self,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        value: &T,
+    ) -> Result<(), RendererError> {
+        let size = std::mem::size_of::<T>() as u32;
+        let Some(end) = offset.checked_add(size) else {
+            return Err(RendererError::InvalidUsage(format!(
+                "push constants of {} bytes at {} overflow",
+                size, offset
+            )));
+        };
+
+        let covered = |stage: vk::ShaderStageFlags| {
+            self.data.push_constant_ranges.iter().any(|range| {
+                range.stage_flags.contains(stage)
+                    && range.offset <= offset
+                    && end <= range.offset + range.size
+            })
+        };
+
+        let all_stages_covered = (0..u32::BITS)
+            .map(|bit| vk::ShaderStageFlags::from_raw(1 << bit) & stages)
+            .filter(|stage| !stage.is_empty())
+            .all(covered);
+
+        let overlapping_included = self.data.push_constant_ranges.iter().all(|range| {
+            range.offset >= end
+                || range.offset + range.size <= offset
+                || stages.contains(range.stage_flags)
+        });
+
+        if stages.is_empty() || !offset.is_multiple_of(4) || !size.is_multiple_of(4) {
+            return Err(RendererError::InvalidUsage(format!(
+                "push constants of {} bytes at {} for {:?} must be 4 byte aligned",
+                size, offset, stages
+            )));
+        }
+        if !all_stages_covered || !overlapping_included {
+            return Err(RendererError::InvalidUsage(format!(
+                "push constants of {} bytes at {} for {:?} don't match the pipeline layout",
+                size, offset, stages
+            )));
+        }
+
+        unsafe {
+            let bytes = std::slice::from_raw_parts(value as *const T as *const u8, size as usize);
+            self.base.device.cmd_push_constants(
+                self.data.command_buffers[self.current_frame_index],
+                self.data.pipeline_layout,
+                stages,
+                offset,
+                bytes,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Advances the time in `frame_uniforms` and writes them to the current frame's buffer.
+    ///
+    /// Must be called after the frame's fence was waited on, the gpu may still read the
+    /// buffer otherwise.
+    #[inline]
+    pub fn update_frame_uniforms(&mut self, delta_time: &Duration) -> Result<(), RendererError> {
+        let uniforms = &mut self.data.frame_uniforms;
+        uniforms.delta_time = delta_time.as_secs_f32();
+        uniforms.time += uniforms.delta_time;
+        uniforms.resolution = [
+            self.base.surface_extent.width as f32,
+            self.base.surface_extent.height as f32,
+        ];
+
+        self.data.uniform_buffers[self.current_frame_index]
+            .write_slice(std::slice::from_ref(&self.data.frame_uniforms), 0)
+    }
+
+    /// Resets `data.viewport` to the whole surface.
     #[inline]
     pub fn set_viewport(&mut self) {
         self.data.viewport = vk::Viewport {
@@ -120,50 +289,58 @@ impl Renderer {
     }
 
     #[inline]
-    pub fn submit(&self) -> Result<(), String> {
-        let fence = self.data.fences[self.current_frame_index as usize];
+    pub fn submit(&self) -> Result<(), RendererError> {
+        let fence = self.data.fences[self.current_frame_index];
 
         let wait_semaphores = [self.data.img_available_semaphores[self.current_frame_index]];
         let masks = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
         let cmd_buffers = [self.data.command_buffers[self.current_frame_index]];
         let signal_semaphores = [self.data.render_finished_semaphores[self.current_frame_index]];
-        let submit_info = vk::SubmitInfo::builder()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&masks)
-            .command_buffers(&cmd_buffers)
-            .signal_semaphores(&signal_semaphores)
-            .build();
+        // without a swapchain nothing signals or waits for the frame semaphores
+        let submit_info = if self.base.is_headless() {
+            vk::SubmitInfo::builder()
+                .command_buffers(&cmd_buffers)
+                .build()
+        } else {
+            vk::SubmitInfo::builder()
+                .wait_semaphores(&wait_semaphores)
+                .wait_dst_stage_mask(&masks)
+                .command_buffers(&cmd_buffers)
+                .signal_semaphores(&signal_semaphores)
+                .build()
+        };
 
         unsafe {
             self.base
                 .device
                 .queue_submit(self.base.queue, &[submit_info], fence)
-                .map_err(|_| String::from("failed to submit graphics command buffer"))?
+                .map_err(RendererError::command(
+                    "failed to submit graphics command buffer",
+                ))?
         }
 
         Ok(())
     }
     #[inline]
-    pub fn wait_resource_available(&self) -> Result<(), String> {
-        let fence = self.data.fences[self.current_frame_index as usize];
+    pub fn wait_resource_available(&self) -> Result<(), RendererError> {
+        let fence = self.data.fences[self.current_frame_index];
 
         unsafe {
             self.base
                 .device
                 .wait_for_fences(&[fence], true, u64::MAX)
-                .map_err(|_| {
-                    format!(
-                        "failed to wait for resource fence {}",
-                        self.current_frame_index
-                    )
-                })?;
-
-            self.base.device.reset_fences(&[fence]).map_err(|_| {
-                format!(
+                .map_err(RendererError::sync(format!(
+                    "failed to wait for resource fence {}",
+                    self.current_frame_index
+                )))?;
+
+            self.base
+                .device
+                .reset_fences(&[fence])
+                .map_err(RendererError::sync(format!(
                     "failed to reset resource fence {}",
                     self.current_frame_index
-                )
-            })?;
+                )))?;
         }
 
         Ok(())
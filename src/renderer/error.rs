@@ -0,0 +1,162 @@
+use std::fmt;
+
+use ash::vk;
+
+/// Every failure the renderer can report.
+///
+/// Variants that wrap a Vulkan call keep the original [`vk::Result`] next to a short
+/// description of what was being attempted, so callers can react to specific results
+/// (see [`RendererError::is_out_of_date`], [`RendererError::is_out_of_memory`] and
+/// [`RendererError::is_device_lost`]) instead of parsing messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RendererError {
+    /// The Vulkan loader could not be found or initialized.
+    Loading(String),
+    Instance {
+        context: String,
+        result: vk::Result,
+    },
+    Surface {
+        context: String,
+        result: vk::Result,
+    },
+    Device {
+        context: String,
+        result: vk::Result,
+    },
+    Swapchain {
+        context: String,
+        result: vk::Result,
+    },
+    Pipeline {
+        context: String,
+        result: vk::Result,
+    },
+    Memory {
+        context: String,
+        result: vk::Result,
+    },
+    Command {
+        context: String,
+        result: vk::Result,
+    },
+    Sync {
+        context: String,
+        result: vk::Result,
+    },
+    /// The hardware or driver lacks something the renderer requires.
+    Unsupported(String),
+    /// An argument breaks a Vulkan usage rule, like a misaligned push constant range.
+    InvalidUsage(String),
+    /// Reading or writing a file failed.
+    Io(String),
+    /// A shader failed to preprocess, compile or validate. Locations point into the original
+    /// source files, not the preprocessed source.
+    Shader(String),
+}
+
+macro_rules! error_constructor {
+    ($name:ident, $variant:ident) => {
+        /// Returns a closure wrapping a [`vk::Result`] into this variant, meant for `map_err`.
+        pub fn $name(context: impl Into<String>) -> impl FnOnce(vk::Result) -> Self {
+            let context = context.into();
+            move |result| Self::$variant { context, result }
+        }
+    };
+}
+
+impl RendererError {
+    error_constructor!(instance, Instance);
+    error_constructor!(surface, Surface);
+    error_constructor!(device, Device);
+    error_constructor!(swapchain, Swapchain);
+    error_constructor!(pipeline, Pipeline);
+    error_constructor!(memory, Memory);
+    error_constructor!(command, Command);
+    error_constructor!(sync, Sync);
+
+    /// Returns a closure wrapping an io error into [`RendererError::Io`], meant for `map_err`.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let context = context.into();
+        move |err| Self::Io(format!("{}: {}", context, err))
+    }
+
+    /// The Vulkan result that caused this error, if there was one.
+    pub fn result(&self) -> Option<vk::Result> {
+        match self {
+            Self::Instance { result, .. }
+            | Self::Surface { result, .. }
+            | Self::Device { result, .. }
+            | Self::Swapchain { result, .. }
+            | Self::Pipeline { result, .. }
+            | Self::Memory { result, .. }
+            | Self::Command { result, .. }
+            | Self::Sync { result, .. } => Some(*result),
+            Self::Loading(_)
+            | Self::Unsupported(_)
+            | Self::InvalidUsage(_)
+            | Self::Io(_)
+            | Self::Shader(_) => None,
+        }
+    }
+
+    /// The swapchain no longer matches the surface and has to be rebuilt.
+    pub fn is_out_of_date(&self) -> bool {
+        matches!(self.result(), Some(vk::Result::ERROR_OUT_OF_DATE_KHR))
+    }
+
+    /// The host or the device ran out of memory.
+    pub fn is_out_of_memory(&self) -> bool {
+        matches!(
+            self.result(),
+            Some(vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)
+        )
+    }
+
+    /// The logical device was lost and has to be recreated.
+    pub fn is_device_lost(&self) -> bool {
+        matches!(self.result(), Some(vk::Result::ERROR_DEVICE_LOST))
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Loading(msg) => write!(f, "failed to load vulkan: {}", msg),
+            Self::Unsupported(msg)
+            | Self::InvalidUsage(msg)
+            | Self::Io(msg)
+            | Self::Shader(msg) => {
+                write!(f, "{}", msg)
+            }
+            Self::Instance { context, result }
+            | Self::Surface { context, result }
+            | Self::Device { context, result }
+            | Self::Swapchain { context, result }
+            | Self::Pipeline { context, result }
+            | Self::Memory { context, result }
+            | Self::Command { context, result }
+            | Self::Sync { context, result } => write!(f, "{} ({})", context, result),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Instance { result, .. }
+            | Self::Surface { result, .. }
+            | Self::Device { result, .. }
+            | Self::Swapchain { result, .. }
+            | Self::Pipeline { result, .. }
+            | Self::Memory { result, .. }
+            | Self::Command { result, .. }
+            | Self::Sync { result, .. } => Some(result),
+            Self::Loading(_)
+            | Self::Unsupported(_)
+            | Self::InvalidUsage(_)
+            | Self::Io(_)
+            | Self::Shader(_) => None,
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use ash::vk;
+
+/// Access flags that write memory, a use with any of them orders later uses after it.
+const WRITE_ACCESS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw(),
+);
+
+/// How a pass uses an image.
+#[derive(Clone, Copy)]
+pub enum ImageAccess {
+    /// Rendered to. Cleared when the pass starts if there is a clear value, loaded otherwise.
+    ColorAttachment(Option<vk::ClearColorValue>),
+    /// Receives the resolve of the color attachment declared at the same position.
+    ResolveAttachment,
+    /// Depth tested and written. Cleared when the pass starts if there is a clear value,
+    /// loaded otherwise.
+    DepthAttachment(Option<vk::ClearDepthStencilValue>),
+    /// Depth tested without writing.
+    DepthReadOnly,
+    /// Read through a sampler by shaders in the given stages.
+    Sampled(vk::PipelineStageFlags),
+    /// Read and written as a storage image by shaders in the given stages.
+    Storage(vk::PipelineStageFlags),
+    TransferSrc,
+    TransferDst,
+}
+
+impl ImageAccess {
+    pub(super) fn state(self) -> AccessState {
+        let (stages, access, layout) = match self {
+            Self::ColorAttachment(_) => (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            Self::ResolveAttachment => (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            Self::DepthAttachment(_) => (
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+            Self::DepthReadOnly => (
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+            Self::Sampled(stages) => (
+                stages,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            Self::Storage(stages) => (
+                stages,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                vk::ImageLayout::GENERAL,
+            ),
+            Self::TransferSrc => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            Self::TransferDst => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+        };
+
+        AccessState {
+            stages,
+            access,
+            layout,
+        }
+    }
+
+    /// The usage flags an image needs for this access.
+    pub(super) fn usage(self) -> vk::ImageUsageFlags {
+        match self {
+            Self::ColorAttachment(_) | Self::ResolveAttachment => {
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+            }
+            Self::DepthAttachment(_) | Self::DepthReadOnly => {
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            }
+            Self::Sampled(_) => vk::ImageUsageFlags::SAMPLED,
+            Self::Storage(_) => vk::ImageUsageFlags::STORAGE,
+            Self::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            Self::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+/// How a pass uses a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAccess {
+    /// Read as a uniform buffer by shaders in the given stages.
+    Uniform(vk::PipelineStageFlags),
+    /// Read as a storage buffer by shaders in the given stages.
+    StorageRead(vk::PipelineStageFlags),
+    /// Read and written as a storage buffer by shaders in the given stages.
+    StorageWrite(vk::PipelineStageFlags),
+    Vertex,
+    Index,
+    Indirect,
+    TransferSrc,
+    TransferDst,
+}
+
+impl BufferAccess {
+    pub(super) fn state(self) -> AccessState {
+        let (stages, access) = match self {
+            Self::Uniform(stages) => (stages, vk::AccessFlags::UNIFORM_READ),
+            Self::StorageRead(stages) => (stages, vk::AccessFlags::SHADER_READ),
+            Self::StorageWrite(stages) => (
+                stages,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            ),
+            Self::Vertex => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            ),
+            Self::Index => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::INDEX_READ,
+            ),
+            Self::Indirect => (
+                vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::AccessFlags::INDIRECT_COMMAND_READ,
+            ),
+            Self::TransferSrc => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            Self::TransferDst => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+        };
+
+        AccessState {
+            stages,
+            access,
+            layout: vk::ImageLayout::UNDEFINED,
+        }
+    }
+}
+
+/// The stages, accesses and layout of a use of a resource, barriers go from one to the next.
+/// Buffers always have an `UNDEFINED` layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct AccessState {
+    pub stages: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+    pub layout: vk::ImageLayout,
+}
+
+impl AccessState {
+    #[inline]
+    pub fn writes(&self) -> bool {
+        self.access.intersects(WRITE_ACCESS)
+    }
+
+    /// The writes of this state, the only accesses a barrier has to make available.
+    #[inline]
+    pub fn write_access(&self) -> vk::AccessFlags {
+        self.access & WRITE_ACCESS
+    }
+}
+
+/// What the next use of a resource has to wait for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ResourceState {
+    /// The last write or layout transition, every later read waits for it.
+    pub write: AccessState,
+    /// The stages and accesses that already wait for `write`, in the current layout.
+    pub reads: AccessState,
+}
+
+impl ResourceState {
+    /// A resource last used with `state`, a write hasn't been waited for by anything yet.
+    pub fn after(state: AccessState) -> Self {
+        let reads = if state.writes() {
+            AccessState {
+                stages: vk::PipelineStageFlags::empty(),
+                access: vk::AccessFlags::empty(),
+                layout: state.layout,
+            }
+        } else {
+            state
+        };
+
+        Self {
+            write: state,
+            reads,
+        }
+    }
+
+    /// The source of a barrier after this state. The stages of the reads are included so a
+    /// barrier after a layout transition also waits for the transition.
+    #[inline]
+    pub fn barrier_src(&self) -> AccessState {
+        AccessState {
+            stages: self.write.stages | self.reads.stages,
+            access: self.write.access,
+            layout: self.reads.layout,
+        }
+    }
+}
@@ -0,0 +1,1532 @@
+use std::collections::BTreeSet;
+
+use ash::vk;
+
+use super::{
+    base::{dynamic_rendering::DynamicRendering, RenderBase},
+    error::RendererError,
+    resources::image::{self, Image, ImageDesc},
+};
+
+use self::access::{AccessState, ResourceState};
+
+mod access;
+
+pub use self::access::{BufferAccess, ImageAccess};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+/// Size of a graph image. Sizes relative to the surface follow it on resize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageSize {
+    Surface,
+    /// The surface size times a factor, at least one pixel.
+    SurfaceScaled(f32),
+    Fixed(vk::Extent2D),
+}
+
+impl ImageSize {
+    fn extent(self, surface_extent: vk::Extent2D) -> vk::Extent2D {
+        match self {
+            Self::Surface => surface_extent,
+            Self::SurfaceScaled(scale) => vk::Extent2D {
+                width: ((surface_extent.width as f32 * scale) as u32).max(1),
+                height: ((surface_extent.height as f32 * scale) as u32).max(1),
+            },
+            Self::Fixed(extent) => extent,
+        }
+    }
+}
+
+/// An image created and owned by the graph, its contents don't outlive the frame.
+#[derive(Debug, Clone, Copy)]
+pub struct TransientImage {
+    pub format: vk::Format,
+    pub size: ImageSize,
+    pub samples: vk::SampleCountFlags,
+}
+
+/// An image owned outside the graph, like the frame images.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportedImage {
+    pub format: vk::Format,
+    pub size: ImageSize,
+    pub samples: vk::SampleCountFlags,
+    /// Layout the image is in when the frame starts, `UNDEFINED` discards the contents.
+    pub initial_layout: vk::ImageLayout,
+    /// Layout the graph leaves the image in at the end of the frame.
+    pub final_layout: vk::ImageLayout,
+}
+
+/// A pass and the resources it reads and writes.
+#[derive(Clone)]
+pub struct PassDesc {
+    pub name: String,
+    pub images: Vec<(ImageId, ImageAccess)>,
+    pub buffers: Vec<(BufferId, BufferAccess)>,
+}
+
+impl PassDesc {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            images: Vec::new(),
+            buffers: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn image(mut self, image: ImageId, access: ImageAccess) -> Self {
+        self.images.push((image, access));
+        self
+    }
+
+    #[inline]
+    pub fn buffer(mut self, buffer: BufferId, access: BufferAccess) -> Self {
+        self.buffers.push((buffer, access));
+        self
+    }
+
+    #[inline]
+    pub fn color_attachment(self, image: ImageId, clear: Option<vk::ClearColorValue>) -> Self {
+        self.image(image, ImageAccess::ColorAttachment(clear))
+    }
+
+    /// Resolves the color attachment at the same position into `image`.
+    #[inline]
+    pub fn resolve_attachment(self, image: ImageId) -> Self {
+        self.image(image, ImageAccess::ResolveAttachment)
+    }
+
+    #[inline]
+    pub fn depth_attachment(
+        self,
+        image: ImageId,
+        clear: Option<vk::ClearDepthStencilValue>,
+    ) -> Self {
+        self.image(image, ImageAccess::DepthAttachment(clear))
+    }
+
+    #[inline]
+    pub fn sampled(self, image: ImageId, stages: vk::PipelineStageFlags) -> Self {
+        self.image(image, ImageAccess::Sampled(stages))
+    }
+}
+
+struct GraphImage {
+    name: String,
+    format: vk::Format,
+    size: ImageSize,
+    samples: vk::SampleCountFlags,
+    /// Initial and final layout of imported images.
+    imported: Option<(vk::ImageLayout, vk::ImageLayout)>,
+    /// Image and view for every frame image index, for imported images.
+    handles: Vec<(vk::Image, vk::ImageView)>,
+    /// Union of the usages of all passes, set by `compile`.
+    usage: vk::ImageUsageFlags,
+    /// Created for transient images that are used by a pass.
+    transient: Option<Image>,
+}
+
+impl GraphImage {
+    #[inline]
+    fn handle(&self, image_index: usize) -> (vk::Image, vk::ImageView) {
+        match &self.transient {
+            Some(image) => (image.image, image.view),
+            None => self.handles[image_index % self.handles.len()],
+        }
+    }
+}
+
+struct GraphBuffer {
+    name: String,
+    buffer: vk::Buffer,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Barrier {
+    resource: usize,
+    src: AccessState,
+    dst: AccessState,
+}
+
+#[derive(Clone, Copy)]
+struct Attachment {
+    image: usize,
+    layout: vk::ImageLayout,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    clear_value: vk::ClearValue,
+}
+
+/// A pass with everything needed to record it.
+struct CompiledPass {
+    pass: usize,
+    image_barriers: Vec<Barrier>,
+    buffer_barriers: Vec<Barrier>,
+    colors: Vec<Attachment>,
+    depth: Option<Attachment>,
+    /// The n-th resolve attachment receives the n-th color attachment.
+    resolves: Vec<Attachment>,
+    /// Null with dynamic rendering and for passes without attachments.
+    render_pass: vk::RenderPass,
+    /// One per frame image index, recreated on resize.
+    framebuffers: Vec<vk::Framebuffer>,
+    extent: vk::Extent2D,
+}
+
+impl CompiledPass {
+    /// The attachments in render pass order: colors, depth, then resolves.
+    #[inline]
+    fn attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.colors.iter().chain(&self.depth).chain(&self.resolves)
+    }
+
+    #[inline]
+    fn has_attachments(&self) -> bool {
+        !self.colors.is_empty() || self.depth.is_some()
+    }
+}
+
+/// Orders passes by the images and buffers they use and records them with the barriers and
+/// layout transitions between them.
+///
+/// A pass that reads a resource runs after every pass that writes it, passes writing the
+/// same resource run in the order they were added. Passes with attachments render with
+/// dynamic rendering when the device has it, otherwise they get a render pass and
+/// framebuffers. Transient images are created by the graph and recreated with the
+/// framebuffers on [`RenderGraph::resize`].
+///
+/// Add the resources and passes, then [`RenderGraph::compile`] once and
+/// [`RenderGraph::execute`] every frame.
+#[derive(Default)]
+pub struct RenderGraph {
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<PassDesc>,
+    /// In execution order.
+    compiled: Vec<CompiledPass>,
+    /// Transitions of the imported images to their final layouts after the last pass.
+    final_barriers: Vec<Barrier>,
+    /// Taken from the base by `compile`, render passes are used without it.
+    dynamic_rendering: Option<DynamicRendering>,
+    /// Taken from the base by `compile`, nothing is created before.
+    device: Option<ash::Device>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_image(&mut self, name: impl Into<String>, desc: TransientImage) -> ImageId {
+        self.images.push(GraphImage {
+            name: name.into(),
+            format: desc.format,
+            size: desc.size,
+            samples: desc.samples,
+            imported: None,
+            handles: Vec::new(),
+            usage: vk::ImageUsageFlags::empty(),
+            transient: None,
+        });
+
+        ImageId(self.images.len() - 1)
+    }
+
+    /// Adds an image owned elsewhere, `handles` has an image and view for every frame image
+    /// index. One handle is used for every frame.
+    pub fn import_image(
+        &mut self,
+        name: impl Into<String>,
+        desc: ImportedImage,
+        handles: Vec<(vk::Image, vk::ImageView)>,
+    ) -> ImageId {
+        self.images.push(GraphImage {
+            name: name.into(),
+            format: desc.format,
+            size: desc.size,
+            samples: desc.samples,
+            imported: Some((desc.initial_layout, desc.final_layout)),
+            handles,
+            usage: vk::ImageUsageFlags::empty(),
+            transient: None,
+        });
+
+        ImageId(self.images.len() - 1)
+    }
+
+    /// Replaces the handles of an imported image, call [`RenderGraph::resize`] afterwards
+    /// to recreate the framebuffers.
+    pub fn set_imported_images(
+        &mut self,
+        image: ImageId,
+        handles: Vec<(vk::Image, vk::ImageView)>,
+    ) {
+        self.images[image.0].handles = handles;
+    }
+
+    pub fn import_buffer(&mut self, name: impl Into<String>, buffer: vk::Buffer) -> BufferId {
+        self.buffers.push(GraphBuffer {
+            name: name.into(),
+            buffer,
+        });
+
+        BufferId(self.buffers.len() - 1)
+    }
+
+    /// Replaces an imported buffer, takes effect with the next [`RenderGraph::execute`].
+    #[inline]
+    pub fn set_imported_buffer(&mut self, buffer: BufferId, handle: vk::Buffer) {
+        self.buffers[buffer.0].buffer = handle;
+    }
+
+    pub fn add_pass(&mut self, desc: PassDesc) -> PassId {
+        self.passes.push(desc);
+        PassId(self.passes.len() - 1)
+    }
+
+    /// The render pass of a pass with attachments, pipelines drawing in the pass are created
+    /// for it. Null before [`RenderGraph::compile`], for passes without attachments and with
+    /// dynamic rendering, where pipelines are created with [`RenderGraph::attachment_formats`].
+    pub fn render_pass(&self, pass: PassId) -> vk::RenderPass {
+        self.compiled
+            .iter()
+            .find(|compiled| compiled.pass == pass.0)
+            .map_or(vk::RenderPass::null(), |compiled| compiled.render_pass)
+    }
+
+    /// The formats of the color attachments and of the depth attachment of a pass.
+    pub fn attachment_formats(&self, pass: PassId) -> (Vec<vk::Format>, Option<vk::Format>) {
+        let images = &self.passes[pass.0].images;
+
+        let colors = images
+            .iter()
+            .filter(|(_, access)| matches!(access, ImageAccess::ColorAttachment(_)))
+            .map(|(image, _)| self.images[image.0].format)
+            .collect();
+        let depth = images
+            .iter()
+            .find(|(_, access)| {
+                matches!(
+                    access,
+                    ImageAccess::DepthAttachment(_) | ImageAccess::DepthReadOnly
+                )
+            })
+            .map(|(image, _)| self.images[image.0].format);
+
+        (colors, depth)
+    }
+
+    /// Orders the passes, works out the barriers and creates the render passes, transient
+    /// images and framebuffers. Can be called again after adding passes.
+    pub fn compile(&mut self, base: &RenderBase) -> Result<(), RendererError> {
+        self.destroy_compiled();
+        self.validate()?;
+        self.dynamic_rendering = base.dynamic_rendering.clone();
+        self.device = Some(base.device.clone());
+
+        let order = self.order()?;
+        log::debug!(
+            "render graph pass order: {:?}",
+            order
+                .iter()
+                .map(|&pass| self.passes[pass].name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        for image in &mut self.images {
+            image.usage = vk::ImageUsageFlags::empty();
+        }
+        for pass in &self.passes {
+            for &(image, access) in &pass.images {
+                self.images[image.0].usage |= access.usage();
+            }
+        }
+
+        // transient resources start each frame where the previous frame left them, imported
+        // images in their initial layout after whatever used them before the frame
+        let mut image_states = (0..self.images.len())
+            .map(|image| match self.images[image].imported {
+                Some((initial_layout, _)) => Some(ResourceState::after(AccessState {
+                    stages: vk::PipelineStageFlags::ALL_COMMANDS,
+                    access: vk::AccessFlags::empty(),
+                    layout: initial_layout,
+                })),
+                None => self.last_image_use(&order, image).map(|state| {
+                    ResourceState::after(AccessState {
+                        layout: vk::ImageLayout::UNDEFINED,
+                        ..state
+                    })
+                }),
+            })
+            .collect::<Vec<_>>();
+        let mut buffer_states = (0..self.buffers.len())
+            .map(|buffer| {
+                self.last_buffer_use(&order, buffer)
+                    .map(ResourceState::after)
+            })
+            .collect::<Vec<_>>();
+
+        // whether an image holds data a pass can load
+        let mut defined = self
+            .images
+            .iter()
+            .map(|image| {
+                matches!(image.imported, Some((layout, _)) if layout != vk::ImageLayout::UNDEFINED)
+            })
+            .collect::<Vec<_>>();
+
+        for (position, &pass) in order.iter().enumerate() {
+            let desc = &self.passes[pass];
+
+            let mut image_barriers = Vec::new();
+            for &(image, access) in &desc.images {
+                let state = image_states[image.0].as_mut().unwrap();
+                if let Some((src, dst)) = transition(state, access.state()) {
+                    image_barriers.push(Barrier {
+                        resource: image.0,
+                        src,
+                        dst,
+                    });
+                }
+            }
+
+            let mut buffer_barriers = Vec::new();
+            for &(buffer, access) in &desc.buffers {
+                let state = buffer_states[buffer.0].as_mut().unwrap();
+                if let Some((src, dst)) = transition(state, access.state()) {
+                    buffer_barriers.push(Barrier {
+                        resource: buffer.0,
+                        src,
+                        dst,
+                    });
+                }
+            }
+
+            // contents are stored if a later pass uses them or they outlive the frame
+            let keep = |image: usize| {
+                self.images[image].imported.is_some()
+                    || order[position + 1..]
+                        .iter()
+                        .any(|&later| self.passes[later].images.iter().any(|&(i, _)| i.0 == image))
+            };
+
+            let mut compiled = self.pass_attachments(desc, &defined, keep);
+            compiled.pass = pass;
+            compiled.image_barriers = image_barriers;
+            compiled.buffer_barriers = buffer_barriers;
+            if compiled.has_attachments() && self.dynamic_rendering.is_none() {
+                compiled.render_pass = self.create_render_pass(&desc.name, &compiled)?;
+            }
+            self.compiled.push(compiled);
+
+            for &(image, access) in &desc.images {
+                if access.state().writes() {
+                    defined[image.0] = true;
+                }
+            }
+        }
+
+        for (index, image) in self.images.iter().enumerate() {
+            let Some((_, final_layout)) = image.imported else {
+                continue;
+            };
+            let state = image_states[index].as_ref().unwrap();
+            let done = AccessState {
+                stages: vk::PipelineStageFlags::ALL_COMMANDS,
+                access: vk::AccessFlags::empty(),
+                layout: final_layout,
+            };
+            // no reads means nothing waited for the last write yet
+            if state.reads.layout != final_layout || state.reads.stages.is_empty() {
+                self.final_barriers.push(Barrier {
+                    resource: index,
+                    src: state.barrier_src(),
+                    dst: done,
+                });
+            }
+        }
+
+        self.create_sized_resources(base)
+    }
+
+    /// Recreates the transient images and framebuffers for the new surface extent and
+    /// imported images.
+    pub fn resize(&mut self, base: &RenderBase) -> Result<(), RendererError> {
+        self.destroy_sized_resources();
+        self.create_sized_resources(base)
+    }
+
+    /// Records the passes in order, calling `record` after the barriers of each pass and
+    /// inside its render pass or dynamic rendering if it has attachments. `image_index` picks
+    /// the handles of imported images and the framebuffers.
+    pub fn execute(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        mut record: impl FnMut(PassId) -> Result<(), RendererError>,
+    ) -> Result<(), RendererError> {
+        for compiled in &self.compiled {
+            self.cmd_barriers(
+                command_buffer,
+                image_index,
+                &compiled.image_barriers,
+                &compiled.buffer_barriers,
+            );
+
+            self.cmd_begin_pass(command_buffer, image_index, compiled);
+            let result = record(PassId(compiled.pass));
+            self.cmd_end_pass(command_buffer, compiled);
+            result?;
+        }
+
+        self.cmd_barriers(command_buffer, image_index, &self.final_barriers, &[]);
+
+        Ok(())
+    }
+
+    pub fn clean_up(&mut self) {
+        self.destroy_compiled();
+    }
+
+    /// The device of the base the graph was compiled for.
+    #[inline]
+    fn device(&self) -> &ash::Device {
+        self.device
+            .as_ref()
+            .expect("render graph used before compile")
+    }
+
+    fn validate(&self) -> Result<(), RendererError> {
+        for pass in &self.passes {
+            let invalid = |reason: String| {
+                RendererError::InvalidUsage(format!("render graph pass {}: {}", pass.name, reason))
+            };
+
+            for (index, &(image, _)) in pass.images.iter().enumerate() {
+                if image.0 >= self.images.len() {
+                    return Err(invalid(format!("unknown image {:?}", image)));
+                }
+                if pass.images[..index]
+                    .iter()
+                    .any(|&(other, _)| other == image)
+                {
+                    return Err(invalid(format!(
+                        "uses {} more than once",
+                        self.images[image.0].name
+                    )));
+                }
+            }
+            for (index, &(buffer, _)) in pass.buffers.iter().enumerate() {
+                if buffer.0 >= self.buffers.len() {
+                    return Err(invalid(format!("unknown buffer {:?}", buffer)));
+                }
+                if pass.buffers[..index]
+                    .iter()
+                    .any(|&(other, _)| other == buffer)
+                {
+                    return Err(invalid(format!(
+                        "uses {} more than once",
+                        self.buffers[buffer.0].name
+                    )));
+                }
+            }
+
+            let count = |matches: fn(&ImageAccess) -> bool| {
+                pass.images
+                    .iter()
+                    .filter(|(_, access)| matches(access))
+                    .count()
+            };
+            let colors = count(|access| matches!(access, ImageAccess::ColorAttachment(_)));
+            let resolves = count(|access| matches!(access, ImageAccess::ResolveAttachment));
+            let depths = count(|access| {
+                matches!(
+                    access,
+                    ImageAccess::DepthAttachment(_) | ImageAccess::DepthReadOnly
+                )
+            });
+
+            if depths > 1 {
+                return Err(invalid(String::from("has more than one depth attachment")));
+            }
+            if resolves > colors {
+                return Err(invalid(String::from(
+                    "has more resolve than color attachments",
+                )));
+            }
+
+            let mut samples = pass
+                .images
+                .iter()
+                .filter(|(_, access)| {
+                    matches!(
+                        access,
+                        ImageAccess::ColorAttachment(_)
+                            | ImageAccess::DepthAttachment(_)
+                            | ImageAccess::DepthReadOnly
+                    )
+                })
+                .map(|(image, _)| self.images[image.0].samples);
+            if let Some(first) = samples.next() {
+                if samples.any(|samples| samples != first) {
+                    return Err(invalid(String::from(
+                        "has attachments with different sample counts",
+                    )));
+                }
+                if resolves > 0 && first == vk::SampleCountFlags::TYPE_1 {
+                    return Err(invalid(String::from(
+                        "resolves attachments that aren't multisampled",
+                    )));
+                }
+            }
+            for &(image, access) in &pass.images {
+                if matches!(access, ImageAccess::ResolveAttachment)
+                    && self.images[image.0].samples != vk::SampleCountFlags::TYPE_1
+                {
+                    return Err(invalid(format!(
+                        "resolves into multisampled {}",
+                        self.images[image.0].name
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sorts the passes so every pass runs after the passes it depends on, keeping the order
+    /// they were added in where there is no dependency.
+    fn order(&self) -> Result<Vec<usize>, RendererError> {
+        let pass_count = self.passes.len();
+        let mut dependents = vec![Vec::<usize>::new(); pass_count];
+        let mut dependency_count = vec![0; pass_count];
+
+        // (pass, writes) of every resource, in the order the passes were added
+        let mut users = vec![Vec::<(usize, bool)>::new(); self.images.len() + self.buffers.len()];
+        for (pass, desc) in self.passes.iter().enumerate() {
+            for &(image, access) in &desc.images {
+                users[image.0].push((pass, access.state().writes()));
+            }
+            for &(buffer, access) in &desc.buffers {
+                users[self.images.len() + buffer.0].push((pass, access.state().writes()));
+            }
+        }
+
+        for resource_users in &users {
+            let writers = resource_users
+                .iter()
+                .filter(|&&(_, writes)| writes)
+                .map(|&(pass, _)| pass)
+                .collect::<Vec<_>>();
+
+            let mut edges = writers
+                .windows(2)
+                .map(|pair| (pair[0], pair[1]))
+                .collect::<Vec<_>>();
+            for &(reader, writes) in resource_users {
+                if !writes {
+                    edges.extend(writers.iter().map(|&writer| (writer, reader)));
+                }
+            }
+
+            for (from, to) in edges {
+                if from != to && !dependents[from].contains(&to) {
+                    dependents[from].push(to);
+                    dependency_count[to] += 1;
+                }
+            }
+        }
+
+        let mut ready = (0..pass_count)
+            .filter(|&pass| dependency_count[pass] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(pass_count);
+
+        while let Some(pass) = ready.pop_first() {
+            order.push(pass);
+            for &dependent in &dependents[pass] {
+                dependency_count[dependent] -= 1;
+                if dependency_count[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+
+        if order.len() < pass_count {
+            let cycle = (0..pass_count)
+                .filter(|pass| !order.contains(pass))
+                .map(|pass| self.passes[pass].name.as_str())
+                .collect::<Vec<_>>();
+            return Err(RendererError::InvalidUsage(format!(
+                "the render graph passes {:?} depend on each other",
+                cycle
+            )));
+        }
+
+        Ok(order)
+    }
+
+    fn last_image_use(&self, order: &[usize], image: usize) -> Option<AccessState> {
+        order.iter().rev().find_map(|&pass| {
+            self.passes[pass]
+                .images
+                .iter()
+                .find(|&&(i, _)| i.0 == image)
+                .map(|&(_, access)| access.state())
+        })
+    }
+
+    fn last_buffer_use(&self, order: &[usize], buffer: usize) -> Option<AccessState> {
+        order.iter().rev().find_map(|&pass| {
+            self.passes[pass]
+                .buffers
+                .iter()
+                .find(|&&(b, _)| b.0 == buffer)
+                .map(|&(_, access)| access.state())
+        })
+    }
+
+    /// Works out the attachments of `desc` with their load and store ops, in the layouts the
+    /// barriers before the pass leave them in. Returns a pass with only its attachments set.
+    fn pass_attachments(
+        &self,
+        desc: &PassDesc,
+        defined: &[bool],
+        keep: impl Fn(usize) -> bool,
+    ) -> CompiledPass {
+        let attachment = |image: usize, access: ImageAccess, clear: Option<vk::ClearValue>| {
+            let load_op = if clear.is_some() {
+                vk::AttachmentLoadOp::CLEAR
+            } else if defined[image] {
+                vk::AttachmentLoadOp::LOAD
+            } else {
+                vk::AttachmentLoadOp::DONT_CARE
+            };
+            let store_op = if keep(image) {
+                vk::AttachmentStoreOp::STORE
+            } else {
+                vk::AttachmentStoreOp::DONT_CARE
+            };
+
+            Attachment {
+                image,
+                layout: access.state().layout,
+                load_op,
+                store_op,
+                clear_value: clear.unwrap_or_default(),
+            }
+        };
+
+        let mut compiled = CompiledPass {
+            pass: 0,
+            image_barriers: Vec::new(),
+            buffer_barriers: Vec::new(),
+            colors: Vec::new(),
+            depth: None,
+            resolves: Vec::new(),
+            render_pass: vk::RenderPass::null(),
+            framebuffers: Vec::new(),
+            extent: vk::Extent2D::default(),
+        };
+
+        for &(image, access) in &desc.images {
+            match access {
+                ImageAccess::ColorAttachment(clear) => compiled.colors.push(attachment(
+                    image.0,
+                    access,
+                    clear.map(|color| vk::ClearValue { color }),
+                )),
+                ImageAccess::DepthAttachment(clear) => {
+                    compiled.depth = Some(attachment(
+                        image.0,
+                        access,
+                        clear.map(|depth_stencil| vk::ClearValue { depth_stencil }),
+                    ))
+                }
+                ImageAccess::DepthReadOnly => {
+                    compiled.depth = Some(attachment(image.0, access, None))
+                }
+                // resolving overwrites the whole image
+                ImageAccess::ResolveAttachment => compiled.resolves.push(Attachment {
+                    load_op: vk::AttachmentLoadOp::DONT_CARE,
+                    store_op: vk::AttachmentStoreOp::STORE,
+                    ..attachment(image.0, access, None)
+                }),
+                _ => {}
+            }
+        }
+
+        compiled
+    }
+
+    /// Creates a render pass with a single subpass using the attachments of `compiled`.
+    fn create_render_pass(
+        &self,
+        name: &str,
+        compiled: &CompiledPass,
+    ) -> Result<vk::RenderPass, RendererError> {
+        let descriptions = compiled
+            .attachments()
+            .map(|attachment| {
+                let image = &self.images[attachment.image];
+                let (stencil_load_op, stencil_store_op) = if image::has_stencil(image.format) {
+                    (attachment.load_op, attachment.store_op)
+                } else {
+                    (
+                        vk::AttachmentLoadOp::DONT_CARE,
+                        vk::AttachmentStoreOp::DONT_CARE,
+                    )
+                };
+
+                vk::AttachmentDescription::builder()
+                    .format(image.format)
+                    .samples(image.samples)
+                    .load_op(attachment.load_op)
+                    .store_op(attachment.store_op)
+                    .stencil_load_op(stencil_load_op)
+                    .stencil_store_op(stencil_store_op)
+                    .initial_layout(attachment.layout)
+                    .final_layout(attachment.layout)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let reference = |index: usize, attachment: &Attachment| vk::AttachmentReference {
+            attachment: index as u32,
+            layout: attachment.layout,
+        };
+        let color_count = compiled.colors.len();
+        let resolve_start = color_count + compiled.depth.iter().count();
+
+        let color_references = compiled
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(index, color)| reference(index, color))
+            .collect::<Vec<_>>();
+        let depth_reference = compiled
+            .depth
+            .as_ref()
+            .map(|depth| reference(color_count, depth));
+
+        // color attachments without a resolve attachment aren't resolved
+        let resolve_references = (0..color_count)
+            .map(|index| match compiled.resolves.get(index) {
+                Some(resolve) => reference(resolve_start + index, resolve),
+                None => vk::AttachmentReference {
+                    attachment: vk::ATTACHMENT_UNUSED,
+                    layout: vk::ImageLayout::UNDEFINED,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let mut subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_references);
+        if !compiled.resolves.is_empty() {
+            subpass = subpass.resolve_attachments(&resolve_references);
+        }
+        if let Some(depth_reference) = &depth_reference {
+            subpass = subpass.depth_stencil_attachment(depth_reference);
+        }
+        let subpasses = [subpass.build()];
+
+        // the barriers recorded before the pass synchronize the attachments
+        let create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&descriptions)
+            .subpasses(&subpasses);
+
+        let render_pass = unsafe {
+            self.device()
+                .create_render_pass(&create_info, None)
+                .map_err(RendererError::pipeline(format!(
+                    "failed to create render pass for {}",
+                    name
+                )))?
+        };
+
+        Ok(render_pass)
+    }
+
+    /// Creates the transient images and the framebuffers, which depend on the surface extent.
+    fn create_sized_resources(&mut self, base: &RenderBase) -> Result<(), RendererError> {
+        for (index, image) in self.images.iter_mut().enumerate() {
+            if image.imported.is_some() {
+                if image.handles.is_empty() {
+                    return Err(RendererError::InvalidUsage(format!(
+                        "imported image {} has no handles",
+                        image.name
+                    )));
+                }
+                continue;
+            }
+            if image.usage.is_empty() {
+                continue;
+            }
+
+            // attachments used by a single pass never leave tile memory on some gpus
+            let attachment_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+            let users = self
+                .passes
+                .iter()
+                .filter(|pass| pass.images.iter().any(|&(i, _)| i.0 == index))
+                .count();
+            let usage = if users == 1 && attachment_usage.contains(image.usage) {
+                image.usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+            } else {
+                image.usage
+            };
+
+            image.transient = Some(Image::new(
+                &base.device,
+                &base.allocator,
+                &ImageDesc {
+                    name: &image.name,
+                    extent: image.size.extent(base.surface_extent),
+                    format: image.format,
+                    usage,
+                    aspect: image::format_aspect(image.format),
+                    samples: image.samples,
+                },
+                &[base.queue_family],
+            )?);
+        }
+
+        for compiled in &mut self.compiled {
+            if !compiled.has_attachments() {
+                continue;
+            }
+
+            let images = compiled
+                .attachments()
+                .map(|attachment| &self.images[attachment.image])
+                .collect::<Vec<_>>();
+
+            let extent = images[0].size.extent(base.surface_extent);
+            if let Some(image) = images
+                .iter()
+                .find(|image| image.size.extent(base.surface_extent) != extent)
+            {
+                return Err(RendererError::InvalidUsage(format!(
+                    "render graph pass {}: attachment {} isn't the size of {}",
+                    self.passes[compiled.pass].name, image.name, images[0].name
+                )));
+            }
+            compiled.extent = extent;
+
+            if compiled.render_pass == vk::RenderPass::null() {
+                continue;
+            }
+
+            let framebuffer_count = images
+                .iter()
+                .filter(|image| image.transient.is_none())
+                .map(|image| image.handles.len())
+                .max()
+                .unwrap_or(1);
+
+            for image_index in 0..framebuffer_count {
+                let views = images
+                    .iter()
+                    .map(|image| image.handle(image_index).1)
+                    .collect::<Vec<_>>();
+
+                let create_info = vk::FramebufferCreateInfo::builder()
+                    .render_pass(compiled.render_pass)
+                    .attachments(&views)
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1);
+
+                let framebuffer = unsafe {
+                    base.device.create_framebuffer(&create_info, None).map_err(
+                        RendererError::pipeline(format!(
+                            "failed to create framebuffer {} for {}",
+                            image_index, self.passes[compiled.pass].name
+                        )),
+                    )?
+                };
+                compiled.framebuffers.push(framebuffer);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroy_sized_resources(&mut self) {
+        if let Some(device) = &self.device {
+            for compiled in &mut self.compiled {
+                for framebuffer in compiled.framebuffers.drain(..) {
+                    unsafe { device.destroy_framebuffer(framebuffer, None) };
+                }
+            }
+        }
+
+        for image in &mut self.images {
+            image.transient = None;
+        }
+    }
+
+    fn destroy_compiled(&mut self) {
+        self.destroy_sized_resources();
+
+        if let Some(device) = &self.device {
+            for compiled in self.compiled.drain(..) {
+                if compiled.render_pass != vk::RenderPass::null() {
+                    unsafe { device.destroy_render_pass(compiled.render_pass, None) };
+                }
+            }
+        }
+        self.final_barriers.clear();
+    }
+
+    /// Begins the render pass or dynamic rendering of a pass with attachments.
+    fn cmd_begin_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        compiled: &CompiledPass,
+    ) {
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: compiled.extent,
+        };
+
+        if compiled.render_pass != vk::RenderPass::null() {
+            let clear_values = compiled
+                .attachments()
+                .map(|attachment| attachment.clear_value)
+                .collect::<Vec<_>>();
+            let begin_info = vk::RenderPassBeginInfo::builder()
+                .render_pass(compiled.render_pass)
+                .framebuffer(compiled.framebuffers[image_index % compiled.framebuffers.len()])
+                .render_area(render_area)
+                .clear_values(&clear_values);
+
+            unsafe {
+                self.device().cmd_begin_render_pass(
+                    command_buffer,
+                    &begin_info,
+                    vk::SubpassContents::INLINE,
+                );
+            }
+            return;
+        }
+
+        let Some(dynamic_rendering) = &self.dynamic_rendering else {
+            return;
+        };
+        if !compiled.has_attachments() {
+            return;
+        }
+
+        let attachment_info = |attachment: &Attachment| {
+            vk::RenderingAttachmentInfo::builder()
+                .image_view(self.images[attachment.image].handle(image_index).1)
+                .image_layout(attachment.layout)
+                .load_op(attachment.load_op)
+                .store_op(attachment.store_op)
+                .clear_value(attachment.clear_value)
+        };
+
+        let color_infos = compiled
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(index, color)| match compiled.resolves.get(index) {
+                Some(resolve) => attachment_info(color)
+                    .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                    .resolve_image_view(self.images[resolve.image].handle(image_index).1)
+                    .resolve_image_layout(resolve.layout)
+                    .build(),
+                None => attachment_info(color).build(),
+            })
+            .collect::<Vec<_>>();
+        let depth_info = compiled
+            .depth
+            .as_ref()
+            .map(|depth| attachment_info(depth).build());
+
+        let mut rendering_info = vk::RenderingInfo::builder()
+            .render_area(render_area)
+            .layer_count(1)
+            .color_attachments(&color_infos);
+        if let (Some(depth), Some(depth_info)) = (&compiled.depth, &depth_info) {
+            rendering_info = rendering_info.depth_attachment(depth_info);
+            if image::has_stencil(self.images[depth.image].format) {
+                rendering_info = rendering_info.stencil_attachment(depth_info);
+            }
+        }
+
+        dynamic_rendering.cmd_begin_rendering(command_buffer, &rendering_info);
+    }
+
+    fn cmd_end_pass(&self, command_buffer: vk::CommandBuffer, compiled: &CompiledPass) {
+        if compiled.render_pass != vk::RenderPass::null() {
+            unsafe { self.device().cmd_end_render_pass(command_buffer) };
+        } else if let Some(dynamic_rendering) = &self.dynamic_rendering {
+            if compiled.has_attachments() {
+                dynamic_rendering.cmd_end_rendering(command_buffer);
+            }
+        }
+    }
+
+    fn cmd_barriers(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        image_barriers: &[Barrier],
+        buffer_barriers: &[Barrier],
+    ) {
+        if image_barriers.is_empty() && buffer_barriers.is_empty() {
+            return;
+        }
+
+        let mut src_stages = vk::PipelineStageFlags::empty();
+        let mut dst_stages = vk::PipelineStageFlags::empty();
+
+        let image_memory_barriers = image_barriers
+            .iter()
+            .map(|barrier| {
+                src_stages |= barrier.src.stages;
+                dst_stages |= barrier.dst.stages;
+
+                let image = &self.images[barrier.resource];
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(barrier.src.write_access())
+                    .dst_access_mask(barrier.dst.access)
+                    .old_layout(barrier.src.layout)
+                    .new_layout(barrier.dst.layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image.handle(image_index).0)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: image::format_aspect(image.format),
+                        base_mip_level: 0,
+                        level_count: vk::REMAINING_MIP_LEVELS,
+                        base_array_layer: 0,
+                        layer_count: vk::REMAINING_ARRAY_LAYERS,
+                    })
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let buffer_memory_barriers = buffer_barriers
+            .iter()
+            .map(|barrier| {
+                src_stages |= barrier.src.stages;
+                dst_stages |= barrier.dst.stages;
+
+                vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(barrier.src.write_access())
+                    .dst_access_mask(barrier.dst.access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(self.buffers[barrier.resource].buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.device().cmd_pipeline_barrier(
+                command_buffer,
+                src_stages,
+                dst_stages,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_memory_barriers,
+                &image_memory_barriers,
+            );
+        }
+    }
+}
+
+/// Moves `state` to `next`, returning the states a barrier has to go between. Writes and
+/// layout transitions wait for the last write and every read since, reads in the current
+/// layout wait for the last write unless an earlier barrier already covers their stages and
+/// accesses.
+fn transition(state: &mut ResourceState, next: AccessState) -> Option<(AccessState, AccessState)> {
+    let src = state.barrier_src();
+
+    if !next.writes() && next.layout == state.reads.layout {
+        if state.reads.stages.contains(next.stages) && state.reads.access.contains(next.access) {
+            return None;
+        }
+        state.reads.stages |= next.stages;
+        state.reads.access |= next.access;
+        return Some((src, next));
+    }
+
+    *state = if next.writes() {
+        ResourceState::after(next)
+    } else {
+        // later reads in the new layout wait for the transition through the stages of this read
+        ResourceState {
+            write: AccessState {
+                layout: next.layout,
+                ..state.write
+            },
+            reads: next,
+        }
+    };
+
+    Some((src, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_write() -> AccessState {
+        ImageAccess::ColorAttachment(None).state()
+    }
+
+    fn sampled(stages: vk::PipelineStageFlags) -> AccessState {
+        ImageAccess::Sampled(stages).state()
+    }
+
+    #[test]
+    fn read_after_write_waits_for_the_write() {
+        let mut state = ResourceState::after(color_write());
+
+        let (src, dst) = transition(&mut state, color_write()).unwrap();
+        assert_eq!(src, color_write());
+        assert_eq!(dst, color_write());
+
+        let read = sampled(vk::PipelineStageFlags::FRAGMENT_SHADER);
+        let (src, dst) = transition(&mut state, read).unwrap();
+        assert_eq!(src.stages, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(src.write_access(), vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(src.layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(dst, read);
+    }
+
+    #[test]
+    fn covered_read_needs_no_barrier() {
+        let mut state = ResourceState::after(color_write());
+        let read = sampled(vk::PipelineStageFlags::FRAGMENT_SHADER);
+
+        assert!(transition(&mut state, read).is_some());
+        assert!(transition(&mut state, read).is_none());
+    }
+
+    #[test]
+    fn read_in_another_stage_waits_for_the_earlier_write() {
+        let mut state = ResourceState::after(color_write());
+        transition(&mut state, sampled(vk::PipelineStageFlags::FRAGMENT_SHADER)).unwrap();
+
+        let vertex_read = sampled(vk::PipelineStageFlags::VERTEX_SHADER);
+        let (src, dst) = transition(&mut state, vertex_read).unwrap();
+
+        // the write and the read that did the layout transition
+        assert_eq!(
+            src.stages,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+        assert_eq!(src.write_access(), vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(src.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(dst, vertex_read);
+    }
+
+    #[test]
+    fn read_with_another_access_waits_for_the_earlier_write() {
+        let write = BufferAccess::StorageWrite(vk::PipelineStageFlags::COMPUTE_SHADER).state();
+        let mut state = ResourceState::after(write);
+        transition(&mut state, write).unwrap();
+        transition(
+            &mut state,
+            BufferAccess::StorageRead(vk::PipelineStageFlags::VERTEX_SHADER).state(),
+        )
+        .unwrap();
+
+        let (src, dst) = transition(
+            &mut state,
+            BufferAccess::Uniform(vk::PipelineStageFlags::VERTEX_SHADER).state(),
+        )
+        .unwrap();
+        assert_eq!(src.write_access(), vk::AccessFlags::SHADER_WRITE);
+        assert!(src.stages.contains(vk::PipelineStageFlags::COMPUTE_SHADER));
+        assert_eq!(dst.access, vk::AccessFlags::UNIFORM_READ);
+    }
+
+    #[test]
+    fn write_after_reads_waits_for_every_read() {
+        let mut state = ResourceState::after(color_write());
+        transition(&mut state, sampled(vk::PipelineStageFlags::FRAGMENT_SHADER)).unwrap();
+        transition(&mut state, sampled(vk::PipelineStageFlags::VERTEX_SHADER)).unwrap();
+
+        let (src, dst) = transition(&mut state, color_write()).unwrap();
+        assert!(src.stages.contains(
+            vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER
+        ));
+        assert_eq!(src.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(dst, color_write());
+        assert_eq!(state, ResourceState::after(color_write()));
+    }
+
+    #[test]
+    fn reads_after_reads_in_the_same_layout_need_no_barrier() {
+        let read = BufferAccess::Vertex.state();
+        let mut state = ResourceState::after(read);
+
+        assert!(transition(&mut state, read).is_none());
+    }
+
+    fn transient_image(
+        graph: &mut RenderGraph,
+        name: &str,
+        samples: vk::SampleCountFlags,
+    ) -> ImageId {
+        graph.create_image(
+            name,
+            TransientImage {
+                format: vk::Format::R8G8B8A8_UNORM,
+                size: ImageSize::Surface,
+                samples,
+            },
+        )
+    }
+
+    fn pass_names(graph: &RenderGraph) -> Vec<&str> {
+        graph
+            .order()
+            .unwrap()
+            .into_iter()
+            .map(|pass| graph.passes[pass].name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn image_size_extent() {
+        let surface = vk::Extent2D {
+            width: 800,
+            height: 600,
+        };
+        let fixed = vk::Extent2D {
+            width: 64,
+            height: 32,
+        };
+
+        assert_eq!(ImageSize::Surface.extent(surface), surface);
+        assert_eq!(
+            ImageSize::SurfaceScaled(0.5).extent(surface),
+            vk::Extent2D {
+                width: 400,
+                height: 300,
+            }
+        );
+        assert_eq!(
+            ImageSize::SurfaceScaled(0.0).extent(surface),
+            vk::Extent2D {
+                width: 1,
+                height: 1,
+            }
+        );
+        assert_eq!(ImageSize::Fixed(fixed).extent(surface), fixed);
+    }
+
+    #[test]
+    fn image_access_states() {
+        let stages = vk::PipelineStageFlags::COMPUTE_SHADER;
+        let expected = [
+            (
+                ImageAccess::DepthReadOnly,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                false,
+            ),
+            (
+                ImageAccess::Sampled(stages),
+                vk::ImageUsageFlags::SAMPLED,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                false,
+            ),
+            (
+                ImageAccess::Storage(stages),
+                vk::ImageUsageFlags::STORAGE,
+                vk::ImageLayout::GENERAL,
+                true,
+            ),
+            (
+                ImageAccess::TransferSrc,
+                vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                false,
+            ),
+            (
+                ImageAccess::TransferDst,
+                vk::ImageUsageFlags::TRANSFER_DST,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                true,
+            ),
+        ];
+
+        for (access, usage, layout, writes) in expected {
+            let state = access.state();
+
+            assert_eq!(access.usage(), usage);
+            assert_eq!(state.layout, layout, "{:?}", usage);
+            assert_eq!(state.writes(), writes, "{:?}", usage);
+        }
+        assert_eq!(ImageAccess::Sampled(stages).state().stages, stages);
+        assert_eq!(ImageAccess::Storage(stages).state().stages, stages);
+    }
+
+    #[test]
+    fn writers_keep_the_order_they_were_added_in() {
+        let mut graph = RenderGraph::new();
+        let image = transient_image(&mut graph, "image", vk::SampleCountFlags::TYPE_1);
+        graph.add_pass(PassDesc::new("first").color_attachment(image, None));
+        graph.add_pass(PassDesc::new("second").image(image, ImageAccess::TransferDst));
+        graph.add_pass(PassDesc::new("third").color_attachment(image, None));
+
+        assert_eq!(pass_names(&graph), ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn readers_run_after_writers() {
+        let mut graph = RenderGraph::new();
+        let image = transient_image(&mut graph, "image", vk::SampleCountFlags::TYPE_1);
+        let buffer = graph.import_buffer("buffer", vk::Buffer::null());
+        graph.add_pass(
+            PassDesc::new("present")
+                .sampled(image, vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .buffer(
+                    buffer,
+                    BufferAccess::Uniform(vk::PipelineStageFlags::VERTEX_SHADER),
+                ),
+        );
+        graph.add_pass(PassDesc::new("upload").buffer(buffer, BufferAccess::TransferDst));
+        graph.add_pass(PassDesc::new("render").color_attachment(image, None));
+
+        assert_eq!(pass_names(&graph), ["upload", "render", "present"]);
+    }
+
+    #[test]
+    fn passes_depending_on_each_other_are_an_error() {
+        let mut graph = RenderGraph::new();
+        let a = transient_image(&mut graph, "a", vk::SampleCountFlags::TYPE_1);
+        let b = transient_image(&mut graph, "b", vk::SampleCountFlags::TYPE_1);
+        graph.add_pass(
+            PassDesc::new("first")
+                .color_attachment(a, None)
+                .image(b, ImageAccess::TransferSrc),
+        );
+        graph.add_pass(
+            PassDesc::new("second")
+                .color_attachment(b, None)
+                .image(a, ImageAccess::TransferSrc),
+        );
+
+        assert!(graph.order().is_err());
+    }
+
+    #[test]
+    fn valid_passes() {
+        let mut graph = RenderGraph::new();
+        let color = transient_image(&mut graph, "color", vk::SampleCountFlags::TYPE_4);
+        let depth = transient_image(&mut graph, "depth", vk::SampleCountFlags::TYPE_4);
+        let resolve = transient_image(&mut graph, "resolve", vk::SampleCountFlags::TYPE_1);
+        let storage = transient_image(&mut graph, "storage", vk::SampleCountFlags::TYPE_1);
+        let buffer = graph.import_buffer("buffer", vk::Buffer::null());
+        graph.add_pass(
+            PassDesc::new("scene")
+                .color_attachment(color, None)
+                .depth_attachment(depth, None)
+                .resolve_attachment(resolve)
+                .buffer(buffer, BufferAccess::Vertex),
+        );
+        graph.add_pass(
+            PassDesc::new("post")
+                .sampled(resolve, vk::PipelineStageFlags::COMPUTE_SHADER)
+                .image(
+                    storage,
+                    ImageAccess::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
+                ),
+        );
+
+        assert!(graph.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_passes() {
+        let invalid: [fn(&mut RenderGraph) -> PassDesc; 8] = [
+            |_| PassDesc::new("unknown image").color_attachment(ImageId(10), None),
+            |_| PassDesc::new("unknown buffer").buffer(BufferId(10), BufferAccess::Index),
+            |graph| {
+                let image = transient_image(graph, "image", vk::SampleCountFlags::TYPE_1);
+                PassDesc::new("twice")
+                    .color_attachment(image, None)
+                    .sampled(image, vk::PipelineStageFlags::FRAGMENT_SHADER)
+            },
+            |graph| {
+                let depth = transient_image(graph, "depth", vk::SampleCountFlags::TYPE_1);
+                let other = transient_image(graph, "other", vk::SampleCountFlags::TYPE_1);
+                PassDesc::new("two depths")
+                    .depth_attachment(depth, None)
+                    .image(other, ImageAccess::DepthReadOnly)
+            },
+            |graph| {
+                let resolve = transient_image(graph, "resolve", vk::SampleCountFlags::TYPE_1);
+                PassDesc::new("resolve only").resolve_attachment(resolve)
+            },
+            |graph| {
+                let color = transient_image(graph, "color", vk::SampleCountFlags::TYPE_4);
+                let depth = transient_image(graph, "depth", vk::SampleCountFlags::TYPE_1);
+                PassDesc::new("mixed samples")
+                    .color_attachment(color, None)
+                    .depth_attachment(depth, None)
+            },
+            |graph| {
+                let color = transient_image(graph, "color", vk::SampleCountFlags::TYPE_1);
+                let resolve = transient_image(graph, "resolve", vk::SampleCountFlags::TYPE_1);
+                PassDesc::new("single sampled resolve")
+                    .color_attachment(color, None)
+                    .resolve_attachment(resolve)
+            },
+            |graph| {
+                let color = transient_image(graph, "color", vk::SampleCountFlags::TYPE_4);
+                let resolve = transient_image(graph, "resolve", vk::SampleCountFlags::TYPE_4);
+                PassDesc::new("multisampled resolve")
+                    .color_attachment(color, None)
+                    .resolve_attachment(resolve)
+            },
+        ];
+
+        for pass in invalid {
+            let mut graph = RenderGraph::new();
+            let desc = pass(&mut graph);
+            let name = desc.name.clone();
+            graph.add_pass(desc);
+
+            assert!(graph.validate().is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn set_imported_buffer_replaces_the_handle() {
+        use ash::vk::Handle;
+
+        let mut graph = RenderGraph::new();
+        let first = graph.import_buffer("first", vk::Buffer::from_raw(1));
+        let second = graph.import_buffer("second", vk::Buffer::from_raw(2));
+
+        graph.set_imported_buffer(first, vk::Buffer::from_raw(3));
+
+        assert_eq!(graph.buffers[first.0].buffer, vk::Buffer::from_raw(3));
+        assert_eq!(graph.buffers[second.0].buffer, vk::Buffer::from_raw(2));
+    }
+}
@@ -3,12 +3,23 @@ use std::time::Duration;
 use ash::vk;
 use winit::window::Window;
 
-use self::{base::RenderBase, data::RenderData, utils::MAX_FRAME_DRAWS};
+use self::{
+    base::RenderBase,
+    capture::CapturedFrame,
+    config::RendererConfig,
+    data::RenderData,
+    error::RendererError,
+    resources::{buffer::Buffer, image::Image, mesh::Mesh, vertex::VertexFormat},
+};
 
 pub mod base;
+pub mod capture;
+pub mod config;
 pub mod data;
 mod draw_setup;
-mod resources;
+pub mod error;
+pub mod graph;
+pub mod resources;
 pub mod utils;
 
 pub struct Renderer {
@@ -18,11 +29,15 @@ pub struct Renderer {
     pub current_frame_index: usize,
     pub rebuild_swapchain: bool,
     pub image_index: usize,
+
+    capture_requested: bool,
+    captured_frame: Option<CapturedFrame>,
+    readback_buffer: Option<Buffer>,
 }
 
 impl Renderer {
-    pub fn new(window: &Window) -> Result<Self, String> {
-        let mut base = RenderBase::new(window)?;
+    pub fn new(window: &Window, config: RendererConfig) -> Result<Self, RendererError> {
+        let mut base = RenderBase::new(window, config)?;
         let data = RenderData::new(&mut base)?;
 
         Ok(Self {
@@ -31,12 +46,36 @@ impl Renderer {
             current_frame_index: 0,
             rebuild_swapchain: true,
             image_index: 0,
+            capture_requested: false,
+            captured_frame: None,
+            readback_buffer: None,
+        })
+    }
+
+    /// Creates a renderer without a window that draws into offscreen images of `extent`.
+    pub fn new_headless(
+        extent: vk::Extent2D,
+        config: RendererConfig,
+    ) -> Result<Self, RendererError> {
+        let mut base = RenderBase::new_headless(extent, config)?;
+        let data = RenderData::new(&mut base)?;
+
+        Ok(Self {
+            base,
+            data,
+            current_frame_index: 0,
+            rebuild_swapchain: false,
+            image_index: 0,
+            capture_requested: false,
+            captured_frame: None,
+            readback_buffer: None,
         })
     }
 
-    #[allow(unused)]
     #[inline]
-    pub fn draw(&mut self, delta_time: &Duration) -> Result<(), String> {
+    pub fn draw(&mut self, delta_time: &Duration) -> Result<(), RendererError> {
+        self.reload_changed_shaders()?;
+
         self.image_index = match self.get_img_index()? {
             Some(index) => index as usize,
             None => {
@@ -45,7 +84,16 @@ impl Renderer {
             }
         };
 
+        // the swapchain may have been recreated with another format since the request
+        if self.capture_requested {
+            if let Err(err) = self.check_capture_support() {
+                log::warn!("dropping capture request: {}", err);
+                self.capture_requested = false;
+            }
+        }
+
         self.wait_resource_available()?;
+        self.update_frame_uniforms(delta_time)?;
         unsafe {
             self.base
                 .device
@@ -53,57 +101,134 @@ impl Renderer {
                     self.data.command_buffers[self.current_frame_index],
                     vk::CommandBufferResetFlags::default(),
                 )
-                .unwrap();
+                .map_err(RendererError::command("failed to reset command buffer"))?;
         }
 
-        self.begin_command_buffer();
-        self.begin_render_pass();
-        self.set_viewport();
-        self.set_scissor();
+        self.begin_command_buffer()?;
+        self.data.render_graph.execute(
+            self.data.command_buffers[self.current_frame_index],
+            self.image_index,
+            |pass| {
+                if pass == self.data.scene_pass {
+                    self.record_scene()?;
+                }
+                Ok(())
+            },
+        )?;
+
+        let capturing = std::mem::take(&mut self.capture_requested);
+        if capturing {
+            self.record_capture()?;
+        }
 
         unsafe {
-            self.base.device.cmd_set_viewport(
-                self.data.command_buffers[self.current_frame_index],
-                0,
-                &[self.data.viewport],
-            );
-
-            self.base.device.cmd_set_scissor(
-                self.data.command_buffers[self.current_frame_index],
-                0,
-                &[self.data.scissor],
-            );
-
-            self.base
-                .device
-                .cmd_end_render_pass(self.data.command_buffers[self.current_frame_index]);
-
             self.base
                 .device
                 .end_command_buffer(self.data.command_buffers[self.current_frame_index])
-                .map_err(|_| String::from("failed to end command buffer"))?
+                .map_err(RendererError::command("failed to end command buffer"))?
         }
         self.submit()?;
 
+        if capturing {
+            self.read_capture()?;
+        }
+
         if !self.present()? {
             self.rebuild_swapchain = true;
             return Ok(());
         }
 
-        self.current_frame_index = (self.current_frame_index + 1) % MAX_FRAME_DRAWS;
+        self.current_frame_index =
+            (self.current_frame_index + 1) % self.base.config.frames_in_flight;
 
         Ok(())
     }
 
     #[inline]
-    pub fn resize(&mut self, window: &Window) -> Result<(), String> {
+    pub fn resize(&mut self, window: &Window) -> Result<(), RendererError> {
         unsafe {
             let _ = self.base.device.device_wait_idle();
         }
-        self.set_scissor();
-        self.set_viewport();
         self.base.resize(window)?;
         self.data.resize(&self.base)?;
+        self.set_scissor();
+        self.set_viewport();
+
+        Ok(())
+    }
+}
+
+impl Renderer {
+    /// Uploads a mesh drawn every frame, returning its index in `data.meshes`.
+    ///
+    /// `V` has to match the vertex input of the pipeline.
+    pub fn add_mesh<V: VertexFormat>(
+        &mut self,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> Result<usize, RendererError> {
+        let binding = V::binding_description();
+        let attributes = V::attribute_descriptions();
+
+        let matches_pipeline = binding.stride == self.data.vertex_binding.stride
+            && binding.input_rate == self.data.vertex_binding.input_rate
+            && attributes.len() == self.data.vertex_attributes.len()
+            && attributes
+                .iter()
+                .zip(&self.data.vertex_attributes)
+                .all(|(a, b)| {
+                    (a.location, a.binding, a.format, a.offset)
+                        == (b.location, b.binding, b.format, b.offset)
+                });
+
+        if !matches_pipeline {
+            return Err(RendererError::Unsupported(format!(
+                "vertex format {} doesn't match the pipeline vertex input",
+                std::any::type_name::<V>()
+            )));
+        }
+
+        let mesh = Mesh::new(
+            &self.base,
+            self.data.transfer_command_pool,
+            vertices,
+            indices,
+        )?;
+        self.data.meshes.push(mesh);
+
+        Ok(self.data.meshes.len() - 1)
+    }
+
+    /// Replaces the texture sampled by the fragment shader with tightly packed sRGB RGBA
+    /// pixels. Waits for the gpu to finish the frames still using the old one.
+    pub fn set_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), RendererError> {
+        let texture = Image::from_rgba8(
+            &self.base,
+            self.data.transfer_command_pool,
+            vk::Extent2D { width, height },
+            pixels,
+            "texture",
+        )?;
+
+        unsafe {
+            self.base
+                .device
+                .device_wait_idle()
+                .map_err(RendererError::sync("failed to wait for device idle"))?;
+        }
+
+        resources::write_texture_set(
+            &self.base.device,
+            self.data.texture_set,
+            texture.view,
+            self.data.sampler,
+        );
+        *self.data.texture = texture;
 
         Ok(())
     }
@@ -113,6 +238,7 @@ impl Drop for Renderer {
     fn drop(&mut self) {
         unsafe {
             let _ = self.base.device.device_wait_idle();
+            self.readback_buffer = None;
             self.data.clean_up(&self.base.device);
             self.base.clean_up();
         }
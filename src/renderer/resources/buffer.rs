@@ -1,144 +1,239 @@
+use std::{
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex},
+};
+
 use ash::vk;
 
+use crate::renderer::{
+    base::{
+        allocator::{Allocation, AllocationDesc, Allocator, MemoryLocation},
+        RenderBase,
+    },
+    error::RendererError,
+};
+
+use super::{pod::Pod, submit_one_time};
+
+/// A buffer and its memory, both freed when it is dropped.
+///
+/// Buffers keep the allocator, and with it the device, alive until they are dropped.
 pub struct Buffer {
-    pub mem: vk::DeviceMemory,
     pub buf: vk::Buffer,
+    pub allocation: ManuallyDrop<Allocation>,
+    /// Size requested at creation, the allocation may be larger.
+    pub size: u64,
+    device: ash::Device,
+    allocator: Arc<Mutex<Allocator>>,
 }
 
 impl Buffer {
     #[inline]
     pub fn new(
         device: &ash::Device,
+        allocator: &Arc<Mutex<Allocator>>,
         buffer_size: u64,
         buffer_usage: vk::BufferUsageFlags,
-        memory_props: vk::PhysicalDeviceMemoryProperties,
-        buffer_props: vk::MemoryPropertyFlags,
-    ) -> Result<Self, String> {
+        location: MemoryLocation,
+        name: &str,
+    ) -> Result<Self, RendererError> {
+        Self::new_shared(
+            device,
+            allocator,
+            buffer_size,
+            buffer_usage,
+            location,
+            name,
+            &[],
+        )
+    }
+
+    /// Like [`Buffer::new`], but the buffer is shared concurrently between `queue_families`
+    /// when there is more than one of them.
+    pub fn new_shared(
+        device: &ash::Device,
+        allocator: &Arc<Mutex<Allocator>>,
+        buffer_size: u64,
+        buffer_usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+        name: &str,
+        queue_families: &[u32],
+    ) -> Result<Self, RendererError> {
         let buffer_info = vk::BufferCreateInfo::builder()
             .size(buffer_size)
-            .usage(buffer_usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .build();
+            .usage(buffer_usage);
+
+        let buffer_info = if queue_families.len() > 1 {
+            buffer_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(queue_families)
+        } else {
+            buffer_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+        };
 
         let buffer = unsafe {
             device
                 .create_buffer(&buffer_info, None)
-                .map_err(|msg| format!("{}", msg))?
+                .map_err(RendererError::memory("failed to create buffer"))?
         };
 
-        let mem_req = unsafe { device.get_buffer_memory_requirements(buffer) };
-
-        let mem_type_index =
-            match find_memory_type_index(memory_props, mem_req.memory_type_bits, buffer_props) {
-                Some(mem_type_ind) => mem_type_ind,
-                None => return Err(String::from("Couldn't find suitable memory type (you really should have found one, so most likely the problem is with you, or with the person who wrote the buffer creation, and in that case it is me. So basically fuck me)")),
-            };
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
 
-        let alloc_info = vk::MemoryAllocateInfo::builder()
-            .allocation_size(mem_req.size)
-            .memory_type_index(mem_type_index);
-
-        let memory = unsafe {
-            device
-                .allocate_memory(&alloc_info, None)
-                .map_err(|msg| format!("{}", msg))?
-        };
+        let mut locked_allocator = allocator.lock().unwrap();
+        let allocation = locked_allocator
+            .allocate(&AllocationDesc {
+                name,
+                requirements,
+                location,
+                linear: true,
+            })
+            .inspect_err(|_| unsafe { device.destroy_buffer(buffer, None) })?;
 
         unsafe {
-            device
-                .bind_buffer_memory(buffer, memory, 0)
-                .map_err(|msg| format!("{}", msg))?
+            if let Err(result) =
+                device.bind_buffer_memory(buffer, allocation.memory, allocation.offset)
+            {
+                device.destroy_buffer(buffer, None);
+                locked_allocator.free(allocation);
+                return Err(RendererError::Memory {
+                    context: String::from("failed to bind buffer memory"),
+                    result,
+                });
+            }
         };
 
         Ok(Self {
             buf: buffer,
-            mem: memory,
+            allocation: ManuallyDrop::new(allocation),
+            size: buffer_size,
+            device: device.clone(),
+            allocator: allocator.clone(),
         })
     }
 
+    /// Creates a `DEVICE_LOCAL` buffer holding `data`, uploaded through a staging buffer on
+    /// the transfer queue. `command_pool` has to belong to the transfer queue family.
+    ///
+    /// Blocks until the upload finished, the staging buffer is freed before returning.
+    pub fn from_slice<T: Pod>(
+        base: &RenderBase,
+        command_pool: vk::CommandPool,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        name: &str,
+    ) -> Result<Self, RendererError> {
+        let size = std::mem::size_of_val(data) as u64;
+
+        if size == 0 {
+            return Err(RendererError::Unsupported(format!("{} is empty", name)));
+        }
+
+        let staging_buffer = Buffer::new(
+            &base.device,
+            &base.allocator,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+            "staging buffer",
+        )?;
+        staging_buffer.write_slice(data, 0)?;
+
+        let buffer = Buffer::new_shared(
+            &base.device,
+            &base.allocator,
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+            name,
+            &base.unique_queue_families(),
+        )?;
+
+        staging_buffer.copy(&buffer, size, base.transfer_queue, command_pool)?;
+
+        Ok(buffer)
+    }
+
+    /// Copies `data` into the buffer at byte `offset` and flushes it if the memory is not
+    /// coherent.
+    pub fn write_slice<T: Pod>(&self, data: &[T], offset: u64) -> Result<(), RendererError> {
+        let size = std::mem::size_of_val(data) as u64;
+        let ptr = self.mapped_range(offset, size)?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, ptr, size as usize);
+        }
+
+        self.allocation.flush(&self.device, offset, size)
+    }
+
+    /// Reads `count` values starting at byte `offset`, invalidating the range first if the
+    /// memory is not coherent.
+    pub fn read_slice<T: Pod>(&self, offset: u64, count: usize) -> Result<Vec<T>, RendererError> {
+        let size = count.checked_mul(std::mem::size_of::<T>()).ok_or_else(|| {
+            RendererError::InvalidUsage(format!("reading {} values overflows", count))
+        })? as u64;
+        let ptr = self.mapped_range(offset, size)?;
+
+        self.allocation.invalidate(&self.device, offset, size)?;
+
+        let mut data = Vec::<T>::with_capacity(count);
+        unsafe {
+            std::ptr::copy_nonoverlapping(ptr, data.as_mut_ptr() as *mut u8, size as usize);
+            data.set_len(count);
+        }
+
+        Ok(data)
+    }
+
+    /// Pointer to `size` bytes at `offset` of the mapped memory.
+    fn mapped_range(&self, offset: u64, size: u64) -> Result<*mut u8, RendererError> {
+        let Some(ptr) = self.allocation.mapped_ptr() else {
+            return Err(RendererError::Unsupported(String::from(
+                "buffer is not host visible",
+            )));
+        };
+
+        if offset.checked_add(size).is_none_or(|end| end > self.size) {
+            return Err(RendererError::InvalidUsage(format!(
+                "range of {} bytes at {} is out of bounds of a {} byte buffer",
+                size, offset, self.size
+            )));
+        }
+
+        Ok(unsafe { (ptr as *mut u8).add(offset as usize) })
+    }
+
     #[inline]
     pub fn copy(
         &self,
-        device: &ash::Device,
         dst_buffer: &Buffer,
         buffer_size: u64,
         queue: vk::Queue,
         command_pool: vk::CommandPool,
-    ) -> Result<(), String> {
-        let allocate_info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(command_pool)
-            .command_buffer_count(1)
-            .level(vk::CommandBufferLevel::PRIMARY)
-            .build();
-
-        let command_buffer = unsafe {
-            device
-                .allocate_command_buffers(&allocate_info)
-                .map_err(|msg| format!("{}", msg))?[0]
-        };
-
-        let begin_info = vk::CommandBufferBeginInfo::builder()
-            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
-            .build();
-
+    ) -> Result<(), RendererError> {
         let buffer_copy_region = vk::BufferCopy::builder()
             .src_offset(0)
             .dst_offset(0)
             .size(buffer_size)
             .build();
 
-        let submit_info = vk::SubmitInfo::builder()
-            .command_buffers(&[command_buffer])
-            .build();
-
-        unsafe {
-            device
-                .begin_command_buffer(command_buffer, &begin_info)
-                .map_err(|msg| format!("{}", msg))?;
-
-            device.cmd_copy_buffer(
+        submit_one_time(&self.device, queue, command_pool, |command_buffer| unsafe {
+            self.device.cmd_copy_buffer(
                 command_buffer,
                 self.buf,
                 dst_buffer.buf,
                 &[buffer_copy_region],
             );
-
-            device
-                .end_command_buffer(command_buffer)
-                .map_err(|msg| format!("{}", msg))?;
-
-            device
-                .queue_submit(queue, &[submit_info], vk::Fence::null())
-                .map_err(|msg| format!("{}", msg))?;
-            device
-                .queue_wait_idle(queue)
-                .map_err(|msg| format!("{}", msg))?;
-            device.free_command_buffers(command_pool, &[command_buffer]);
-        }
-
-        Ok(())
+        })
     }
+}
 
-    #[inline]
-    fn free(&self, device: &ash::Device) {
+impl Drop for Buffer {
+    fn drop(&mut self) {
         unsafe {
-            device.destroy_buffer(self.buf, None);
-            device.free_memory(self.mem, None);
+            self.device.destroy_buffer(self.buf, None);
+            let allocation = ManuallyDrop::take(&mut self.allocation);
+            self.allocator.lock().unwrap().free(allocation);
         }
     }
 }
-
-fn find_memory_type_index(
-    memory_props: vk::PhysicalDeviceMemoryProperties,
-    allowed_types: u32,
-    props: vk::MemoryPropertyFlags,
-) -> Option<u32> {
-    memory_props
-        .memory_types
-        .iter()
-        .enumerate()
-        .find(|(i, mem_type)| {
-            allowed_types & (1 << i) != 0 && mem_type.property_flags & props == props
-        })
-        .map(|(i, _)| i as u32)
-}
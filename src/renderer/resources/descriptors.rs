@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::renderer::error::RendererError;
+
+use super::buffer::Buffer;
+
+/// Descriptors reserved per type for every set a pool can hold, every core type is covered
+/// so any layout can be allocated.
+const DEFAULT_POOL_RATIOS: [(vk::DescriptorType, f32); 11] = [
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 2.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+    (vk::DescriptorType::SAMPLER, 1.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::UNIFORM_TEXEL_BUFFER, 0.5),
+    (vk::DescriptorType::STORAGE_TEXEL_BUFFER, 0.5),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 0.5),
+];
+
+const INITIAL_SETS_PER_POOL: u32 = 32;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Creates each distinct descriptor set layout once, keyed by its bindings.
+pub struct DescriptorLayoutCache {
+    device: ash::Device,
+    layouts: HashMap<Vec<BindingKey>, vk::DescriptorSetLayout>,
+}
+
+/// The parts of a `VkDescriptorSetLayoutBinding` that identify it, immutable samplers are
+/// not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct BindingKey {
+    binding: u32,
+    descriptor_type: i32,
+    descriptor_count: u32,
+    stage_flags: u32,
+}
+
+impl DescriptorLayoutCache {
+    pub fn new(device: &ash::Device) -> Self {
+        Self {
+            device: device.clone(),
+            layouts: HashMap::new(),
+        }
+    }
+
+    /// Returns the layout for `bindings`, creating it on first use. The order of the
+    /// bindings doesn't matter.
+    pub fn get(
+        &mut self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<vk::DescriptorSetLayout, RendererError> {
+        let mut key = bindings
+            .iter()
+            .map(|binding| BindingKey {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type.as_raw(),
+                descriptor_count: binding.descriptor_count,
+                stage_flags: binding.stage_flags.as_raw(),
+            })
+            .collect::<Vec<_>>();
+        key.sort_unstable();
+
+        if let Some(&layout) = self.layouts.get(&key) {
+            return Ok(layout);
+        }
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+
+        let layout = unsafe {
+            self.device
+                .create_descriptor_set_layout(&create_info, None)
+                .map_err(RendererError::pipeline(
+                    "failed to create descriptor set layout",
+                ))?
+        };
+
+        self.layouts.insert(key, layout);
+
+        Ok(layout)
+    }
+
+    pub fn clean_up(&mut self) {
+        for (_, layout) in self.layouts.drain() {
+            unsafe { self.device.destroy_descriptor_set_layout(layout, None) };
+        }
+    }
+}
+
+/// Allocates descriptor sets from pools that are created as they fill up.
+///
+/// Each new pool holds twice as many sets as the previous one, up to `MAX_SETS_PER_POOL`.
+pub struct DescriptorAllocator {
+    device: ash::Device,
+    pool_ratios: Vec<(vk::DescriptorType, f32)>,
+    pools: PoolList,
+    /// The pool each live set was allocated from, needed to free it.
+    set_pools: HashMap<vk::DescriptorSet, vk::DescriptorPool>,
+}
+
+impl DescriptorAllocator {
+    pub fn new(device: &ash::Device) -> Self {
+        Self::with_pool_ratios(device, &DEFAULT_POOL_RATIOS)
+    }
+
+    /// `pool_ratios` gives the number of descriptors of each type reserved per set.
+    pub fn with_pool_ratios(
+        device: &ash::Device,
+        pool_ratios: &[(vk::DescriptorType, f32)],
+    ) -> Self {
+        Self {
+            device: device.clone(),
+            pool_ratios: pool_ratios.to_vec(),
+            pools: PoolList::default(),
+            set_pools: HashMap::new(),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, RendererError> {
+        loop {
+            let (pool, fresh) = match self.pools.current() {
+                Some(pool) => (pool, false),
+                None => (self.create_pool()?, true),
+            };
+
+            let layouts = [layout];
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts);
+
+            match unsafe { self.device.allocate_descriptor_sets(&allocate_info) } {
+                Ok(sets) => {
+                    if fresh {
+                        self.pools.ready.push(pool);
+                    }
+                    self.set_pools.insert(sets[0], pool);
+                    return Ok(sets[0]);
+                }
+                // only a pool that already had sets can be full, retry with the next one
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL)
+                    if !fresh =>
+                {
+                    self.pools.mark_full();
+                }
+                Err(result) => {
+                    // a new pool the set doesn't fit in would only fail the same way again
+                    if fresh {
+                        unsafe { self.device.destroy_descriptor_pool(pool, None) };
+                    }
+                    return Err(RendererError::Pipeline {
+                        context: String::from("failed to allocate descriptor set"),
+                        result,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns `set` to its pool, the set must no longer be in use by the gpu.
+    pub fn free(&mut self, set: vk::DescriptorSet) -> Result<(), RendererError> {
+        let Some(pool) = self.set_pools.remove(&set) else {
+            return Ok(());
+        };
+
+        unsafe {
+            self.device
+                .free_descriptor_sets(pool, &[set])
+                .map_err(RendererError::pipeline("failed to free descriptor set"))?
+        };
+
+        self.pools.mark_freed(pool);
+
+        Ok(())
+    }
+
+    /// Frees every set allocated so far, none of them may be in use by the gpu.
+    pub fn reset(&mut self) -> Result<(), RendererError> {
+        self.set_pools.clear();
+
+        for &pool in self.pools.reset() {
+            unsafe {
+                self.device
+                    .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                    .map_err(RendererError::pipeline("failed to reset descriptor pool"))?
+            };
+        }
+
+        Ok(())
+    }
+
+    pub fn clean_up(&mut self) {
+        for pool in self.pools.ready.drain(..).chain(self.pools.full.drain(..)) {
+            unsafe { self.device.destroy_descriptor_pool(pool, None) };
+        }
+        self.set_pools.clear();
+    }
+
+    fn create_pool(&mut self) -> Result<vk::DescriptorPool, RendererError> {
+        let sets = self.pools.grow();
+        let pool_sizes = pool_sizes(&self.pool_ratios, sets);
+
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .max_sets(sets)
+            .pool_sizes(&pool_sizes);
+
+        let pool = unsafe {
+            self.device
+                .create_descriptor_pool(&create_info, None)
+                .map_err(RendererError::pipeline("failed to create descriptor pool"))?
+        };
+
+        Ok(pool)
+    }
+}
+
+/// The pools of a [`DescriptorAllocator`], split by whether they may have room left.
+#[derive(Debug)]
+struct PoolList {
+    /// Pools that may have room left, the last one is allocated from first.
+    ready: Vec<vk::DescriptorPool>,
+    full: Vec<vk::DescriptorPool>,
+    /// Sets the next created pool holds.
+    sets_per_pool: u32,
+}
+
+impl Default for PoolList {
+    fn default() -> Self {
+        Self {
+            ready: Vec::new(),
+            full: Vec::new(),
+            sets_per_pool: INITIAL_SETS_PER_POOL,
+        }
+    }
+}
+
+impl PoolList {
+    #[inline]
+    fn current(&self) -> Option<vk::DescriptorPool> {
+        self.ready.last().copied()
+    }
+
+    /// The number of sets of the next pool, doubling it for the one after.
+    fn grow(&mut self) -> u32 {
+        let sets = self.sets_per_pool;
+        self.sets_per_pool = (self.sets_per_pool * 2).min(MAX_SETS_PER_POOL);
+        sets
+    }
+
+    /// Moves the current pool to the full ones.
+    fn mark_full(&mut self) {
+        if let Some(pool) = self.ready.pop() {
+            self.full.push(pool);
+        }
+    }
+
+    /// A set of `pool` was freed, a full pool has room again. It goes to the back so the
+    /// current pool keeps being used first.
+    fn mark_freed(&mut self, pool: vk::DescriptorPool) {
+        if let Some(index) = self.full.iter().position(|&full| full == pool) {
+            self.full.swap_remove(index);
+            self.ready.insert(0, pool);
+        }
+    }
+
+    /// Marks every pool as ready again, returning them all.
+    fn reset(&mut self) -> &[vk::DescriptorPool] {
+        self.ready.append(&mut self.full);
+        &self.ready
+    }
+}
+
+/// The descriptors of each type a pool of `sets` sets reserves, at least one per type.
+fn pool_sizes(pool_ratios: &[(vk::DescriptorType, f32)], sets: u32) -> Vec<vk::DescriptorPoolSize> {
+    pool_ratios
+        .iter()
+        .map(|&(ty, ratio)| vk::DescriptorPoolSize {
+            ty,
+            descriptor_count: ((ratio * sets as f32).ceil() as u32).max(1),
+        })
+        .collect()
+}
+
+/// Collects descriptor writes for a set and applies them in one `vkUpdateDescriptorSets`.
+///
+/// ```ignore
+/// DescriptorWriter::new()
+///     .buffer(0, &uniform_buffer, vk::DescriptorType::UNIFORM_BUFFER)
+///     .combined_image_sampler(1, view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+///     .update(&device, set);
+/// ```
+#[derive(Default)]
+pub struct DescriptorWriter {
+    buffer_writes: Vec<(u32, vk::DescriptorType, vk::DescriptorBufferInfo)>,
+    image_writes: Vec<(u32, vk::DescriptorType, vk::DescriptorImageInfo)>,
+}
+
+impl DescriptorWriter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds the whole of `buffer`.
+    #[inline]
+    pub fn buffer(self, binding: u32, buffer: &Buffer, ty: vk::DescriptorType) -> Self {
+        self.buffer_range(binding, buffer.buf, 0, buffer.size, ty)
+    }
+
+    pub fn buffer_range(
+        mut self,
+        binding: u32,
+        buffer: vk::Buffer,
+        offset: u64,
+        range: u64,
+        ty: vk::DescriptorType,
+    ) -> Self {
+        self.buffer_writes.push((
+            binding,
+            ty,
+            vk::DescriptorBufferInfo {
+                buffer,
+                offset,
+                range,
+            },
+        ));
+        self
+    }
+
+    /// A sampled or storage image.
+    pub fn image(
+        mut self,
+        binding: u32,
+        image_view: vk::ImageView,
+        image_layout: vk::ImageLayout,
+        ty: vk::DescriptorType,
+    ) -> Self {
+        self.image_writes.push((
+            binding,
+            ty,
+            vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view,
+                image_layout,
+            },
+        ));
+        self
+    }
+
+    pub fn sampler(mut self, binding: u32, sampler: vk::Sampler) -> Self {
+        self.image_writes.push((
+            binding,
+            vk::DescriptorType::SAMPLER,
+            vk::DescriptorImageInfo {
+                sampler,
+                image_view: vk::ImageView::null(),
+                image_layout: vk::ImageLayout::UNDEFINED,
+            },
+        ));
+        self
+    }
+
+    pub fn combined_image_sampler(
+        mut self,
+        binding: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        image_layout: vk::ImageLayout,
+    ) -> Self {
+        self.image_writes.push((
+            binding,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            vk::DescriptorImageInfo {
+                sampler,
+                image_view,
+                image_layout,
+            },
+        ));
+        self
+    }
+
+    pub fn update(&self, device: &ash::Device, set: vk::DescriptorSet) {
+        let buffer_writes = self.buffer_writes.iter().map(|(binding, ty, info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(*binding)
+                .descriptor_type(*ty)
+                .buffer_info(std::slice::from_ref(info))
+                .build()
+        });
+
+        let image_writes = self.image_writes.iter().map(|(binding, ty, info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(*binding)
+                .descriptor_type(*ty)
+                .image_info(std::slice::from_ref(info))
+                .build()
+        });
+
+        let writes = buffer_writes.chain(image_writes).collect::<Vec<_>>();
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+
+    use super::*;
+
+    fn pool(raw: u64) -> vk::DescriptorPool {
+        vk::DescriptorPool::from_raw(raw)
+    }
+
+    #[test]
+    fn pools_grow_up_to_the_limit() {
+        let mut pools = PoolList::default();
+
+        let sets = (0..12).map(|_| pools.grow()).collect::<Vec<_>>();
+
+        assert_eq!(sets[..3], [32, 64, 128]);
+        assert_eq!(sets[7], MAX_SETS_PER_POOL);
+        assert_eq!(sets[11], MAX_SETS_PER_POOL);
+    }
+
+    #[test]
+    fn full_pools_are_skipped_until_freed() {
+        let mut pools = PoolList::default();
+        assert_eq!(pools.current(), None);
+
+        pools.ready.push(pool(1));
+        pools.mark_full();
+        assert_eq!(pools.current(), None);
+
+        pools.ready.push(pool(2));
+        assert_eq!(pools.current(), Some(pool(2)));
+
+        // freeing from a pool that isn't full changes nothing
+        pools.mark_freed(pool(2));
+        assert_eq!(pools.ready, [pool(2)]);
+
+        // the freed pool is used once the current one fills up
+        pools.mark_freed(pool(1));
+        assert_eq!(pools.current(), Some(pool(2)));
+        assert!(pools.full.is_empty());
+
+        pools.mark_full();
+        assert_eq!(pools.current(), Some(pool(1)));
+        assert_eq!(pools.full, [pool(2)]);
+    }
+
+    #[test]
+    fn reset_makes_every_pool_ready() {
+        let mut pools = PoolList::default();
+        pools.ready.extend([pool(1), pool(2)]);
+        pools.mark_full();
+        pools.mark_full();
+
+        let mut reset = pools.reset().to_vec();
+        reset.sort_unstable_by_key(|pool| pool.as_raw());
+
+        assert_eq!(reset, [pool(1), pool(2)]);
+        assert!(pools.full.is_empty());
+    }
+
+    #[test]
+    fn default_pools_cover_every_core_descriptor_type() {
+        for raw in
+            vk::DescriptorType::SAMPLER.as_raw()..=vk::DescriptorType::INPUT_ATTACHMENT.as_raw()
+        {
+            let ty = vk::DescriptorType::from_raw(raw);
+            assert!(
+                DEFAULT_POOL_RATIOS
+                    .iter()
+                    .any(|&(pool_ty, _)| pool_ty == ty),
+                "{:?}",
+                ty
+            );
+        }
+    }
+
+    #[test]
+    fn pool_sizes_reserve_at_least_one_descriptor() {
+        let sizes = pool_sizes(
+            &[
+                (vk::DescriptorType::UNIFORM_BUFFER, 1.5),
+                (vk::DescriptorType::SAMPLER, 0.0),
+            ],
+            3,
+        );
+
+        let counts = sizes
+            .iter()
+            .map(|size| (size.ty, size.descriptor_count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            [
+                (vk::DescriptorType::UNIFORM_BUFFER, 5),
+                (vk::DescriptorType::SAMPLER, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn writer_collects_image_writes() {
+        let view = vk::ImageView::from_raw(1);
+        let sampler = vk::Sampler::from_raw(2);
+
+        let writer = DescriptorWriter::new()
+            .combined_image_sampler(0, view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .sampler(1, sampler);
+
+        let writes = writer
+            .image_writes
+            .iter()
+            .map(|(binding, ty, info)| (*binding, *ty, info.image_view, info.sampler))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            writes,
+            [
+                (0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, view, sampler),
+                (
+                    1,
+                    vk::DescriptorType::SAMPLER,
+                    vk::ImageView::null(),
+                    sampler
+                ),
+            ]
+        );
+        assert!(writer.buffer_writes.is_empty());
+    }
+}
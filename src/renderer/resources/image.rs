@@ -0,0 +1,444 @@
+use std::{
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex},
+};
+
+use ash::vk;
+
+use crate::renderer::{
+    base::{
+        allocator::{Allocation, AllocationDesc, Allocator, MemoryLocation},
+        RenderBase,
+    },
+    error::RendererError,
+};
+
+use super::{buffer::Buffer, submit_one_time};
+
+/// Describes a 2D image to create.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageDesc<'a> {
+    pub name: &'a str,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub aspect: vk::ImageAspectFlags,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl<'a> ImageDesc<'a> {
+    /// A single sampled color image that can be uploaded to.
+    pub fn texture(name: &'a str, extent: vk::Extent2D, format: vk::Format) -> Self {
+        Self {
+            name,
+            extent,
+            format,
+            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            aspect: vk::ImageAspectFlags::COLOR,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+}
+
+/// A 2D image with its memory and a view of the whole image, freed when dropped.
+///
+/// Like [`Buffer`], images keep the allocator and the device alive until they are dropped.
+pub struct Image {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub allocation: ManuallyDrop<Allocation>,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub aspect: vk::ImageAspectFlags,
+    device: ash::Device,
+    allocator: Arc<Mutex<Allocator>>,
+}
+
+impl Image {
+    /// Creates the image in device local memory, shared concurrently between `queue_families`
+    /// when there is more than one of them. The image starts in `UNDEFINED` layout.
+    pub fn new(
+        device: &ash::Device,
+        allocator: &Arc<Mutex<Allocator>>,
+        desc: &ImageDesc,
+        queue_families: &[u32],
+    ) -> Result<Self, RendererError> {
+        if desc.extent.width == 0 || desc.extent.height == 0 {
+            return Err(RendererError::InvalidUsage(format!(
+                "{} has an empty extent of {}x{}",
+                desc.name, desc.extent.width, desc.extent.height
+            )));
+        }
+
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(desc.format)
+            .extent(vk::Extent3D {
+                width: desc.extent.width,
+                height: desc.extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(desc.samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(desc.usage)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let create_info = if queue_families.len() > 1 {
+            create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(queue_families)
+        } else {
+            create_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+        };
+
+        let image = unsafe {
+            device
+                .create_image(&create_info, None)
+                .map_err(RendererError::memory(format!(
+                    "failed to create {}",
+                    desc.name
+                )))?
+        };
+
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+
+        let mut locked_allocator = allocator.lock().unwrap();
+        let allocation = locked_allocator
+            .allocate(&AllocationDesc {
+                name: desc.name,
+                requirements,
+                location: MemoryLocation::GpuOnly,
+                linear: false,
+            })
+            .inspect_err(|_| unsafe { device.destroy_image(image, None) })?;
+
+        let view = unsafe {
+            device
+                .bind_image_memory(image, allocation.memory, allocation.offset)
+                .map_err(RendererError::memory("failed to bind image memory"))
+                .and_then(|_| create_image_view(device, image, desc.format, desc.aspect))
+        };
+
+        let view = match view {
+            Ok(view) => view,
+            Err(err) => {
+                unsafe { device.destroy_image(image, None) };
+                locked_allocator.free(allocation);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            image,
+            view,
+            allocation: ManuallyDrop::new(allocation),
+            extent: desc.extent,
+            format: desc.format,
+            aspect: desc.aspect,
+            device: device.clone(),
+            allocator: allocator.clone(),
+        })
+    }
+
+    /// Creates a sampled `R8G8B8A8_SRGB` texture from tightly packed RGBA rows, uploaded on the
+    /// transfer queue and left in `SHADER_READ_ONLY_OPTIMAL` layout. `command_pool` has to
+    /// belong to the transfer queue family.
+    pub fn from_rgba8(
+        base: &RenderBase,
+        command_pool: vk::CommandPool,
+        extent: vk::Extent2D,
+        pixels: &[u8],
+        name: &str,
+    ) -> Result<Self, RendererError> {
+        let image = Image::new(
+            &base.device,
+            &base.allocator,
+            &ImageDesc::texture(name, extent, vk::Format::R8G8B8A8_SRGB),
+            &base.unique_queue_families(),
+        )?;
+
+        image.upload(base, command_pool, pixels)?;
+
+        Ok(image)
+    }
+
+    /// Copies `data` into the whole image through a staging buffer, the previous contents
+    /// are discarded. Leaves the image in `SHADER_READ_ONLY_OPTIMAL` layout.
+    ///
+    /// `data` has to hold exactly one tightly packed texel per pixel.
+    pub fn upload(
+        &self,
+        base: &RenderBase,
+        command_pool: vk::CommandPool,
+        data: &[u8],
+    ) -> Result<(), RendererError> {
+        let Some(texel_size) = texel_size(self.format) else {
+            return Err(RendererError::Unsupported(format!(
+                "uploading to {:?} images",
+                self.format
+            )));
+        };
+        let expected = u64::from(self.extent.width)
+            .checked_mul(u64::from(self.extent.height))
+            .and_then(|texels| texels.checked_mul(texel_size));
+        if expected != Some(data.len() as u64) {
+            return Err(RendererError::InvalidUsage(format!(
+                "{} bytes uploaded to a {}x{} {:?} image",
+                data.len(),
+                self.extent.width,
+                self.extent.height,
+                self.format
+            )));
+        }
+
+        let staging_buffer = Buffer::new(
+            &base.device,
+            &base.allocator,
+            data.len() as u64,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+            "image staging buffer",
+        )?;
+        staging_buffer.write_slice(data, 0)?;
+
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: self.aspect,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            })
+            .build();
+
+        submit_one_time(
+            &base.device,
+            base.transfer_queue,
+            command_pool,
+            |command_buffer| unsafe {
+                self.transition_layout(
+                    command_buffer,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+
+                base.device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer.buf,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                );
+
+                self.transition_layout(
+                    command_buffer,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                );
+            },
+        )
+    }
+
+    /// Records a barrier moving the whole image from `old_layout` to `new_layout`.
+    ///
+    /// The stages and accesses are derived from the layouts, which covers the usual
+    /// transitions of attachments, transfers and sampled images.
+    pub fn transition_layout(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) {
+        let (src_stage, src_access) = layout_stage_and_access(old_layout);
+        let (dst_stage, dst_access) = layout_stage_and_access(new_layout);
+
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: self.aspect,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .build();
+
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        }
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+            let allocation = ManuallyDrop::take(&mut self.allocation);
+            self.allocator.lock().unwrap().free(allocation);
+        }
+    }
+}
+
+pub fn create_image_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect: vk::ImageAspectFlags,
+) -> Result<vk::ImageView, RendererError> {
+    let create_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: aspect,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        });
+
+    let view = unsafe {
+        device
+            .create_image_view(&create_info, None)
+            .map_err(RendererError::memory("failed to create image view"))?
+    };
+
+    Ok(view)
+}
+
+/// Bytes per texel of the uncompressed color formats that can be uploaded to, `None` for
+/// any other format.
+pub fn texel_size(format: vk::Format) -> Option<u64> {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB => Some(1),
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB | vk::Format::R16_SFLOAT => Some(2),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => Some(8),
+        vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
+
+/// Whether a depth format also has a stencil component.
+#[inline]
+pub fn has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+/// The aspects of an image of `format`, the depth and stencil aspects for depth formats and
+/// the color aspect otherwise.
+#[inline]
+pub fn format_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+/// A sampler without mipmapping or anisotropic filtering.
+pub fn create_sampler(
+    device: &ash::Device,
+    filter: vk::Filter,
+    address_mode: vk::SamplerAddressMode,
+) -> Result<vk::Sampler, RendererError> {
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .max_lod(0.0)
+        .border_color(vk::BorderColor::FLOAT_OPAQUE_BLACK);
+
+    let sampler = unsafe {
+        device
+            .create_sampler(&create_info, None)
+            .map_err(RendererError::memory("failed to create sampler"))?
+    };
+
+    Ok(sampler)
+}
+
+/// The pipeline stages and accesses that use an image in `layout`.
+fn layout_stage_and_access(layout: vk::ImageLayout) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+    match layout {
+        vk::ImageLayout::UNDEFINED | vk::ImageLayout::PRESENT_SRC_KHR => (
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::AccessFlags::empty(),
+        ),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+        ),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+        ),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        ),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        | vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL => (
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ),
+        // sampled images may be read on a transfer only queue, where the shader stages
+        // don't exist, so the later submit on the graphics queue provides the ordering
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::AccessFlags::empty(),
+        ),
+        _ => (
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+        ),
+    }
+}
@@ -0,0 +1,84 @@
+use ash::vk;
+
+use crate::renderer::{base::RenderBase, error::RendererError};
+
+use super::{buffer::Buffer, uniforms::IDENTITY, vertex::VertexFormat};
+
+/// Vertex and index buffers of a mesh drawn with 32 bit indices, freed when dropped.
+pub struct Mesh {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub index_count: u32,
+    /// Pushed as [`MeshPushConstants`](super::uniforms::MeshPushConstants) when the mesh is drawn.
+    pub model: [[f32; 4]; 4],
+    /// Replaces `RenderData::viewport` while drawing this mesh, e.g. to draw into a
+    /// sub-region of the frame.
+    pub viewport: Option<vk::Viewport>,
+    /// Replaces `RenderData::scissor` while drawing this mesh.
+    pub scissor: Option<vk::Rect2D>,
+}
+
+impl Mesh {
+    /// Uploads the vertices and indices into device local buffers, `command_pool` has to
+    /// belong to the transfer queue family.
+    pub fn new<V: VertexFormat>(
+        base: &RenderBase,
+        command_pool: vk::CommandPool,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> Result<Self, RendererError> {
+        let vertex_buffer = Buffer::from_slice(
+            base,
+            command_pool,
+            vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            "vertex buffer",
+        )?;
+
+        let index_buffer = Buffer::from_slice(
+            base,
+            command_pool,
+            indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            "index buffer",
+        )?;
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            model: IDENTITY,
+            viewport: None,
+            scissor: None,
+        })
+    }
+
+    /// Draws the mesh into `region` of the frame only, scaling it to fit.
+    #[inline]
+    pub fn set_region(&mut self, region: vk::Rect2D) {
+        self.viewport = Some(vk::Viewport {
+            x: region.offset.x as f32,
+            y: region.offset.y as f32,
+            width: region.extent.width as f32,
+            height: region.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        });
+        self.scissor = Some(region);
+    }
+
+    /// Binds the buffers and records an indexed draw of the whole mesh.
+    #[inline]
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.buf], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer.buf,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+        }
+    }
+}
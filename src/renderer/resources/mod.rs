@@ -1,222 +1,215 @@
 pub mod buffer;
+pub mod descriptors;
+pub mod image;
+pub mod mesh;
+pub mod pipeline;
+pub mod pod;
+pub mod reflection;
+pub mod shader;
+pub mod shader_compiler;
+pub mod uniforms;
+pub mod vertex;
 
 use ash::vk;
 
-use super::utils::MAX_FRAME_DRAWS;
+use self::{
+    buffer::Buffer,
+    descriptors::{DescriptorAllocator, DescriptorWriter},
+    image::Image,
+    uniforms::FrameUniforms,
+};
 
-pub fn create_render_pass(
+use super::{
+    base::{allocator::MemoryLocation, RenderBase},
+    error::RendererError,
+};
+
+/// Creates a pipeline layout after checking `push_constant_ranges` against the device limits.
+pub fn create_pipeline_layout(
     device: &ash::Device,
-    surface_format: vk::Format,
-) -> Result<vk::RenderPass, String> {
-    let mut attachment_descriptions = Vec::new();
-
-    attachment_descriptions.push(
-        vk::AttachmentDescription::builder()
-            .format(surface_format)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .build(),
-    );
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+    limits: &vk::PhysicalDeviceLimits,
+) -> Result<vk::PipelineLayout, RendererError> {
+    validate_push_constant_ranges(push_constant_ranges, limits)?;
 
-    let col_attachment_ref = vk::AttachmentReference::builder()
-        .attachment(0)
-        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+    let create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(push_constant_ranges)
         .build();
 
-    let references = [col_attachment_ref];
-
-    let mut subpass_descriptions = Vec::new();
-
-    subpass_descriptions.push(
-        vk::SubpassDescription::builder()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&references)
-            .build(),
-    );
-
-    let create_info = vk::RenderPassCreateInfo::builder()
-        .attachments(&attachment_descriptions)
-        .subpasses(&subpass_descriptions);
-
-    let render_pass = unsafe {
+    let pipeline_layout = unsafe {
         device
-            .create_render_pass(&create_info, None)
-            .map_err(|_| String::from("failed to create render pass"))?
+            .create_pipeline_layout(&create_info, None)
+            .map_err(RendererError::pipeline("failed to create pipeline layout"))?
     };
 
-    Ok(render_pass)
+    Ok(pipeline_layout)
 }
 
-pub fn create_pipelines(
-    device: &ash::Device,
-    vertex_shader_module: vk::ShaderModule,
-    fragment_shader_module: vk::ShaderModule,
-    pipeline_layout: vk::PipelineLayout,
-    render_pass: vk::RenderPass,
-) -> Result<vk::Pipeline, String> {
-    let shader_entry_name = std::ffi::CString::new("main").unwrap();
-
-    let vs_state = vk::PipelineShaderStageCreateInfo::builder()
-        .stage(vk::ShaderStageFlags::VERTEX)
-        .module(vertex_shader_module)
-        .name(&shader_entry_name)
-        .build();
-
-    let fs_state = vk::PipelineShaderStageCreateInfo::builder()
-        .stage(vk::ShaderStageFlags::FRAGMENT)
-        .module(fragment_shader_module)
-        .name(&shader_entry_name)
-        .build();
-
-    let ia_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-        .build();
-
-    let raster_state = vk::PipelineRasterizationStateCreateInfo::builder()
-        .polygon_mode(vk::PolygonMode::FILL)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::CLOCKWISE)
-        .line_width(1.0f32)
-        .build();
-
-    let col_blend_attachment_state = vk::PipelineColorBlendAttachmentState::builder()
-        .blend_enable(false)
-        .color_write_mask(
-            vk::ColorComponentFlags::R
-                | vk::ColorComponentFlags::G
-                | vk::ColorComponentFlags::B
-                | vk::ColorComponentFlags::A,
-        )
-        .build();
-
-    let attachments = [col_blend_attachment_state];
-    let col_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
-        .attachments(&attachments)
-        .build();
-
-    // let states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
-
-    let viewports = [vk::Viewport {
-        ..Default::default()
-    }];
-    let scissors = [vk::Rect2D {
-        ..Default::default()
-    }];
-
-    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(&viewports)
-        .scissors(&scissors)
-        .build();
-
-    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-    let stages = [vs_state, fs_state];
-
-    // let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&states);
-
-    let vert_inp_state = vk::PipelineVertexInputStateCreateInfo::builder().build();
-
-    let solid_pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
-        .flags(vk::PipelineCreateFlags::ALLOW_DERIVATIVES)
-        .stages(&stages)
-        .input_assembly_state(&ia_state)
-        .rasterization_state(&raster_state)
-        .color_blend_state(&col_blend_state)
-        .viewport_state(&viewport_state)
-        .layout(pipeline_layout)
-        // .dynamic_state(&dynamic_state_info)
-        .render_pass(render_pass)
-        .subpass(0)
-        .multisample_state(&multisample_state)
-        .vertex_input_state(&vert_inp_state)
-        .build();
+/// Checks the rules `VkPipelineLayoutCreateInfo` puts on push constant ranges: 4 byte
+/// aligned, within `maxPushConstantsSize` and no stage in more than one range.
+pub fn validate_push_constant_ranges(
+    ranges: &[vk::PushConstantRange],
+    limits: &vk::PhysicalDeviceLimits,
+) -> Result<(), RendererError> {
+    let mut used_stages = vk::ShaderStageFlags::empty();
+
+    for range in ranges {
+        if range.size == 0 || !range.offset.is_multiple_of(4) || !range.size.is_multiple_of(4) {
+            return Err(RendererError::InvalidUsage(format!(
+                "push constant range {:?} must be non-empty with offset and size multiples of 4",
+                range
+            )));
+        }
+        let Some(end) = range.offset.checked_add(range.size) else {
+            return Err(RendererError::InvalidUsage(format!(
+                "push constant range {:?} overflows",
+                range
+            )));
+        };
+        if end > limits.max_push_constants_size {
+            return Err(RendererError::Unsupported(format!(
+                "push constant range {:?} exceeds maxPushConstantsSize ({})",
+                range, limits.max_push_constants_size
+            )));
+        }
+        if range.stage_flags.is_empty() || used_stages.intersects(range.stage_flags) {
+            return Err(RendererError::InvalidUsage(format!(
+                "push constant range {:?} has no stages or shares a stage with another range",
+                range
+            )));
+        }
+        used_stages |= range.stage_flags;
+    }
 
-    let pipelines = unsafe {
-        device
-            .create_graphics_pipelines(
-                vk::PipelineCache::null(),
-                &[solid_pipeline_create_info],
-                None,
-            )
-            .map_err(|_| String::from("failed to create pipelines"))?
-    };
+    Ok(())
+}
 
-    let pipeline = pipelines[0];
+/// Bindings of the per frame set: the [`FrameUniforms`] buffer at binding 0.
+pub fn frame_set_bindings() -> [vk::DescriptorSetLayoutBinding; 1] {
+    [vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .build()]
+}
 
-    Ok(pipeline)
+/// Bindings of the texture set: the sampled image at binding 0 and its sampler at binding 1.
+pub fn texture_set_bindings() -> [vk::DescriptorSetLayoutBinding; 2] {
+    [
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build(),
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build(),
+    ]
 }
 
-pub fn create_pipeline_layout(
+/// Points a texture set to `view` sampled with `sampler`.
+pub fn write_texture_set(
     device: &ash::Device,
-    // descriptor_set_layout: vk::DescriptorSetLayout,
-) -> Result<vk::PipelineLayout, String> {
-    // let layouts = [descriptor_set_layout];
-    let create_info = vk::PipelineLayoutCreateInfo::builder()
-        // .set_layouts(&layouts)
-        .build();
-
-    let pipeline_layout = unsafe {
-        device
-            .create_pipeline_layout(&create_info, None)
-            .map_err(|_| String::from("failed to create pipeline layout"))?
-    };
+    set: vk::DescriptorSet,
+    view: vk::ImageView,
+    sampler: vk::Sampler,
+) {
+    DescriptorWriter::new()
+        .image(
+            0,
+            view,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::SAMPLED_IMAGE,
+        )
+        .sampler(1, sampler)
+        .update(device, set);
+}
 
-    Ok(pipeline_layout)
+/// A 2x2 black and white checkerboard, the texture used until one is set.
+pub fn create_default_texture(
+    base: &RenderBase,
+    command_pool: vk::CommandPool,
+) -> Result<Image, RendererError> {
+    #[rustfmt::skip]
+    let pixels = [
+        255, 255, 255, 255,   0,   0,   0, 255,
+          0,   0,   0, 255, 255, 255, 255, 255,
+    ];
+
+    Image::from_rgba8(
+        base,
+        command_pool,
+        vk::Extent2D {
+            width: 2,
+            height: 2,
+        },
+        &pixels,
+        "default texture",
+    )
 }
 
-pub fn create_framebuffers(
+/// Allocates one set per uniform buffer, each pointing to its buffer.
+pub fn create_frame_descriptor_sets(
     device: &ash::Device,
-    swapchain_image_views: &Vec<vk::ImageView>,
-    render_pass: vk::RenderPass,
-    framebuffer_extent: vk::Extent2D,
-) -> Result<Vec<vk::Framebuffer>, String> {
-    let mut framebuffers = Vec::with_capacity(swapchain_image_views.len());
-
-    for (i, &view) in swapchain_image_views.iter().enumerate() {
-        let attachments = [view];
-
-        let create_info = vk::FramebufferCreateInfo::builder()
-            .render_pass(render_pass)
-            .attachments(&attachments)
-            .width(framebuffer_extent.width)
-            .height(framebuffer_extent.height)
-            .layers(1)
-            .build();
-
-        let framebuffer = unsafe {
-            device.create_framebuffer(&create_info, None).map_err(|_| {
-                for &fb in &framebuffers {
-                    device.destroy_framebuffer(fb, None);
-                }
-                format!("failed to create framebuffer {}", i)
-            })?
-        };
-
-        framebuffers.push(framebuffer);
-    }
+    descriptor_allocator: &mut DescriptorAllocator,
+    layout: vk::DescriptorSetLayout,
+    uniform_buffers: &[Buffer],
+) -> Result<Vec<vk::DescriptorSet>, RendererError> {
+    uniform_buffers
+        .iter()
+        .map(|buffer| {
+            let set = descriptor_allocator.allocate(layout)?;
+            DescriptorWriter::new()
+                .buffer(0, buffer, vk::DescriptorType::UNIFORM_BUFFER)
+                .update(device, set);
+            Ok(set)
+        })
+        .collect()
+}
 
-    Ok(framebuffers)
+/// Creates `count` host visible buffers holding [`FrameUniforms`], one per frame in flight.
+pub fn create_uniform_buffers(
+    base: &RenderBase,
+    count: usize,
+) -> Result<Vec<Buffer>, RendererError> {
+    (0..count)
+        .map(|_| {
+            Buffer::new(
+                &base.device,
+                &base.allocator,
+                std::mem::size_of::<FrameUniforms>() as u64,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+                "frame uniform buffer",
+            )
+        })
+        .collect()
 }
 
 pub fn create_semaphore(
     device: &ash::Device,
     object_name: &str,
-) -> Result<Vec<vk::Semaphore>, String> {
-    let mut semaphores = Vec::<vk::Semaphore>::with_capacity(MAX_FRAME_DRAWS);
+    count: usize,
+) -> Result<Vec<vk::Semaphore>, RendererError> {
+    let mut semaphores = Vec::<vk::Semaphore>::with_capacity(count);
 
-    for _ in 0..MAX_FRAME_DRAWS {
+    for _ in 0..count {
         let create_info = vk::SemaphoreCreateInfo::default();
 
         let semaphore = unsafe {
             device
                 .create_semaphore(&create_info, None)
-                .map_err(|_| format!("failed to create {}", object_name))?
+                .map_err(RendererError::sync(format!(
+                    "failed to create {}",
+                    object_name
+                )))?
         };
 
         semaphores.push(semaphore);
@@ -225,21 +218,24 @@ pub fn create_semaphore(
     Ok(semaphores)
 }
 
-pub fn create_fences(device: &ash::Device) -> Result<Vec<vk::Fence>, String> {
+pub fn create_fences(device: &ash::Device, count: usize) -> Result<Vec<vk::Fence>, RendererError> {
     let create_info = vk::FenceCreateInfo::builder()
         .flags(vk::FenceCreateFlags::SIGNALED)
         .build();
 
-    let mut fences = Vec::with_capacity(MAX_FRAME_DRAWS as usize);
+    let mut fences = Vec::with_capacity(count);
 
-    for i in 0..MAX_FRAME_DRAWS {
+    for i in 0..count {
         let fence = unsafe {
-            device.create_fence(&create_info, None).map_err(|_| {
+            device.create_fence(&create_info, None).map_err(|result| {
                 for &f in &fences {
                     device.destroy_fence(f, None);
                 }
 
-                format!("failed to create fence {}", i)
+                RendererError::Sync {
+                    context: format!("failed to create fence {}", i),
+                    result,
+                }
             })?
         };
 
@@ -252,7 +248,7 @@ pub fn create_fences(device: &ash::Device) -> Result<Vec<vk::Fence>, String> {
 pub fn create_command_pool(
     device: &ash::Device,
     queue_family: u32,
-) -> Result<vk::CommandPool, String> {
+) -> Result<vk::CommandPool, RendererError> {
     let create_info = vk::CommandPoolCreateInfo::builder()
         .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
         .queue_family_index(queue_family);
@@ -260,8 +256,133 @@ pub fn create_command_pool(
     let command_pool = unsafe {
         device
             .create_command_pool(&create_info, None)
-            .expect("Failed to create command pool")
+            .map_err(RendererError::command("failed to create command pool"))?
     };
 
     Ok(command_pool)
 }
+
+/// Records commands with `record` into a temporary command buffer from `command_pool`,
+/// submits it to `queue` and waits for it to finish.
+pub fn submit_one_time(
+    device: &ash::Device,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    record: impl FnOnce(vk::CommandBuffer),
+) -> Result<(), RendererError> {
+    let allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(command_pool)
+        .command_buffer_count(1)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .build();
+
+    let command_buffer = unsafe {
+        device
+            .allocate_command_buffers(&allocate_info)
+            .map_err(RendererError::command(
+                "failed to allocate one time command buffer",
+            ))?[0]
+    };
+
+    let begin_info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+        .build();
+
+    let command_buffers = [command_buffer];
+    let submit_info = vk::SubmitInfo::builder()
+        .command_buffers(&command_buffers)
+        .build();
+
+    let result = unsafe {
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .map_err(RendererError::command(
+                "failed to begin one time command buffer",
+            ))
+            .and_then(|_| {
+                record(command_buffer);
+                device
+                    .end_command_buffer(command_buffer)
+                    .map_err(RendererError::command(
+                        "failed to end one time command buffer",
+                    ))
+            })
+            .and_then(|_| {
+                device
+                    .queue_submit(queue, &[submit_info], vk::Fence::null())
+                    .map_err(RendererError::command(
+                        "failed to submit one time command buffer",
+                    ))
+            })
+            .and_then(|_| {
+                device
+                    .queue_wait_idle(queue)
+                    .map_err(RendererError::sync("failed to wait for one time submit"))
+            })
+    };
+
+    unsafe { device.free_command_buffers(command_pool, &command_buffers) };
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(stage_flags: vk::ShaderStageFlags, offset: u32, size: u32) -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags,
+            offset,
+            size,
+        }
+    }
+
+    fn limits() -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits {
+            max_push_constants_size: 128,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_push_constant_ranges() {
+        let ranges = [
+            range(vk::ShaderStageFlags::VERTEX, 0, 64),
+            range(vk::ShaderStageFlags::FRAGMENT, 64, 64),
+        ];
+
+        assert!(validate_push_constant_ranges(&ranges, &limits()).is_ok());
+        assert!(validate_push_constant_ranges(&[], &limits()).is_ok());
+    }
+
+    #[test]
+    fn invalid_push_constant_ranges() {
+        let vertex = vk::ShaderStageFlags::VERTEX;
+        let invalid = [
+            // overflow
+            vec![range(vertex, u32::MAX - 3, 4)],
+            // unaligned
+            vec![range(vertex, 2, 4)],
+            vec![range(vertex, 0, 6)],
+            vec![range(vertex, 0, 0)],
+            // overlapping stages
+            vec![
+                range(vertex | vk::ShaderStageFlags::FRAGMENT, 0, 16),
+                range(vertex, 16, 16),
+            ],
+            vec![range(vk::ShaderStageFlags::empty(), 0, 16)],
+            // exceeding max_push_constants_size
+            vec![range(vertex, 0, 132)],
+            vec![range(vertex, 64, 68)],
+        ];
+
+        for ranges in invalid {
+            assert!(
+                validate_push_constant_ranges(&ranges, &limits()).is_err(),
+                "{:?}",
+                ranges
+            );
+        }
+    }
+}
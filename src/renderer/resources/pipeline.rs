@@ -0,0 +1,576 @@
+use std::ffi::CString;
+
+use ash::vk;
+
+use crate::renderer::{base::RenderBase, error::RendererError};
+
+use super::{image, vertex::VertexFormat};
+
+/// How the fragment color is combined with the color already in the attachment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites the attachment.
+    #[default]
+    Opaque,
+    /// Standard transparency, `src * src_alpha + dst * (1 - src_alpha)`.
+    Alpha,
+    /// `src * src_alpha + dst`, for light and particles.
+    Additive,
+    /// Transparency for colors already multiplied by their alpha, `src + dst * (1 - src_alpha)`.
+    Premultiplied,
+}
+
+impl BlendMode {
+    fn attachment_state(
+        self,
+        color_write_mask: vk::ColorComponentFlags,
+    ) -> vk::PipelineColorBlendAttachmentState {
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => {
+                return vk::PipelineColorBlendAttachmentState::builder()
+                    .blend_enable(false)
+                    .color_write_mask(color_write_mask)
+                    .build()
+            }
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Premultiplied => (
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+        };
+
+        vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(color_write_mask)
+            .build()
+    }
+}
+
+/// Depth test and write settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: vk::CompareOp,
+}
+
+impl DepthState {
+    /// No depth testing or writing.
+    pub const DISABLED: Self = Self {
+        test: false,
+        write: false,
+        compare_op: vk::CompareOp::ALWAYS,
+    };
+
+    /// Tests and writes depth, keeping the closest fragments.
+    pub const READ_WRITE: Self = Self {
+        test: true,
+        write: true,
+        compare_op: vk::CompareOp::LESS,
+    };
+
+    /// Tests against the depth buffer without writing to it, e.g. for transparent geometry.
+    pub const READ_ONLY: Self = Self {
+        test: true,
+        write: false,
+        compare_op: vk::CompareOp::LESS_OR_EQUAL,
+    };
+
+    fn create_info(self) -> vk::PipelineDepthStencilStateCreateInfo {
+        vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.test)
+            .depth_write_enable(self.write)
+            .depth_compare_op(self.compare_op)
+            .build()
+    }
+}
+
+/// A shader module and the stage it runs in.
+#[derive(Debug, Clone)]
+pub struct ShaderStage {
+    pub stage: vk::ShaderStageFlags,
+    pub module: vk::ShaderModule,
+    pub entry_point: CString,
+}
+
+/// Describes a graphics pipeline with one color attachment, viewport and scissor are always
+/// dynamic.
+///
+/// Starts as an opaque, back face culled triangle list without depth testing:
+///
+/// ```ignore
+/// let pipeline = PipelineBuilder::new()
+///     .stage(vk::ShaderStageFlags::VERTEX, vertex_shader_module)
+///     .stage(vk::ShaderStageFlags::FRAGMENT, fragment_shader_module)
+///     .vertex_input::<Vertex>()
+///     .polygon_mode(vk::PolygonMode::LINE)
+///     .blend_mode(BlendMode::Alpha)
+///     .depth_state(DepthState::READ_ONLY)
+///     .build(&base, pipeline_layout, render_pass)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct PipelineBuilder {
+    pub stages: Vec<ShaderStage>,
+    pub vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    pub vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    pub topology: vk::PrimitiveTopology,
+    pub primitive_restart: bool,
+    /// Used with tessellation shaders, which require a `PATCH_LIST` topology.
+    pub patch_control_points: u32,
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub line_width: f32,
+    pub blend_mode: BlendMode,
+    pub color_write_mask: vk::ColorComponentFlags,
+    pub depth_state: DepthState,
+    pub samples: vk::SampleCountFlags,
+    pub subpass: u32,
+    /// Attachment formats for dynamic rendering, only used when building without a render
+    /// pass. `UNDEFINED` for no depth attachment.
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        Self {
+            stages: Vec::new(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            patch_control_points: 3,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::CLOCKWISE,
+            line_width: 1.0,
+            blend_mode: BlendMode::Opaque,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            depth_state: DepthState::DISABLED,
+            samples: vk::SampleCountFlags::TYPE_1,
+            subpass: 0,
+            color_format: vk::Format::UNDEFINED,
+            depth_format: vk::Format::UNDEFINED,
+        }
+    }
+}
+
+impl PipelineBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stage using the `main` entry point.
+    #[inline]
+    pub fn stage(self, stage: vk::ShaderStageFlags, module: vk::ShaderModule) -> Self {
+        self.stage_with_entry(stage, module, "main")
+    }
+
+    pub fn stage_with_entry(
+        mut self,
+        stage: vk::ShaderStageFlags,
+        module: vk::ShaderModule,
+        entry_point: &str,
+    ) -> Self {
+        self.stages.push(ShaderStage {
+            stage,
+            module,
+            entry_point: CString::new(entry_point).unwrap(),
+        });
+        self
+    }
+
+    /// Reads a single vertex buffer binding from a [`VertexFormat`].
+    #[inline]
+    pub fn vertex_input<V: VertexFormat>(self) -> Self {
+        self.vertex_layout(&[V::binding_description()], &V::attribute_descriptions())
+    }
+
+    #[inline]
+    pub fn vertex_layout(
+        mut self,
+        bindings: &[vk::VertexInputBindingDescription],
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Self {
+        self.vertex_bindings = bindings.to_vec();
+        self.vertex_attributes = attributes.to_vec();
+        self
+    }
+
+    #[inline]
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    #[inline]
+    pub fn primitive_restart(mut self, primitive_restart: bool) -> Self {
+        self.primitive_restart = primitive_restart;
+        self
+    }
+
+    #[inline]
+    pub fn patch_control_points(mut self, patch_control_points: u32) -> Self {
+        self.patch_control_points = patch_control_points;
+        self
+    }
+
+    /// `LINE` and `POINT` need the `fill_mode_non_solid` feature.
+    #[inline]
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    #[inline]
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    #[inline]
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    /// Widths other than 1 need the `wide_lines` feature.
+    #[inline]
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    #[inline]
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    #[inline]
+    pub fn color_write_mask(mut self, color_write_mask: vk::ColorComponentFlags) -> Self {
+        self.color_write_mask = color_write_mask;
+        self
+    }
+
+    #[inline]
+    pub fn depth_state(mut self, depth_state: DepthState) -> Self {
+        self.depth_state = depth_state;
+        self
+    }
+
+    /// Has to match the sample count of the render pass attachments.
+    #[inline]
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    #[inline]
+    pub fn subpass(mut self, subpass: u32) -> Self {
+        self.subpass = subpass;
+        self
+    }
+
+    /// The formats of the attachments the pipeline renders to with dynamic rendering.
+    #[inline]
+    pub fn rendering_formats(
+        mut self,
+        color_format: vk::Format,
+        depth_format: Option<vk::Format>,
+    ) -> Self {
+        self.color_format = color_format;
+        self.depth_format = depth_format.unwrap_or(vk::Format::UNDEFINED);
+        self
+    }
+
+    /// Checks the settings against the enabled device features.
+    pub fn validate(&self, base: &RenderBase) -> Result<(), RendererError> {
+        let features = &base.enabled_features.core;
+        let stages = self
+            .stages
+            .iter()
+            .fold(vk::ShaderStageFlags::empty(), |stages, stage| {
+                stages | stage.stage
+            });
+
+        if !stages.contains(vk::ShaderStageFlags::VERTEX) {
+            return Err(RendererError::InvalidUsage(String::from(
+                "graphics pipelines need a vertex shader stage",
+            )));
+        }
+        if self.stages.len() != stages.as_raw().count_ones() as usize {
+            return Err(RendererError::InvalidUsage(format!(
+                "a shader stage is given more than once in {:?}",
+                stages
+            )));
+        }
+
+        let tessellation = vk::ShaderStageFlags::TESSELLATION_CONTROL
+            | vk::ShaderStageFlags::TESSELLATION_EVALUATION;
+        if stages.intersects(tessellation) {
+            if features.tessellation_shader == vk::FALSE {
+                return Err(RendererError::Unsupported(String::from(
+                    "tessellation shaders need the tessellation_shader feature",
+                )));
+            }
+            if !stages.contains(tessellation) || self.topology != vk::PrimitiveTopology::PATCH_LIST
+            {
+                return Err(RendererError::InvalidUsage(String::from(
+                    "tessellation needs both tessellation stages and a PATCH_LIST topology",
+                )));
+            }
+        }
+        if stages.contains(vk::ShaderStageFlags::GEOMETRY) && features.geometry_shader == vk::FALSE
+        {
+            return Err(RendererError::Unsupported(String::from(
+                "geometry shaders need the geometry_shader feature",
+            )));
+        }
+        if self.polygon_mode != vk::PolygonMode::FILL && features.fill_mode_non_solid == vk::FALSE {
+            return Err(RendererError::Unsupported(format!(
+                "polygon mode {:?} needs the fill_mode_non_solid feature",
+                self.polygon_mode
+            )));
+        }
+        if self.line_width != 1.0 && features.wide_lines == vk::FALSE {
+            return Err(RendererError::Unsupported(String::from(
+                "line widths other than 1 need the wide_lines feature",
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Validates the settings and creates the pipeline for `subpass` of `render_pass`, or for
+    /// dynamic rendering with the `rendering_formats` when `render_pass` is null.
+    pub fn build(
+        &self,
+        base: &RenderBase,
+        pipeline_layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> Result<vk::Pipeline, RendererError> {
+        self.validate(base)?;
+
+        let dynamic_rendering = render_pass == vk::RenderPass::null();
+        if dynamic_rendering && base.dynamic_rendering.is_none() {
+            return Err(RendererError::Unsupported(String::from(
+                "pipelines without a render pass need dynamic rendering",
+            )));
+        }
+        if dynamic_rendering && self.color_format == vk::Format::UNDEFINED {
+            return Err(RendererError::InvalidUsage(String::from(
+                "pipelines without a render pass need the rendering formats",
+            )));
+        }
+
+        let stages = self
+            .stages
+            .iter()
+            .map(|stage| {
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(stage.stage)
+                    .module(stage.module)
+                    .name(&stage.entry_point)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let vert_inp_state = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&self.vertex_bindings)
+            .vertex_attribute_descriptions(&self.vertex_attributes)
+            .build();
+
+        let ia_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(self.topology)
+            .primitive_restart_enable(self.primitive_restart)
+            .build();
+
+        let tessellation_state = vk::PipelineTessellationStateCreateInfo::builder()
+            .patch_control_points(self.patch_control_points)
+            .build();
+
+        let raster_state = vk::PipelineRasterizationStateCreateInfo::builder()
+            .polygon_mode(self.polygon_mode)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .line_width(self.line_width)
+            .build();
+
+        let attachments = [self.blend_mode.attachment_state(self.color_write_mask)];
+        let col_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&attachments)
+            .build();
+
+        let depth_stencil_state = self.depth_state.create_info();
+
+        // set while recording, so resizing doesn't require new pipelines
+        let states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&states);
+
+        let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1)
+            .build();
+
+        let multisample_state =
+            vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(self.samples);
+
+        let mut create_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&stages)
+            .vertex_input_state(&vert_inp_state)
+            .input_assembly_state(&ia_state)
+            .rasterization_state(&raster_state)
+            .color_blend_state(&col_blend_state)
+            .viewport_state(&viewport_state)
+            .depth_stencil_state(&depth_stencil_state)
+            .multisample_state(&multisample_state)
+            .dynamic_state(&dynamic_state_info)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(self.subpass);
+
+        if self.topology == vk::PrimitiveTopology::PATCH_LIST {
+            create_info = create_info.tessellation_state(&tessellation_state);
+        }
+
+        let color_formats = [self.color_format];
+        let stencil_format = if image::has_stencil(self.depth_format) {
+            self.depth_format
+        } else {
+            vk::Format::UNDEFINED
+        };
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+            .color_attachment_formats(&color_formats)
+            .depth_attachment_format(self.depth_format)
+            .stencil_attachment_format(stencil_format);
+        if dynamic_rendering {
+            create_info = create_info.push_next(&mut rendering_info);
+        }
+
+        let pipelines = unsafe {
+            base.device
+                .create_graphics_pipelines(base.pipeline_cache.cache, &[create_info.build()], None)
+                .map_err(|(_, result)| RendererError::Pipeline {
+                    context: String::from("failed to create graphics pipeline"),
+                    result,
+                })?
+        };
+
+        Ok(pipelines[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factors(
+        state: &vk::PipelineColorBlendAttachmentState,
+    ) -> (
+        vk::BlendFactor,
+        vk::BlendFactor,
+        vk::BlendFactor,
+        vk::BlendFactor,
+    ) {
+        (
+            state.src_color_blend_factor,
+            state.dst_color_blend_factor,
+            state.src_alpha_blend_factor,
+            state.dst_alpha_blend_factor,
+        )
+    }
+
+    #[test]
+    fn opaque_disables_blending() {
+        let state = BlendMode::Opaque.attachment_state(vk::ColorComponentFlags::RGBA);
+
+        assert_eq!(state.blend_enable, vk::FALSE);
+        assert_eq!(state.color_write_mask, vk::ColorComponentFlags::RGBA);
+    }
+
+    #[test]
+    fn blend_mode_factors() {
+        use vk::BlendFactor as F;
+
+        let expected = [
+            (
+                BlendMode::Alpha,
+                (
+                    F::SRC_ALPHA,
+                    F::ONE_MINUS_SRC_ALPHA,
+                    F::ONE,
+                    F::ONE_MINUS_SRC_ALPHA,
+                ),
+            ),
+            (BlendMode::Additive, (F::SRC_ALPHA, F::ONE, F::ONE, F::ONE)),
+            (
+                BlendMode::Premultiplied,
+                (
+                    F::ONE,
+                    F::ONE_MINUS_SRC_ALPHA,
+                    F::ONE,
+                    F::ONE_MINUS_SRC_ALPHA,
+                ),
+            ),
+        ];
+
+        for (mode, factors_of_mode) in expected {
+            let state = mode.attachment_state(vk::ColorComponentFlags::R);
+
+            assert_eq!(state.blend_enable, vk::TRUE, "{:?}", mode);
+            assert_eq!(factors(&state), factors_of_mode, "{:?}", mode);
+            assert_eq!(state.color_blend_op, vk::BlendOp::ADD);
+            assert_eq!(state.alpha_blend_op, vk::BlendOp::ADD);
+            assert_eq!(state.color_write_mask, vk::ColorComponentFlags::R);
+        }
+    }
+
+    #[test]
+    fn depth_states() {
+        let states = [
+            DepthState::DISABLED,
+            DepthState::READ_WRITE,
+            DepthState::READ_ONLY,
+        ]
+        .map(|state| {
+            let info = state.create_info();
+            (
+                info.depth_test_enable,
+                info.depth_write_enable,
+                info.depth_compare_op,
+            )
+        });
+
+        assert_eq!(
+            states,
+            [
+                (vk::FALSE, vk::FALSE, vk::CompareOp::ALWAYS),
+                (vk::TRUE, vk::TRUE, vk::CompareOp::LESS),
+                (vk::TRUE, vk::FALSE, vk::CompareOp::LESS_OR_EQUAL),
+            ]
+        );
+    }
+}
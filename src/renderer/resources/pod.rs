@@ -0,0 +1,25 @@
+use num::Num;
+
+use crate::engine::lin_alg::{Vector2, Vector3};
+
+/// Plain old data, values that can be copied to and from gpu memory as raw bytes.
+///
+/// # Safety
+///
+/// Implementors must have a defined layout (`#[repr(C)]`, a primitive or an array of them)
+/// without padding bytes, hold no pointers or references, and be valid for any bit pattern.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(unsafe impl Pod for $ty {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+// both are repr(C) with fields of one type, so there is no padding
+unsafe impl<T: Num + Pod> Pod for Vector2<T> {}
+unsafe impl<T: Num + Pod> Pod for Vector3<T> {}
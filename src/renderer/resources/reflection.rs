@@ -0,0 +1,1062 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ash::vk;
+
+use crate::renderer::error::RendererError;
+
+use super::{descriptors::DescriptorLayoutCache, shader::SPIRV_MAGIC};
+
+/// The SPIR-V opcodes, decorations and enums reflection looks at.
+mod spirv {
+    pub const OP_NAME: u32 = 5;
+    pub const OP_ENTRY_POINT: u32 = 15;
+    pub const OP_TYPE_BOOL: u32 = 20;
+    pub const OP_TYPE_INT: u32 = 21;
+    pub const OP_TYPE_FLOAT: u32 = 22;
+    pub const OP_TYPE_VECTOR: u32 = 23;
+    pub const OP_TYPE_MATRIX: u32 = 24;
+    pub const OP_TYPE_IMAGE: u32 = 25;
+    pub const OP_TYPE_SAMPLER: u32 = 26;
+    pub const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const OP_TYPE_ARRAY: u32 = 28;
+    pub const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const OP_TYPE_STRUCT: u32 = 30;
+    pub const OP_TYPE_POINTER: u32 = 32;
+    pub const OP_CONSTANT: u32 = 43;
+    pub const OP_VARIABLE: u32 = 59;
+    pub const OP_DECORATE: u32 = 71;
+    pub const OP_MEMBER_DECORATE: u32 = 72;
+    pub const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+    pub const DECORATION_BLOCK: u32 = 2;
+    pub const DECORATION_BUFFER_BLOCK: u32 = 3;
+    pub const DECORATION_ARRAY_STRIDE: u32 = 6;
+    pub const DECORATION_MATRIX_STRIDE: u32 = 7;
+    pub const DECORATION_BUILT_IN: u32 = 11;
+    pub const DECORATION_LOCATION: u32 = 30;
+    pub const DECORATION_BINDING: u32 = 33;
+    pub const DECORATION_DESCRIPTOR_SET: u32 = 34;
+    pub const DECORATION_OFFSET: u32 = 35;
+
+    pub const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+    pub const STORAGE_CLASS_INPUT: u32 = 1;
+    pub const STORAGE_CLASS_UNIFORM: u32 = 2;
+    pub const STORAGE_CLASS_OUTPUT: u32 = 3;
+    pub const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+    pub const DIM_BUFFER: u32 = 5;
+    pub const DIM_SUBPASS_DATA: u32 = 6;
+}
+
+/// A resource a shader reads through a descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    /// Every stage that declares the binding.
+    pub stages: vk::ShaderStageFlags,
+    pub name: String,
+}
+
+/// A stage input or output with a location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub format: vk::Format,
+    pub name: String,
+}
+
+/// What one entry point of a SPIR-V module expects from the pipeline.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub entry_point: String,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub push_constant_range: Option<vk::PushConstantRange>,
+    /// Sorted by location, built-ins are left out.
+    pub inputs: Vec<InterfaceVariable>,
+    /// Sorted by location, built-ins are left out.
+    pub outputs: Vec<InterfaceVariable>,
+}
+
+impl ShaderReflection {
+    /// Reflects `entry_point`, or the only entry point of the module if `None`.
+    pub fn from_spirv(code: &[u32], entry_point: Option<&str>) -> Result<Self, RendererError> {
+        let module = Module::parse(code)?;
+
+        let entry = match entry_point {
+            Some(name) => module.entry_points.iter().find(|entry| entry.name == name),
+            None if module.entry_points.len() == 1 => module.entry_points.first(),
+            None => None,
+        }
+        .ok_or_else(|| {
+            let names = module
+                .entry_points
+                .iter()
+                .map(|entry| entry.name.as_str())
+                .collect::<Vec<_>>();
+            RendererError::InvalidUsage(format!(
+                "entry point {} not found, the module has {:?}",
+                entry_point.unwrap_or("<only>"),
+                names
+            ))
+        })?;
+
+        let stage = execution_model_stage(entry.execution_model)?;
+        // tessellation and geometry stages see an array of their inputs per primitive
+        let arrayed_inputs = stage.intersects(
+            vk::ShaderStageFlags::TESSELLATION_CONTROL
+                | vk::ShaderStageFlags::TESSELLATION_EVALUATION
+                | vk::ShaderStageFlags::GEOMETRY,
+        );
+        let arrayed_outputs = stage == vk::ShaderStageFlags::TESSELLATION_CONTROL;
+
+        let mut reflection = Self {
+            stage,
+            entry_point: entry.name.clone(),
+            descriptor_bindings: Vec::new(),
+            push_constant_range: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+
+        for variable in &module.variables {
+            let Some(Type::Pointer { pointee, .. }) = module.types.get(&variable.type_id) else {
+                continue;
+            };
+            let name = module.name(variable.id);
+
+            match variable.storage_class {
+                spirv::STORAGE_CLASS_INPUT | spirv::STORAGE_CLASS_OUTPUT => {
+                    let is_input = variable.storage_class == spirv::STORAGE_CLASS_INPUT;
+                    // before SPIR-V 1.4 the interface only lists inputs and outputs
+                    if !entry.interface.contains(&variable.id)
+                        || module.is_built_in(variable.id, *pointee)
+                    {
+                        continue;
+                    }
+
+                    let location = module
+                        .decoration(variable.id, spirv::DECORATION_LOCATION)
+                        .ok_or_else(|| {
+                            RendererError::InvalidUsage(format!(
+                                "{:?} interface variable {} has no location",
+                                stage, name
+                            ))
+                        })?;
+
+                    let arrayed = if is_input {
+                        arrayed_inputs
+                    } else {
+                        arrayed_outputs
+                    };
+                    let format = module.interface_format(*pointee, arrayed).ok_or_else(|| {
+                        RendererError::Unsupported(format!(
+                            "{:?} interface variable {} has an unsupported type",
+                            stage, name
+                        ))
+                    })?;
+
+                    let interface_variable = InterfaceVariable {
+                        location,
+                        format,
+                        name,
+                    };
+                    if is_input {
+                        reflection.inputs.push(interface_variable);
+                    } else {
+                        reflection.outputs.push(interface_variable);
+                    }
+                }
+                spirv::STORAGE_CLASS_PUSH_CONSTANT => {
+                    let (offset, size) = module.push_constant_block_range(*pointee)?;
+                    reflection.push_constant_range = Some(vk::PushConstantRange {
+                        stage_flags: stage,
+                        offset,
+                        size,
+                    });
+                }
+                spirv::STORAGE_CLASS_UNIFORM_CONSTANT
+                | spirv::STORAGE_CLASS_UNIFORM
+                | spirv::STORAGE_CLASS_STORAGE_BUFFER => {
+                    let Some((descriptor_type, count)) =
+                        module.descriptor_type(variable.storage_class, *pointee)?
+                    else {
+                        continue;
+                    };
+
+                    let set = module.decoration(variable.id, spirv::DECORATION_DESCRIPTOR_SET);
+                    let binding = module.decoration(variable.id, spirv::DECORATION_BINDING);
+                    let (Some(set), Some(binding)) = (set, binding) else {
+                        return Err(RendererError::InvalidUsage(format!(
+                            "{:?} resource {} has no descriptor set or binding",
+                            stage, name
+                        )));
+                    };
+
+                    reflection.descriptor_bindings.push(DescriptorBinding {
+                        set,
+                        binding,
+                        descriptor_type,
+                        count,
+                        stages: stage,
+                        name,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        reflection.inputs.sort_by_key(|variable| variable.location);
+        reflection.outputs.sort_by_key(|variable| variable.location);
+
+        Ok(reflection)
+    }
+}
+
+/// The combined interface of the shader stages of a pipeline.
+#[derive(Debug, Clone)]
+pub struct PipelineReflection {
+    /// Bindings of each descriptor set, sorted by binding.
+    pub sets: BTreeMap<u32, Vec<DescriptorBinding>>,
+    /// One range per distinct push constant block, stages sharing a block share the range.
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    /// Inputs of the vertex stage.
+    pub vertex_inputs: Vec<InterfaceVariable>,
+}
+
+impl PipelineReflection {
+    /// Merges the stages, failing if they declare the same binding differently or if a stage
+    /// reads an input the previous stage doesn't write.
+    pub fn new(shaders: &[ShaderReflection]) -> Result<Self, RendererError> {
+        let mut shaders = shaders.iter().collect::<Vec<_>>();
+        // stage bits are in pipeline order
+        shaders.sort_by_key(|shader| shader.stage.as_raw());
+
+        let mut stages = vk::ShaderStageFlags::empty();
+        for shader in &shaders {
+            if stages.intersects(shader.stage) {
+                return Err(RendererError::InvalidUsage(format!(
+                    "more than one {:?} shader",
+                    shader.stage
+                )));
+            }
+            stages |= shader.stage;
+        }
+
+        for pair in shaders.windows(2) {
+            check_stage_interface(pair[0], pair[1])?;
+        }
+
+        let mut sets = BTreeMap::<u32, Vec<DescriptorBinding>>::new();
+        for binding in shaders
+            .iter()
+            .flat_map(|shader| &shader.descriptor_bindings)
+        {
+            let bindings = sets.entry(binding.set).or_default();
+
+            match bindings.iter_mut().find(|b| b.binding == binding.binding) {
+                Some(existing) => {
+                    if (existing.descriptor_type, existing.count)
+                        != (binding.descriptor_type, binding.count)
+                    {
+                        return Err(RendererError::InvalidUsage(format!(
+                            "set {} binding {} is {} x {:?} in {:?} but {} x {:?} in {:?}",
+                            binding.set,
+                            binding.binding,
+                            existing.count,
+                            existing.descriptor_type,
+                            existing.stages,
+                            binding.count,
+                            binding.descriptor_type,
+                            binding.stages
+                        )));
+                    }
+                    existing.stages |= binding.stages;
+                }
+                None => bindings.push(binding.clone()),
+            }
+        }
+        for bindings in sets.values_mut() {
+            bindings.sort_by_key(|binding| binding.binding);
+        }
+
+        let mut push_constant_ranges = Vec::<vk::PushConstantRange>::new();
+        for range in shaders
+            .iter()
+            .filter_map(|shader| shader.push_constant_range)
+        {
+            match push_constant_ranges
+                .iter_mut()
+                .find(|r| (r.offset, r.size) == (range.offset, range.size))
+            {
+                Some(existing) => existing.stage_flags |= range.stage_flags,
+                None => push_constant_ranges.push(range),
+            }
+        }
+
+        let vertex_inputs = shaders
+            .iter()
+            .find(|shader| shader.stage == vk::ShaderStageFlags::VERTEX)
+            .map(|shader| shader.inputs.clone())
+            .unwrap_or_default();
+
+        Ok(Self {
+            sets,
+            push_constant_ranges,
+            vertex_inputs,
+        })
+    }
+
+    /// Layout bindings of `set`, empty if no stage uses it.
+    pub fn set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding> {
+        self.sets
+            .get(&set)
+            .into_iter()
+            .flatten()
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.count)
+                    .stage_flags(binding.stages)
+                    .build()
+            })
+            .collect()
+    }
+
+    /// Gets a layout for every set up to the highest one used, unused sets get empty layouts.
+    pub fn set_layouts(
+        &self,
+        cache: &mut DescriptorLayoutCache,
+    ) -> Result<Vec<vk::DescriptorSetLayout>, RendererError> {
+        let set_count = self.sets.keys().next_back().map_or(0, |&set| set + 1);
+
+        (0..set_count)
+            .map(|set| cache.get(&self.set_layout_bindings(set)))
+            .collect()
+    }
+
+    /// Whether a pipeline of these shaders can use the pipeline layout made for `other`.
+    pub fn same_layout(&self, other: &Self) -> bool {
+        let set_keys = |reflection: &Self| {
+            reflection
+                .sets
+                .iter()
+                .map(|(&set, bindings)| {
+                    let bindings = bindings
+                        .iter()
+                        .map(|b| (b.binding, b.descriptor_type, b.count, b.stages))
+                        .collect::<Vec<_>>();
+                    (set, bindings)
+                })
+                .collect::<Vec<_>>()
+        };
+        let push_keys = |reflection: &Self| {
+            let mut keys = reflection
+                .push_constant_ranges
+                .iter()
+                .map(|r| (r.stage_flags.as_raw(), r.offset, r.size))
+                .collect::<Vec<_>>();
+            keys.sort_unstable();
+            keys
+        };
+
+        set_keys(self) == set_keys(other) && push_keys(self) == push_keys(other)
+    }
+
+    /// Checks that the shaders declare exactly the bindings in `expected` for `set`, ignoring
+    /// stage flags. Used where the renderer writes the set itself.
+    pub fn check_set(
+        &self,
+        set: u32,
+        expected: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<(), RendererError> {
+        let mut declared = self
+            .set_layout_bindings(set)
+            .iter()
+            .map(|b| (b.binding, b.descriptor_type, b.descriptor_count))
+            .collect::<Vec<_>>();
+        let mut expected = expected
+            .iter()
+            .map(|b| (b.binding, b.descriptor_type, b.descriptor_count))
+            .collect::<Vec<_>>();
+        declared.sort_unstable_by_key(|&(binding, ..)| binding);
+        expected.sort_unstable_by_key(|&(binding, ..)| binding);
+
+        if declared != expected {
+            return Err(RendererError::InvalidUsage(format!(
+                "the shaders declare set {} as {:?}, expected {:?}",
+                set, declared, expected
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the shaders' push constant blocks are exactly `expected`.
+    pub fn check_push_constants(
+        &self,
+        expected: &[vk::PushConstantRange],
+    ) -> Result<(), RendererError> {
+        let key =
+            |range: &vk::PushConstantRange| (range.stage_flags.as_raw(), range.offset, range.size);
+        let mut declared = self
+            .push_constant_ranges
+            .iter()
+            .map(key)
+            .collect::<Vec<_>>();
+        let mut expected_keys = expected.iter().map(key).collect::<Vec<_>>();
+        declared.sort_unstable();
+        expected_keys.sort_unstable();
+
+        if declared != expected_keys {
+            return Err(RendererError::InvalidUsage(format!(
+                "the shaders declare push constants {:?}, expected {:?}",
+                self.push_constant_ranges, expected
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `attributes` provide every vertex shader input with a matching format.
+    pub fn check_vertex_input(
+        &self,
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Result<(), RendererError> {
+        for input in &self.vertex_inputs {
+            match attributes
+                .iter()
+                .find(|attribute| attribute.location == input.location)
+            {
+                Some(attribute) if attribute.format == input.format => (),
+                Some(attribute) => {
+                    return Err(RendererError::InvalidUsage(format!(
+                        "vertex input {} at location {} is {:?} but the attribute is {:?}",
+                        input.name, input.location, input.format, attribute.format
+                    )))
+                }
+                None => {
+                    return Err(RendererError::InvalidUsage(format!(
+                        "vertex input {} at location {} has no attribute",
+                        input.name, input.location
+                    )))
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Every input of `next` has to be written by `previous` at the same location with the
+/// same format.
+fn check_stage_interface(
+    previous: &ShaderReflection,
+    next: &ShaderReflection,
+) -> Result<(), RendererError> {
+    for input in &next.inputs {
+        match previous
+            .outputs
+            .iter()
+            .find(|output| output.location == input.location)
+        {
+            Some(output) if output.format == input.format => (),
+            Some(output) => {
+                return Err(RendererError::InvalidUsage(format!(
+                    "{:?} input {} at location {} is {:?} but the {:?} output {} is {:?}",
+                    next.stage,
+                    input.name,
+                    input.location,
+                    input.format,
+                    previous.stage,
+                    output.name,
+                    output.format
+                )))
+            }
+            None => {
+                return Err(RendererError::InvalidUsage(format!(
+                    "{:?} input {} at location {} isn't written by the {:?} stage",
+                    next.stage, input.name, input.location, previous.stage
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn execution_model_stage(execution_model: u32) -> Result<vk::ShaderStageFlags, RendererError> {
+    Ok(match execution_model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        _ => {
+            return Err(RendererError::Unsupported(format!(
+                "unsupported spir-v execution model {}",
+                execution_model
+            )))
+        }
+    })
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    AccelerationStructure,
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+struct EntryPoint {
+    execution_model: u32,
+    name: String,
+    interface: Vec<u32>,
+}
+
+struct Variable {
+    id: u32,
+    type_id: u32,
+    storage_class: u32,
+}
+
+/// The parts of a SPIR-V module needed for reflection.
+#[derive(Default)]
+struct Module {
+    entry_points: Vec<EntryPoint>,
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<Variable>,
+    /// (target, decoration) to its first literal, or 0 for decorations without one.
+    decorations: HashMap<(u32, u32), u32>,
+    /// (struct, member, decoration) to its first literal.
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+impl Module {
+    fn parse(code: &[u32]) -> Result<Self, RendererError> {
+        let invalid =
+            |reason: &str| RendererError::InvalidUsage(format!("invalid spir-v: {}", reason));
+
+        if code.len() < 5 || code[0] != SPIRV_MAGIC {
+            return Err(invalid("missing header"));
+        }
+
+        let mut module = Module::default();
+        let mut words = &code[5..];
+
+        while !words.is_empty() {
+            let word_count = (words[0] >> 16) as usize;
+            let opcode = words[0] & 0xffff;
+            if word_count == 0 || word_count > words.len() {
+                return Err(invalid("truncated instruction"));
+            }
+            let operands = &words[1..word_count];
+            words = &words[word_count..];
+
+            let operand = |index: usize| {
+                operands
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| invalid("missing operand"))
+            };
+
+            match opcode {
+                spirv::OP_NAME => {
+                    let (name, _) = parse_string(&operands[1.min(operands.len())..]);
+                    module.names.insert(operand(0)?, name);
+                }
+                spirv::OP_ENTRY_POINT => {
+                    let (name, name_words) = parse_string(&operands[2.min(operands.len())..]);
+                    module.entry_points.push(EntryPoint {
+                        execution_model: operand(0)?,
+                        name,
+                        interface: operands[(2 + name_words).min(operands.len())..].to_vec(),
+                    });
+                }
+                spirv::OP_TYPE_BOOL => {
+                    module.types.insert(operand(0)?, Type::Bool);
+                }
+                spirv::OP_TYPE_INT => {
+                    let ty = Type::Int {
+                        width: operand(1)?,
+                        signed: operand(2)? != 0,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_TYPE_FLOAT => {
+                    let ty = Type::Float { width: operand(1)? };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_TYPE_VECTOR => {
+                    let ty = Type::Vector {
+                        component: operand(1)?,
+                        count: operand(2)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_TYPE_MATRIX => {
+                    let ty = Type::Matrix {
+                        column: operand(1)?,
+                        count: operand(2)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_TYPE_IMAGE => {
+                    let ty = Type::Image {
+                        dim: operand(2)?,
+                        sampled: operand(6)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_TYPE_SAMPLER => {
+                    module.types.insert(operand(0)?, Type::Sampler);
+                }
+                spirv::OP_TYPE_SAMPLED_IMAGE => {
+                    module.types.insert(operand(0)?, Type::SampledImage);
+                }
+                spirv::OP_TYPE_ACCELERATION_STRUCTURE => {
+                    module
+                        .types
+                        .insert(operand(0)?, Type::AccelerationStructure);
+                }
+                spirv::OP_TYPE_ARRAY => {
+                    let length = module
+                        .constants
+                        .get(&operand(2)?)
+                        .copied()
+                        .ok_or_else(|| invalid("array length isn't a constant"))?;
+                    let ty = Type::Array {
+                        element: operand(1)?,
+                        length,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_TYPE_RUNTIME_ARRAY => {
+                    module.types.insert(operand(0)?, Type::RuntimeArray);
+                }
+                spirv::OP_TYPE_STRUCT => {
+                    let ty = Type::Struct {
+                        members: operands[1.min(operands.len())..].to_vec(),
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_TYPE_POINTER => {
+                    let ty = Type::Pointer {
+                        pointee: operand(2)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                spirv::OP_CONSTANT => {
+                    // only the low word matters for array lengths
+                    module.constants.insert(operand(1)?, operand(2)?);
+                }
+                spirv::OP_VARIABLE => {
+                    module.variables.push(Variable {
+                        type_id: operand(0)?,
+                        id: operand(1)?,
+                        storage_class: operand(2)?,
+                    });
+                }
+                spirv::OP_DECORATE => {
+                    let literal = operands.get(2).copied().unwrap_or(0);
+                    module
+                        .decorations
+                        .insert((operand(0)?, operand(1)?), literal);
+                }
+                spirv::OP_MEMBER_DECORATE => {
+                    let literal = operands.get(3).copied().unwrap_or(0);
+                    module
+                        .member_decorations
+                        .insert((operand(0)?, operand(1)?, operand(2)?), literal);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(module)
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names
+            .get(&id)
+            .filter(|name| !name.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("%{}", id))
+    }
+
+    #[inline]
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&(id, decoration)).copied()
+    }
+
+    #[inline]
+    fn member_decoration(&self, id: u32, member: usize, decoration: u32) -> Option<u32> {
+        self.member_decorations
+            .get(&(id, member as u32, decoration))
+            .copied()
+    }
+
+    /// Built-in variables, or blocks of built-ins like `gl_PerVertex`.
+    fn is_built_in(&self, variable: u32, pointee: u32) -> bool {
+        if self
+            .decoration(variable, spirv::DECORATION_BUILT_IN)
+            .is_some()
+        {
+            return true;
+        }
+
+        let mut ty = pointee;
+        while let Some(Type::Array { element, .. }) = self.types.get(&ty) {
+            ty = *element;
+        }
+
+        match self.types.get(&ty) {
+            Some(Type::Struct { members }) => (0..members.len()).any(|member| {
+                self.member_decoration(ty, member, spirv::DECORATION_BUILT_IN)
+                    .is_some()
+            }),
+            _ => false,
+        }
+    }
+
+    /// The vertex attribute format matching a scalar or vector interface type.
+    fn interface_format(&self, ty: u32, arrayed: bool) -> Option<vk::Format> {
+        let ty = match (arrayed, self.types.get(&ty)?) {
+            (true, Type::Array { element, .. }) => *element,
+            (true, _) => return None,
+            (false, _) => ty,
+        };
+
+        let (component, count) = match self.types.get(&ty)? {
+            Type::Vector { component, count } => (*component, *count),
+            _ => (ty, 1),
+        };
+
+        let formats = match self.types.get(&component)? {
+            Type::Float { width: 32 } => [
+                vk::Format::R32_SFLOAT,
+                vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT,
+                vk::Format::R32G32B32A32_SFLOAT,
+            ],
+            Type::Float { width: 64 } => [
+                vk::Format::R64_SFLOAT,
+                vk::Format::R64G64_SFLOAT,
+                vk::Format::R64G64B64_SFLOAT,
+                vk::Format::R64G64B64A64_SFLOAT,
+            ],
+            Type::Int {
+                width: 32,
+                signed: true,
+            } => [
+                vk::Format::R32_SINT,
+                vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT,
+                vk::Format::R32G32B32A32_SINT,
+            ],
+            Type::Int {
+                width: 32,
+                signed: false,
+            } => [
+                vk::Format::R32_UINT,
+                vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT,
+                vk::Format::R32G32B32A32_UINT,
+            ],
+            _ => return None,
+        };
+
+        formats.get(count.checked_sub(1)? as usize).copied()
+    }
+
+    /// Descriptor type and count of a resource, `None` for types that aren't descriptors.
+    fn descriptor_type(
+        &self,
+        storage_class: u32,
+        pointee: u32,
+    ) -> Result<Option<(vk::DescriptorType, u32)>, RendererError> {
+        let (ty, count) = match self.types.get(&pointee) {
+            Some(Type::Array { element, length }) => (*element, *length),
+            Some(Type::RuntimeArray) => {
+                return Err(RendererError::Unsupported(String::from(
+                    "runtime sized descriptor arrays are not supported",
+                )))
+            }
+            _ => (pointee, 1),
+        };
+
+        let descriptor_type = match (storage_class, self.types.get(&ty)) {
+            (spirv::STORAGE_CLASS_STORAGE_BUFFER, Some(Type::Struct { .. })) => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (spirv::STORAGE_CLASS_UNIFORM, Some(Type::Struct { .. })) => {
+                if self
+                    .decoration(ty, spirv::DECORATION_BUFFER_BLOCK)
+                    .is_some()
+                {
+                    vk::DescriptorType::STORAGE_BUFFER
+                } else if self.decoration(ty, spirv::DECORATION_BLOCK).is_some() {
+                    vk::DescriptorType::UNIFORM_BUFFER
+                } else {
+                    return Ok(None);
+                }
+            }
+            (spirv::STORAGE_CLASS_UNIFORM_CONSTANT, Some(Type::Sampler)) => {
+                vk::DescriptorType::SAMPLER
+            }
+            (spirv::STORAGE_CLASS_UNIFORM_CONSTANT, Some(Type::SampledImage)) => {
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            }
+            (spirv::STORAGE_CLASS_UNIFORM_CONSTANT, Some(Type::AccelerationStructure)) => {
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+            }
+            (spirv::STORAGE_CLASS_UNIFORM_CONSTANT, Some(Type::Image { dim, sampled })) => {
+                // sampled is 1 for images used with a sampler and 2 for storage images
+                match (*dim, *sampled) {
+                    (spirv::DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                    (spirv::DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    (spirv::DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                    _ => vk::DescriptorType::SAMPLED_IMAGE,
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some((descriptor_type, count)))
+    }
+
+    /// Offset and size of the members of a push constant block.
+    fn push_constant_block_range(&self, block: u32) -> Result<(u32, u32), RendererError> {
+        let Some(Type::Struct { members }) = self.types.get(&block) else {
+            return Err(RendererError::InvalidUsage(String::from(
+                "invalid spir-v: push constants are not a struct",
+            )));
+        };
+
+        let mut start = u32::MAX;
+        let mut end = 0;
+        for (index, &member) in members.iter().enumerate() {
+            let offset = self
+                .member_decoration(block, index, spirv::DECORATION_OFFSET)
+                .unwrap_or(0);
+            let matrix_stride =
+                self.member_decoration(block, index, spirv::DECORATION_MATRIX_STRIDE);
+            start = start.min(offset);
+            end = end.max(offset + self.type_size(member, matrix_stride)?);
+        }
+
+        if members.is_empty() {
+            start = 0;
+        }
+
+        Ok((start, end - start))
+    }
+
+    /// Size of a type in an explicitly laid out block.
+    fn type_size(&self, ty: u32, matrix_stride: Option<u32>) -> Result<u32, RendererError> {
+        let unsupported =
+            || RendererError::Unsupported(format!("can't compute the size of spir-v type %{}", ty));
+
+        Ok(match self.types.get(&ty).ok_or_else(unsupported)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => count * self.type_size(*component, None)?,
+            Type::Matrix { column, count } => {
+                count * matrix_stride.map_or_else(|| self.type_size(*column, None), Ok)?
+            }
+            Type::Array { element, length } => {
+                let stride = self.decoration(ty, spirv::DECORATION_ARRAY_STRIDE);
+                length * stride.map_or_else(|| self.type_size(*element, matrix_stride), Ok)?
+            }
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (index, &member) in members.iter().enumerate() {
+                    let offset = self
+                        .member_decoration(ty, index, spirv::DECORATION_OFFSET)
+                        .unwrap_or(0);
+                    let matrix_stride =
+                        self.member_decoration(ty, index, spirv::DECORATION_MATRIX_STRIDE);
+                    size = size.max(offset + self.type_size(member, matrix_stride)?);
+                }
+                size
+            }
+            _ => return Err(unsupported()),
+        })
+    }
+}
+
+/// Decodes a nul terminated literal string, returning it and the number of words it used.
+fn parse_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = Vec::new();
+
+    for (index, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), index + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, path::Path};
+
+    use super::*;
+    use crate::renderer::resources::shader::{embedded_spirv, parse_spirv};
+
+    fn embedded_code(name: &str) -> Vec<u32> {
+        let bytes = embedded_spirv(OsStr::new(name)).unwrap();
+        parse_spirv(bytes, Path::new(name)).unwrap()
+    }
+
+    fn embedded(name: &str) -> ShaderReflection {
+        ShaderReflection::from_spirv(&embedded_code(name), None).unwrap()
+    }
+
+    fn locations(variables: &[InterfaceVariable]) -> Vec<(u32, vk::Format)> {
+        variables
+            .iter()
+            .map(|variable| (variable.location, variable.format))
+            .collect()
+    }
+
+    fn bindings(bindings: &[DescriptorBinding]) -> Vec<(u32, vk::DescriptorType, u32)> {
+        bindings
+            .iter()
+            .map(|binding| (binding.binding, binding.descriptor_type, binding.count))
+            .collect()
+    }
+
+    #[test]
+    fn embedded_vertex_shader() {
+        let vertex = embedded("vert.spv");
+
+        assert_eq!(vertex.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(vertex.entry_point, "main");
+        assert_eq!(
+            locations(&vertex.inputs),
+            [
+                (0, vk::Format::R32G32B32_SFLOAT),
+                (1, vk::Format::R32G32B32_SFLOAT),
+                (2, vk::Format::R32G32_SFLOAT),
+            ]
+        );
+        assert_eq!(
+            locations(&vertex.outputs),
+            [
+                (0, vk::Format::R32G32B32_SFLOAT),
+                (1, vk::Format::R32G32_SFLOAT),
+            ]
+        );
+
+        let range = vertex.push_constant_range.unwrap();
+        assert_eq!(
+            (range.stage_flags, range.offset, range.size),
+            (vk::ShaderStageFlags::VERTEX, 0, 64)
+        );
+    }
+
+    #[test]
+    fn embedded_fragment_shader() {
+        let fragment = embedded("frag.spv");
+
+        assert_eq!(fragment.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(
+            locations(&fragment.inputs),
+            [
+                (0, vk::Format::R32G32B32_SFLOAT),
+                (1, vk::Format::R32G32_SFLOAT),
+            ]
+        );
+        assert_eq!(
+            locations(&fragment.outputs),
+            [(0, vk::Format::R32G32B32A32_SFLOAT)]
+        );
+        assert!(fragment.push_constant_range.is_none());
+    }
+
+    #[test]
+    fn embedded_pipeline() {
+        let pipeline =
+            PipelineReflection::new(&[embedded("frag.spv"), embedded("vert.spv")]).unwrap();
+
+        assert_eq!(pipeline.sets.keys().copied().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(
+            bindings(&pipeline.sets[&0]),
+            [(0, vk::DescriptorType::UNIFORM_BUFFER, 1)]
+        );
+        assert_eq!(
+            pipeline.sets[&0][0].stages,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+        assert_eq!(
+            bindings(&pipeline.sets[&1]),
+            [
+                (0, vk::DescriptorType::SAMPLED_IMAGE, 1),
+                (1, vk::DescriptorType::SAMPLER, 1),
+            ]
+        );
+
+        let ranges = pipeline
+            .push_constant_ranges
+            .iter()
+            .map(|range| (range.stage_flags, range.offset, range.size))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(vk::ShaderStageFlags::VERTEX, 0, 64)]);
+        assert_eq!(
+            locations(&pipeline.vertex_inputs),
+            locations(&embedded("vert.spv").inputs)
+        );
+    }
+
+    #[test]
+    fn mismatched_stage_interface() {
+        let vertex = embedded("vert.spv");
+
+        let mut wrong_format = embedded("frag.spv");
+        wrong_format.inputs[1].format = vk::Format::R32G32B32_SFLOAT;
+        assert!(PipelineReflection::new(&[vertex.clone(), wrong_format]).is_err());
+
+        let mut unwritten = embedded("frag.spv");
+        unwritten.inputs[1].location = 5;
+        assert!(PipelineReflection::new(&[vertex.clone(), unwritten]).is_err());
+
+        assert!(PipelineReflection::new(&[vertex.clone(), vertex]).is_err());
+    }
+
+    #[test]
+    fn truncated_modules() {
+        let code = embedded_code("vert.spv");
+
+        assert!(ShaderReflection::from_spirv(&code[..4], None).is_err());
+
+        // the first instruction after the header is longer than one word
+        assert!(code[5] >> 16 > 1);
+        assert!(ShaderReflection::from_spirv(&code[..6], None).is_err());
+
+        assert!(ShaderReflection::from_spirv(&code, Some("missing")).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    ffi::OsStr,
+    io::Cursor,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use ash::vk;
+
+use crate::renderer::error::RendererError;
+
+pub const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// The precompiled default shaders, used when they are missing from the shader directory.
+const EMBEDDED_SPIRV: [(&str, &[u8]); 2] = [
+    ("vert.spv", include_bytes!("../../shaders/vert.spv")),
+    ("frag.spv", include_bytes!("../../shaders/frag.spv")),
+];
+
+/// How often [`ShaderWatcher::poll`] looks at the files.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads a SPIR-V binary from `path`, checking the size and magic number so garbage is
+/// rejected before it reaches reflection or the driver.
+pub fn read_spirv(path: &Path) -> Result<Vec<u32>, RendererError> {
+    let bytes = std::fs::read(path).map_err(RendererError::io(format!(
+        "failed to read shader {}",
+        path.display()
+    )))?;
+
+    parse_spirv(&bytes, path)
+}
+
+/// The built in SPIR-V of the default shader named `file_name`, `vert.spv` or `frag.spv`.
+pub fn embedded_spirv(file_name: &OsStr) -> Option<&'static [u8]> {
+    EMBEDDED_SPIRV
+        .iter()
+        .find(|(name, _)| file_name == *name)
+        .map(|&(_, bytes)| bytes)
+}
+
+/// Checks and converts a SPIR-V binary, `path` names it in errors.
+pub fn parse_spirv(bytes: &[u8], path: &Path) -> Result<Vec<u32>, RendererError> {
+    let code = ash::util::read_spv(&mut Cursor::new(bytes)).map_err(|err| {
+        RendererError::InvalidUsage(format!("{}: invalid spir-v: {}", path.display(), err))
+    })?;
+
+    // read_spv already swapped the words if the file was written with the other endianness
+    if code.first() != Some(&SPIRV_MAGIC) {
+        return Err(RendererError::InvalidUsage(format!(
+            "{}: invalid spir-v: wrong magic number",
+            path.display()
+        )));
+    }
+
+    Ok(code)
+}
+
+pub fn create_shader_module(
+    device: &ash::Device,
+    code: &[u32],
+) -> Result<vk::ShaderModule, RendererError> {
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(code);
+
+    let shader_module = unsafe {
+        device
+            .create_shader_module(&create_info, None)
+            .map_err(RendererError::pipeline("failed to create shader module"))?
+    };
+
+    Ok(shader_module)
+}
+
+/// Notices changes to a set of files by polling their modification times.
+pub struct ShaderWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        Self {
+            files: paths
+                .iter()
+                .map(|path| (path.clone(), modified_time(path)))
+                .collect(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Replaces the watched files, files that were watched before keep their last seen time.
+    pub fn watch(&mut self, paths: &[PathBuf]) {
+        let files = paths
+            .iter()
+            .map(|path| {
+                let last_modified = self
+                    .files
+                    .iter()
+                    .find(|(file, _)| file == path)
+                    .map_or_else(|| modified_time(path), |&(_, modified)| modified);
+                (path.clone(), last_modified)
+            })
+            .collect();
+
+        self.files = files;
+    }
+
+    /// Returns true if any file was modified, created or removed since the last change was
+    /// reported. Checks at most every `POLL_INTERVAL`.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = modified_time(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+#[inline]
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_shaders_are_valid_spirv() {
+        for (name, _) in EMBEDDED_SPIRV {
+            let bytes = embedded_spirv(OsStr::new(name)).unwrap();
+            assert!(parse_spirv(bytes, Path::new(name)).is_ok(), "{}", name);
+        }
+
+        assert!(embedded_spirv(OsStr::new("vertex.vert")).is_none());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse_spirv(&[0; 8], Path::new("garbage.spv")).is_err());
+        assert!(parse_spirv(&[1, 2, 3], Path::new("truncated.spv")).is_err());
+    }
+}
@@ -0,0 +1,703 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use ash::vk;
+use naga::{
+    back::spv,
+    front::{glsl, wgsl},
+    valid::{Capabilities, ValidationFlags, Validator},
+    ShaderStage, Span,
+};
+
+use crate::renderer::error::RendererError;
+
+use super::shader;
+
+/// Source languages [`ShaderCompiler`] understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderLanguage {
+    Glsl,
+    Wgsl,
+}
+
+impl ShaderLanguage {
+    /// Picks the language from the file extension, `None` for SPIR-V and unknown extensions.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "wgsl" => Some(Self::Wgsl),
+            "glsl" | "vert" | "frag" | "comp" => Some(Self::Glsl),
+            _ => None,
+        }
+    }
+}
+
+pub struct CompiledShader {
+    pub code: Vec<u32>,
+    /// The shader and every file it included, the shader first.
+    pub files: Vec<PathBuf>,
+}
+
+/// Compiles GLSL and WGSL to SPIR-V in process, so changing a shader doesn't need the
+/// Vulkan SDK.
+///
+/// `#include "file"` lines are replaced by the file, looked up next to the including file and
+/// then in `include_dirs`. Includes are expanded before conditionals are evaluated, so shared
+/// files should be guarded with `#pragma once`. GLSL has its own preprocessor for everything
+/// else, WGSL gets `#define`, `#undef`, `#ifdef`, `#ifndef`, `#else` and `#endif`.
+///
+/// Errors point to the line in the file they come from.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderCompiler {
+    pub include_dirs: Vec<PathBuf>,
+    /// Defined before the first line of every shader, as name and value.
+    pub defines: Vec<(String, String)>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    #[inline]
+    pub fn define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defines.push((name.into(), value.into()));
+        self
+    }
+
+    /// Compiles the `stage` entry point of the shader at `path`, or reads it if it's already
+    /// SPIR-V.
+    pub fn load_file(
+        &self,
+        path: &Path,
+        stage: vk::ShaderStageFlags,
+    ) -> Result<CompiledShader, RendererError> {
+        let Some(language) = ShaderLanguage::from_path(path) else {
+            return Ok(CompiledShader {
+                code: shader::read_spirv(path)?,
+                files: vec![path.to_path_buf()],
+            });
+        };
+
+        let source = read_source(path)?;
+        self.compile(&source, path, language, stage)
+    }
+
+    /// Compiles the `stage` entry point of `source`. `path` names the source in errors and
+    /// relative includes are looked up next to it.
+    pub fn compile(
+        &self,
+        source: &str,
+        path: &Path,
+        language: ShaderLanguage,
+        stage: vk::ShaderStageFlags,
+    ) -> Result<CompiledShader, RendererError> {
+        let naga_stage = match stage {
+            vk::ShaderStageFlags::VERTEX => ShaderStage::Vertex,
+            vk::ShaderStageFlags::FRAGMENT => ShaderStage::Fragment,
+            vk::ShaderStageFlags::COMPUTE => ShaderStage::Compute,
+            _ => {
+                return Err(RendererError::Unsupported(format!(
+                    "{}: can't compile {:?} shaders",
+                    path.display(),
+                    stage
+                )))
+            }
+        };
+
+        let preprocessed = Preprocessor::new(self, language).run(source, path)?;
+
+        let module = match language {
+            ShaderLanguage::Glsl => {
+                let options = glsl::Options {
+                    stage: naga_stage,
+                    defines: self.defines.iter().cloned().collect(),
+                };
+
+                glsl::Frontend::default()
+                    .parse(&options, &preprocessed.source)
+                    .map_err(|errors| {
+                        let messages = errors
+                            .iter()
+                            .map(|err| preprocessed.message(Some(err.meta), &err.kind.to_string()))
+                            .collect::<Vec<_>>();
+                        RendererError::Shader(messages.join("\n"))
+                    })?
+            }
+            ShaderLanguage::Wgsl => {
+                wgsl::Frontend::new()
+                    .parse(&preprocessed.source)
+                    .map_err(|err| {
+                        let span = err.labels().next().map(|(span, _)| span);
+                        RendererError::Shader(preprocessed.message(span, err.message()))
+                    })?
+            }
+        };
+
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|err| {
+                let span = err.spans().next().map(|&(span, _)| span);
+                RendererError::Shader(preprocessed.message(span, &error_chain(err.as_inner())))
+            })?;
+
+        let entry_point = module
+            .entry_points
+            .iter()
+            .find(|entry| entry.stage == naga_stage)
+            .ok_or_else(|| {
+                RendererError::Shader(format!(
+                    "{}: no {:?} entry point",
+                    path.display(),
+                    naga_stage
+                ))
+            })?;
+
+        let mut options = spv::Options::default();
+        // glsl is written for vulkan already, wgsl clip space has y pointing up
+        if language == ShaderLanguage::Glsl {
+            options
+                .flags
+                .remove(spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+        }
+        let pipeline_options = spv::PipelineOptions {
+            shader_stage: naga_stage,
+            entry_point: entry_point.name.clone(),
+        };
+
+        let code = spv::write_vec(&module, &info, &options, Some(&pipeline_options))
+            .map_err(|err| RendererError::Shader(format!("{}: {}", path.display(), err)))?;
+
+        Ok(CompiledShader {
+            code,
+            files: preprocessed.files,
+        })
+    }
+}
+
+/// Source with the includes expanded, remembering where every line came from.
+struct Preprocessed {
+    source: String,
+    /// Index into `files` and line number in that file, for every line of `source`.
+    lines: Vec<(usize, u32)>,
+    files: Vec<PathBuf>,
+}
+
+impl Preprocessed {
+    /// Prefixes `message` with the file, line and column `span` points to.
+    fn message(&self, span: Option<Span>, message: &str) -> String {
+        let location = span
+            .filter(|span| span.is_defined())
+            .map(|span| span.location(&self.source))
+            .and_then(|location| {
+                let line = self.lines.get(location.line_number as usize - 1)?;
+                Some((line, location.line_position))
+            });
+
+        match location {
+            Some((&(file, line), column)) => format!(
+                "{}:{}:{}: {}",
+                self.files[file].display(),
+                line,
+                column,
+                message
+            ),
+            None => format!("{}: {}", self.files[0].display(), message),
+        }
+    }
+}
+
+/// An `#ifdef` or `#ifndef` block of a WGSL shader.
+struct Condition {
+    active: bool,
+    parent_active: bool,
+    seen_else: bool,
+}
+
+struct Preprocessor<'a> {
+    compiler: &'a ShaderCompiler,
+    language: ShaderLanguage,
+    output: Preprocessed,
+    /// Files currently being expanded, to catch include cycles.
+    include_stack: Vec<PathBuf>,
+    /// Files with `#pragma once` that were expanded already.
+    included_once: Vec<PathBuf>,
+    /// Only used for WGSL, GLSL keeps its defines for its own preprocessor.
+    defines: HashMap<String, String>,
+    conditions: Vec<Condition>,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn new(compiler: &'a ShaderCompiler, language: ShaderLanguage) -> Self {
+        let defines = match language {
+            ShaderLanguage::Glsl => HashMap::new(),
+            ShaderLanguage::Wgsl => compiler.defines.iter().cloned().collect(),
+        };
+
+        Self {
+            compiler,
+            language,
+            output: Preprocessed {
+                source: String::new(),
+                lines: Vec::new(),
+                files: Vec::new(),
+            },
+            include_stack: Vec::new(),
+            included_once: Vec::new(),
+            defines,
+            conditions: Vec::new(),
+        }
+    }
+
+    fn run(mut self, source: &str, path: &Path) -> Result<Preprocessed, RendererError> {
+        self.expand(source, path)?;
+        Ok(self.output)
+    }
+
+    #[inline]
+    fn active(&self) -> bool {
+        self.conditions
+            .last()
+            .is_none_or(|condition| condition.active)
+    }
+
+    fn expand(&mut self, source: &str, path: &Path) -> Result<(), RendererError> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.included_once.contains(&canonical) {
+            return Ok(());
+        }
+        if self.include_stack.contains(&canonical) {
+            return Err(RendererError::Shader(format!(
+                "{}: included recursively",
+                path.display()
+            )));
+        }
+        self.include_stack.push(canonical.clone());
+
+        let file = match self.output.files.iter().position(|file| file == path) {
+            Some(file) => file,
+            None => {
+                self.output.files.push(path.to_path_buf());
+                self.output.files.len() - 1
+            }
+        };
+        let condition_depth = self.conditions.len();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let error = |message: String| {
+                RendererError::Shader(format!("{}:{}: {}", path.display(), line_number, message))
+            };
+
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if self.active() {
+                    let line = match self.language {
+                        ShaderLanguage::Glsl => line.to_string(),
+                        ShaderLanguage::Wgsl => self.substitute(line),
+                    };
+                    self.push_line(&line, file, line_number);
+                }
+                continue;
+            };
+
+            let directive = directive.trim();
+            let (name, argument) = directive
+                .split_once(char::is_whitespace)
+                .map_or((directive, ""), |(name, argument)| (name, argument.trim()));
+
+            match (self.language, name) {
+                (_, "include") => {
+                    if !self.active() {
+                        continue;
+                    }
+
+                    let include = parse_include(argument).ok_or_else(|| {
+                        error(format!("expected #include \"file\", found {}", line))
+                    })?;
+                    let include_path = self
+                        .resolve_include(path, include)
+                        .ok_or_else(|| error(format!("can't find include {}", include)))?;
+                    let include_source = read_source(&include_path)?;
+
+                    self.expand(&include_source, &include_path)?;
+                }
+                (_, "pragma") if argument == "once" => {
+                    if self.active() {
+                        self.included_once.push(canonical.clone());
+                    }
+                }
+                (ShaderLanguage::Glsl, _) => {
+                    self.push_line(line, file, line_number);
+                }
+                (ShaderLanguage::Wgsl, "define") => {
+                    if self.active() {
+                        let (define, value) = argument
+                            .split_once(char::is_whitespace)
+                            .map_or((argument, ""), |(define, value)| (define, value.trim()));
+                        if define.is_empty() {
+                            return Err(error(String::from("#define without a name")));
+                        }
+                        self.defines.insert(define.to_string(), value.to_string());
+                    }
+                }
+                (ShaderLanguage::Wgsl, "undef") => {
+                    if self.active() {
+                        self.defines.remove(argument);
+                    }
+                }
+                (ShaderLanguage::Wgsl, "ifdef" | "ifndef") => {
+                    let defined = self.defines.contains_key(argument);
+                    let parent_active = self.active();
+                    self.conditions.push(Condition {
+                        active: parent_active && defined == (name == "ifdef"),
+                        parent_active,
+                        seen_else: false,
+                    });
+                }
+                (ShaderLanguage::Wgsl, "else") => {
+                    if self.conditions.len() <= condition_depth {
+                        return Err(error(String::from("#else without #ifdef")));
+                    }
+                    let condition = self.conditions.last_mut().unwrap();
+                    if condition.seen_else {
+                        return Err(error(String::from("second #else in one #ifdef")));
+                    }
+                    condition.seen_else = true;
+                    condition.active = condition.parent_active && !condition.active;
+                }
+                (ShaderLanguage::Wgsl, "endif") => {
+                    if self.conditions.len() <= condition_depth {
+                        return Err(error(String::from("#endif without #ifdef")));
+                    }
+                    self.conditions.pop();
+                }
+                (ShaderLanguage::Wgsl, _) => {
+                    return Err(error(format!("unknown directive #{}", name)));
+                }
+            }
+        }
+
+        if self.conditions.len() > condition_depth {
+            return Err(RendererError::Shader(format!(
+                "{}: #ifdef without #endif",
+                path.display()
+            )));
+        }
+
+        self.include_stack.pop();
+
+        Ok(())
+    }
+
+    #[inline]
+    fn push_line(&mut self, line: &str, file: usize, line_number: u32) {
+        self.output.source.push_str(line);
+        self.output.source.push('\n');
+        self.output.lines.push((file, line_number));
+    }
+
+    /// Looks for `include` next to `including_file`, then in the include dirs.
+    fn resolve_include(&self, including_file: &Path, include: &str) -> Option<PathBuf> {
+        let relative = including_file
+            .parent()
+            .map(|dir| dir.join(include))
+            .into_iter();
+        let searched = self
+            .compiler
+            .include_dirs
+            .iter()
+            .map(|dir| dir.join(include));
+
+        relative.chain(searched).find(|path| path.is_file())
+    }
+
+    /// Replaces every identifier that is a define with its value.
+    fn substitute(&self, line: &str) -> String {
+        if self.defines.is_empty() {
+            return line.to_string();
+        }
+
+        let mut result = String::with_capacity(line.len());
+        let mut identifier_start = None;
+
+        for (index, c) in line.char_indices().chain([(line.len(), ' ')]) {
+            let is_identifier = c == '_' || c.is_ascii_alphanumeric();
+            match (identifier_start, is_identifier) {
+                (None, true) => identifier_start = Some(index),
+                (Some(start), false) => {
+                    let identifier = &line[start..index];
+                    result.push_str(self.defines.get(identifier).map_or(identifier, |v| v));
+                    identifier_start = None;
+                }
+                _ => (),
+            }
+
+            if identifier_start.is_none() && !is_identifier && index < line.len() {
+                result.push(c);
+            }
+        }
+
+        result
+    }
+}
+
+/// The file name of `"file"` or `<file>`.
+fn parse_include(argument: &str) -> Option<&str> {
+    argument
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .or_else(|| {
+            argument
+                .strip_prefix('<')
+                .and_then(|rest| rest.strip_suffix('>'))
+        })
+        .filter(|name| !name.is_empty())
+}
+
+#[inline]
+fn read_source(path: &Path) -> Result<String, RendererError> {
+    std::fs::read_to_string(path).map_err(RendererError::io(format!(
+        "failed to read shader {}",
+        path.display()
+    )))
+}
+
+/// Joins an error with all its sources, naga keeps the useful details in the sources.
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory holding `files`, as name and source.
+    fn source_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("shader_compiler_{}_{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (name, source) in files {
+            std::fs::write(dir.join(name), source).unwrap();
+        }
+
+        dir
+    }
+
+    fn preprocess_wgsl(
+        compiler: &ShaderCompiler,
+        source: &str,
+        path: &Path,
+    ) -> Result<Preprocessed, RendererError> {
+        Preprocessor::new(compiler, ShaderLanguage::Wgsl).run(source, path)
+    }
+
+    fn lines(preprocessed: &Preprocessed) -> Vec<&str> {
+        preprocessed.source.lines().collect()
+    }
+
+    fn shader_error(result: Result<Preprocessed, RendererError>) -> String {
+        match result {
+            Err(RendererError::Shader(message)) => message,
+            Err(err) => panic!("expected a shader error, got {}", err),
+            Ok(preprocessed) => panic!("expected an error, got {:?}", preprocessed.source),
+        }
+    }
+
+    #[test]
+    fn includes_resolve_next_to_the_file_then_in_include_dirs() {
+        let dir = source_dir("includes", &[("common.wgsl", "#pragma once\ncommon\n")]);
+        let include_dir = source_dir("include_dir", &[("shared.wgsl", "shared\n")]);
+        let compiler = ShaderCompiler::new().include_dir(&include_dir);
+        let path = dir.join("main.wgsl");
+
+        let preprocessed = preprocess_wgsl(
+            &compiler,
+            "#include \"common.wgsl\"\n#include <shared.wgsl>\n#include \"common.wgsl\"\nmain\n",
+            &path,
+        )
+        .unwrap();
+
+        assert_eq!(lines(&preprocessed), ["common", "shared", "main"]);
+        assert_eq!(
+            preprocessed.files,
+            [
+                path,
+                dir.join("common.wgsl"),
+                include_dir.join("shared.wgsl")
+            ]
+        );
+        assert_eq!(preprocessed.lines, [(1, 2), (2, 1), (0, 4)]);
+    }
+
+    #[test]
+    fn include_errors() {
+        let dir = source_dir(
+            "include_errors",
+            &[
+                ("a.wgsl", "#include \"b.wgsl\"\n"),
+                ("b.wgsl", "#include \"a.wgsl\"\n"),
+            ],
+        );
+        let compiler = ShaderCompiler::new();
+
+        let recursive = shader_error(preprocess_wgsl(
+            &compiler,
+            "#include \"a.wgsl\"\n",
+            &dir.join("main.wgsl"),
+        ));
+        assert!(recursive.contains("included recursively"), "{}", recursive);
+
+        let missing = shader_error(preprocess_wgsl(
+            &compiler,
+            "\n#include \"missing.wgsl\"\n",
+            &dir.join("main.wgsl"),
+        ));
+        assert!(missing.contains("main.wgsl:2:"), "{}", missing);
+
+        let malformed = shader_error(preprocess_wgsl(
+            &compiler,
+            "#include missing.wgsl\n",
+            &dir.join("main.wgsl"),
+        ));
+        assert!(malformed.contains("main.wgsl:1:"), "{}", malformed);
+    }
+
+    #[test]
+    fn defines_are_substituted_in_wgsl() {
+        let compiler = ShaderCompiler::new().define("SCALE", "2.0");
+
+        let preprocessed = preprocess_wgsl(
+            &compiler,
+            "#define OFFSET 1.0\nlet x = SCALE * SCALED + OFFSET;\n#undef OFFSET\nOFFSET\n",
+            Path::new("main.wgsl"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            lines(&preprocessed),
+            ["let x = 2.0 * SCALED + 1.0;", "OFFSET"]
+        );
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let compiler = ShaderCompiler::new().define("A", "");
+        let source = "\
+#ifdef A
+a
+#ifdef B
+b
+#else
+not_b
+#ifndef C
+not_c
+#endif
+#endif
+#else
+not_a
+#ifdef A
+never
+#endif
+#endif
+end
+";
+
+        let preprocessed = preprocess_wgsl(&compiler, source, Path::new("main.wgsl")).unwrap();
+
+        assert_eq!(lines(&preprocessed), ["a", "not_b", "not_c", "end"]);
+    }
+
+    #[test]
+    fn mismatched_conditionals() {
+        let compiler = ShaderCompiler::new();
+        let invalid = [
+            ("#endif\n", "main.wgsl:1:"),
+            ("x\n#else\n", "main.wgsl:2:"),
+            ("#ifdef A\n#else\n#else\n#endif\n", "main.wgsl:3:"),
+            ("#ifdef A\n", "main.wgsl: #ifdef without #endif"),
+            ("#if A\n#endif\n", "main.wgsl:1:"),
+        ];
+
+        for (source, location) in invalid {
+            let message = shader_error(preprocess_wgsl(&compiler, source, Path::new("main.wgsl")));
+            assert!(message.starts_with(location), "{:?}: {}", source, message);
+        }
+
+        // an included file can't close a condition of the file including it
+        let dir = source_dir("included_endif", &[("endif.wgsl", "\n#endif\n")]);
+        let message = shader_error(preprocess_wgsl(
+            &compiler,
+            "#ifndef A\n#include \"endif.wgsl\"\n#endif\n",
+            &dir.join("main.wgsl"),
+        ));
+        assert!(message.contains("endif.wgsl:2:"), "{}", message);
+    }
+
+    #[test]
+    fn errors_point_into_included_files() {
+        let dir = source_dir(
+            "error_location",
+            &[(
+                "helper.wgsl",
+                "// a helper\n\nfn helper() -> f32 {\n    return 1.0 +;\n}\n",
+            )],
+        );
+        let source = "\
+#include \"helper.wgsl\"
+
+@vertex
+fn main() -> @builtin(position) vec4<f32> {
+    return vec4<f32>(helper());
+}
+";
+
+        let message = match ShaderCompiler::new().compile(
+            source,
+            &dir.join("main.wgsl"),
+            ShaderLanguage::Wgsl,
+            vk::ShaderStageFlags::VERTEX,
+        ) {
+            Err(RendererError::Shader(message)) => message,
+            Err(err) => panic!("expected a shader error, got {}", err),
+            Ok(_) => panic!("expected an error"),
+        };
+
+        let location = format!("{}:4:", dir.join("helper.wgsl").display());
+        assert!(message.starts_with(&location), "{}", message);
+    }
+
+    #[test]
+    fn compiles_the_shipped_shaders() {
+        let shader_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders");
+        let compiler = ShaderCompiler::new().include_dir(&shader_dir);
+
+        for (name, stage) in [
+            ("vertex.vert", vk::ShaderStageFlags::VERTEX),
+            ("fragment.frag", vk::ShaderStageFlags::FRAGMENT),
+        ] {
+            let path = shader_dir.join(name);
+            let compiled = compiler.load_file(&path, stage).unwrap();
+
+            assert_eq!(compiled.code[0], shader::SPIRV_MAGIC, "{}", name);
+            assert_eq!(compiled.files, [path]);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use ash::vk;
+
+use super::pod::Pod;
+
+pub const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Per frame data available to the shaders at set 0, binding 0.
+///
+/// Laid out to match the std140 uniform block in the shaders.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FrameUniforms {
+    pub view_projection: [[f32; 4]; 4],
+    /// Seconds since the renderer was created.
+    pub time: f32,
+    /// Seconds since the previous frame.
+    pub delta_time: f32,
+    /// Size of the render target in pixels.
+    pub resolution: [f32; 2],
+}
+
+// every field is made of f32 and the block is a multiple of 16 bytes, so there is no padding
+unsafe impl Pod for FrameUniforms {}
+
+impl Default for FrameUniforms {
+    fn default() -> Self {
+        Self {
+            view_projection: IDENTITY,
+            time: 0.0,
+            delta_time: 0.0,
+            resolution: [0.0, 0.0],
+        }
+    }
+}
+
+/// Per draw data pushed before every mesh is drawn, visible to the vertex stage.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct MeshPushConstants {
+    pub model: [[f32; 4]; 4],
+}
+
+unsafe impl Pod for MeshPushConstants {}
+
+impl MeshPushConstants {
+    pub fn range() -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: std::mem::size_of::<Self>() as u32,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use ash::vk;
+
+use crate::{
+    engine::lin_alg::{Vector2, Vector3},
+    offset_of,
+};
+
+use super::pod::Pod;
+
+/// Describes how a vertex type is laid out in a vertex buffer, used to build the vertex
+/// input state of pipelines.
+pub trait VertexFormat: Pod {
+    fn binding_description() -> vk::VertexInputBindingDescription;
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription>;
+}
+
+/// The vertex format of the default pipeline.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub uv: Vector2<f32>,
+}
+
+impl Vertex {
+    #[inline]
+    pub fn new(position: Vector3<f32>, color: Vector3<f32>, uv: Vector2<f32>) -> Self {
+        Self {
+            position,
+            color,
+            uv,
+        }
+    }
+}
+
+// three tightly packed vectors of f32
+unsafe impl Pod for Vertex {}
+
+impl VertexFormat for Vertex {
+    fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Self, position) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Self, color) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Self, uv) as u32,
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_layout() {
+        let binding = Vertex::binding_description();
+        let offsets = Vertex::attribute_descriptions()
+            .iter()
+            .map(|attribute| (attribute.location, attribute.offset))
+            .collect::<Vec<_>>();
+
+        assert_eq!(binding.stride, 32);
+        assert_eq!(offsets, [(0, 0), (1, 12), (2, 24)]);
+    }
+}
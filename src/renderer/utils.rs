@@ -3,8 +3,11 @@ use std::ffi::CStr;
 
 use ash::vk::{self, DebugUtilsMessageSeverityFlagsEXT};
 
-pub const MAX_FRAME_DRAWS: usize = 2;
-
+/// Logs validation layer messages.
+///
+/// # Safety
+///
+/// Only to be called by the Vulkan loader, `p_callback_data` has to point to valid callback data.
 pub unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -53,23 +56,3 @@ macro_rules! msg {
         );
     }};
 }
-
-#[macro_export]
-macro_rules! create_shader {
-    ($path:expr, $device:expr) => {{
-        unsafe {
-            use ash::{util::read_spv, vk};
-            use std::io::Cursor;
-
-            let mut spv_file = Cursor::new(&include_bytes!($path)[..]);
-
-            let code = read_spv(&mut spv_file).expect("Failed to read shader spv file");
-            let shader_info = vk::ShaderModuleCreateInfo::builder().code(&code);
-            let shader_module = $device
-                .create_shader_module(&shader_info, None)
-                .expect("shader module error");
-
-            shader_module
-        }
-    }};
-}
@@ -1,5 +1,23 @@
 #version 450
 
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view_projection;
+    float time;
+    float delta_time;
+    vec2 resolution;
+} frame;
+
+layout(set = 1, binding = 0) uniform texture2D tex;
+layout(set = 1, binding = 1) uniform sampler tex_sampler;
+
+layout(location = 0) in vec3 frag_color;
+layout(location = 1) in vec2 frag_uv;
+
+layout(location = 0) out vec4 out_color;
+
 void main()
 {
-}
\ No newline at end of file
+    float pulse = 0.75 + 0.25 * sin(frame.time * 2.0);
+    vec4 texel = texture(sampler2D(tex, tex_sampler), frag_uv);
+    out_color = vec4(frag_color * texel.rgb * pulse, texel.a);
+}
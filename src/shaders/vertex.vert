@@ -1,6 +1,26 @@
 #version 450
 
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view_projection;
+    float time;
+    float delta_time;
+    vec2 resolution;
+} frame;
+
+layout(push_constant) uniform MeshPushConstants {
+    mat4 model;
+} mesh;
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_color;
+layout(location = 2) in vec2 in_uv;
+
+layout(location = 0) out vec3 frag_color;
+layout(location = 1) out vec2 frag_uv;
+
 void main()
 {
-
-}
\ No newline at end of file
+    gl_Position = frame.view_projection * mesh.model * vec4(in_position, 1.0);
+    frag_color = in_color;
+    frag_uv = in_uv;
+}